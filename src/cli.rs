@@ -0,0 +1,105 @@
+use ray_tracer_in_one_weekend::renderer::ToneMapping;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "usage: ray-tracer-in-one-weekend [options] [scene.ron | mesh.obj]
+
+options:
+    -s, --scene <path>      scene file (.ron) or mesh (.obj) to render
+    -W, --width <pixels>    override the image width
+    -H, --height <pixels>   override the image height
+    -n, --samples <count>   samples per pixel
+    -j, --threads <count>   number of render threads (default: 4)
+    -o, --output <path>     render without a window and save the image to <path>
+                            (.png, .exr, .hdr or .pfm)
+        --half              write half float instead of float EXR files
+        --aovs              add normal and depth layers to EXR files
+    -e, --exposure <ev>     exposure adjustment in stops
+    -t, --tonemap <curve>   tone mapping curve: clamp, reinhard, aces, filmic or agx
+        --dither            dither the 8 bit window and PNG output
+        --spectral          trace sampled wavelengths instead of RGB (for dispersion)
+    -h, --help              print this message";
+
+pub struct Options {
+    pub scene: Option<String>,
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    pub samples: Option<u32>,
+    pub threads: usize,
+    pub output: Option<PathBuf>,
+    pub half: bool,
+    pub aovs: bool,
+    pub exposure: Option<f32>,
+    pub tone_mapping: Option<ToneMapping>,
+    pub dither: bool,
+    pub spectral: bool,
+    pub help: bool,
+}
+
+fn value<T: std::str::FromStr>(
+    flag: &str,
+    args: &mut dyn Iterator<Item = String>,
+) -> Result<T, String> {
+    let value = args
+        .next()
+        .ok_or_else(|| format!("missing value for {}", flag))?;
+
+    value
+        .parse::<T>()
+        .map_err(|_| format!("invalid value '{}' for {}", value, flag))
+}
+
+fn positive<T: std::str::FromStr + PartialOrd + Default>(
+    flag: &str,
+    args: &mut dyn Iterator<Item = String>,
+) -> Result<T, String> {
+    let value = value::<T>(flag, args)?;
+    if value > T::default() {
+        Ok(value)
+    } else {
+        Err(format!("{} must be greater than zero", flag))
+    }
+}
+
+pub fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        scene: None,
+        width: None,
+        height: None,
+        samples: None,
+        threads: 4,
+        output: None,
+        half: false,
+        aovs: false,
+        exposure: None,
+        tone_mapping: None,
+        dither: false,
+        spectral: false,
+        help: false,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-s" | "--scene" => options.scene = Some(value(&arg, &mut args)?),
+            "-W" | "--width" => options.width = Some(positive(&arg, &mut args)?),
+            "-H" | "--height" => options.height = Some(positive(&arg, &mut args)?),
+            "-n" | "--samples" => options.samples = Some(positive(&arg, &mut args)?),
+            "-j" | "--threads" => options.threads = positive(&arg, &mut args)?,
+            "-o" | "--output" => options.output = Some(value(&arg, &mut args)?),
+            "--half" => options.half = true,
+            "--aovs" => options.aovs = true,
+            "-e" | "--exposure" => options.exposure = Some(value(&arg, &mut args)?),
+            "-t" | "--tonemap" => {
+                let name: String = value(&arg, &mut args)?;
+                options.tone_mapping = Some(name.parse()?);
+            }
+            "--dither" => options.dither = true,
+            "--spectral" => options.spectral = true,
+            "-h" | "--help" => options.help = true,
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+            _ if options.scene.is_none() => options.scene = Some(arg),
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+
+    Ok(options)
+}
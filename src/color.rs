@@ -3,14 +3,21 @@ use image::Rgba;
 use std::convert::From;
 use std::ops::{Add, Mul};
 
-const GAMMA: f32 = 2.2;
-
-fn gamma_encode(linear: f32) -> f32 {
-    linear.powf(1.0 / GAMMA)
+// The piecewise sRGB transfer function (IEC 61966-2-1), with a linear segment near black.
+pub fn srgb_encode(linear: f32) -> f32 {
+    if linear <= 0.003_130_8 {
+        12.92 * linear
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
 }
 
-fn gamma_decode(encoded: f32) -> f32 {
-    encoded.powf(GAMMA)
+pub fn srgb_decode(encoded: f32) -> f32 {
+    if encoded <= 0.040_45 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -33,10 +40,10 @@ impl Color {
 
     pub fn from_rgba(rgba: Rgba<u8>) -> Color {
         Color {
-            r: gamma_decode((rgba[0] as f32) / 255.0),
-            g: gamma_decode((rgba[1] as f32) / 255.0),
-            b: gamma_decode((rgba[2] as f32) / 255.0),
-            a: gamma_decode((rgba[3] as f32) / 255.0),
+            r: srgb_decode((rgba[0] as f32) / 255.0),
+            g: srgb_decode((rgba[1] as f32) / 255.0),
+            b: srgb_decode((rgba[2] as f32) / 255.0),
+            a: (rgba[3] as f32) / 255.0,
         }
     }
 
@@ -49,13 +56,9 @@ impl Color {
         }
     }
 
-    pub fn into_pixel(&self) -> u32 {
-        let r: u32 = (((gamma_encode(self.r) * 255.0) as u8) as u32) << 16;
-        let g: u32 = (((gamma_encode(self.g) * 255.0) as u8) as u32) << 8;
-        let b: u32 = (((gamma_encode(self.b) * 255.0) as u8) as u32) << 0;
-        let a: u32 = (((gamma_encode(self.a) * 255.0) as u8) as u32) << 24;
-
-        r | g | b | a
+    // Relative luminance of linear sRGB.
+    pub fn luminance(&self) -> f32 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
     }
 }
 
@@ -0,0 +1,7 @@
+pub mod color;
+pub mod math;
+pub mod objects;
+pub mod renderer;
+pub mod scene;
+
+pub use renderer::{Framebuffer, RenderSettings, Renderer};
@@ -1,71 +1,24 @@
-mod color;
-mod math;
-mod objects;
-mod renderer;
-mod scene;
-
-use color::Color;
-use crossbeam_queue::SegQueue;
-use math::Vec3;
+mod cli;
+
 use minifb::{Key, Window, WindowOptions};
-use objects::{MovingSphere, Object, Sphere};
 use rand::prelude::*;
-use renderer::{Dialectric, Lambertian, Material, Metal};
-use scene::{Camera, Scene};
-use std::sync::{Arc, Mutex};
-use std::thread;
-
-const WINDOW_WIDTH: usize = 512;
-const WINDOW_HEIGHT: usize = 512;
-
-const CHUNK_WIDTH: usize = 128;
-const CHUNK_HEIGHT: usize = 128;
-
-pub type SharedBuffer = Arc<Mutex<Vec<u32>>>;
-pub type SharedScene = Arc<Scene>;
-
-#[derive(Copy, Clone)]
-pub struct Chunk {
-    pub x: usize,
-    pub y: usize,
-    pub w: usize,
-    pub h: usize,
-}
-
-#[derive(Copy, Clone)]
-pub struct RenderJob {
-    pub chunk: Chunk,
-    pub ms: u32,
-}
-
-fn main() {
-    let buffer: Vec<u32> = vec![0; WINDOW_WIDTH * WINDOW_HEIGHT];
-    let buffer = Arc::new(Mutex::new(buffer));
-
-    let mut window = Window::new(
-        "Ray Tracer",
-        WINDOW_WIDTH,
-        WINDOW_HEIGHT,
-        WindowOptions {
-            borderless: false,
-            resize: true,
-            scale: minifb::Scale::X1,
-            scale_mode: minifb::ScaleMode::AspectRatioStretch,
-            title: true,
-            topmost: false,
-            transparency: false,
-        },
-    )
-    .unwrap();
-
-    window.limit_update_rate(Some(std::time::Duration::from_millis(10)));
-
-    let mut rng = rand::thread_rng();
-
-    let scene = Scene::create_with_bvh(&random_spheres(&mut rng), 32);
-    let scene = Arc::new(scene);
-
-    let aspect = (WINDOW_WIDTH as f64) / (WINDOW_HEIGHT as f64);
+use ray_tracer_in_one_weekend::color::Color;
+use ray_tracer_in_one_weekend::math::Vec3;
+use ray_tracer_in_one_weekend::objects::{MovingSphere, Object, Sphere};
+use ray_tracer_in_one_weekend::renderer::{
+    Dialectric, ExrPixelType, Lambertian, Material, Metal, OutputFormat, Texture, ViewTransform,
+};
+use ray_tracer_in_one_weekend::scene::{
+    load_obj, load_scene, Camera, LoadedScene, Scene, SceneError,
+};
+use ray_tracer_in_one_weekend::{RenderSettings, Renderer};
+use std::path::Path;
+
+fn default_scene(objects: Vec<Object>) -> LoadedScene {
+    let width = 512;
+    let height = 512;
+
+    let aspect = (width as f64) / (height as f64);
     let from = Vec3::new(13.0, 2.0, 3.0);
     let at = Vec3::new(0.0, 0.0, 0.0);
     let dist = 10.0;
@@ -83,106 +36,179 @@ fn main() {
         1.0,
     );
 
-    let chunks_x = WINDOW_WIDTH / CHUNK_WIDTH;
-    let chunks_y = WINDOW_HEIGHT / CHUNK_HEIGHT;
-    let mut chunks: Vec<Chunk> = (0..(chunks_x * chunks_y))
-        .map(|i| Chunk {
-            x: (i % chunks_x) * CHUNK_WIDTH,
-            y: (i / chunks_x) * CHUNK_HEIGHT,
-            w: CHUNK_WIDTH,
-            h: CHUNK_HEIGHT,
-        })
-        .collect();
+    LoadedScene {
+        scene: Scene::create_with_bvh(&objects, 32),
+        camera,
+        width,
+        height,
+        samples: vec![4, 32, 128, 256, 512],
+        view: ViewTransform::new(),
+    }
+}
 
-    chunks.shuffle(&mut rng);
+fn load(path: Option<String>, rng: &mut dyn RngCore) -> LoadedScene {
+    let path = match path {
+        Some(path) => path,
+        None => return default_scene(random_spheres(rng)),
+    };
+
+    let is_obj = Path::new(&path)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("obj"));
+
+    let result = if is_obj {
+        load_obj(Path::new(&path))
+            .map(|meshes| {
+                default_scene(
+                    meshes
+                        .into_iter()
+                        .flat_map(|mesh| mesh.into_objects())
+                        .collect(),
+                )
+            })
+            .map_err(SceneError::from)
+    } else {
+        load_scene(Path::new(&path))
+    };
+
+    match result {
+        Ok(loaded) => loaded,
+        Err(error) => {
+            eprintln!("failed to load {}: {}", path, error);
+            std::process::exit(1);
+        }
+    }
+}
 
-    let job_queue = SegQueue::<RenderJob>::new();
-    let job_queue = Arc::new(job_queue);
+fn main() {
+    let options = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{}\n\n{}", error, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
 
-    for chunk in &chunks {
-        job_queue.push(RenderJob {
-            chunk: *chunk,
-            ms: 4,
-        });
+    if options.help {
+        println!("{}", cli::USAGE);
+        return;
     }
 
-    for chunk in &chunks {
-        job_queue.push(RenderJob {
-            chunk: *chunk,
-            ms: 32,
-        });
-    }
+    let exr_type = if options.half {
+        ExrPixelType::Half
+    } else {
+        ExrPixelType::Float
+    };
+    let format = options.output.as_ref().map(|output| {
+        OutputFormat::from_path(output, exr_type).unwrap_or_else(|| {
+            eprintln!(
+                "unsupported output format for {}, expected .png, .exr, .hdr or .pfm",
+                output.display()
+            );
+            std::process::exit(2);
+        })
+    });
 
-    for chunk in &chunks {
-        job_queue.push(RenderJob {
-            chunk: *chunk,
-            ms: 128,
-        });
-    }
+    let mut rng = rand::thread_rng();
 
-    for chunk in &chunks {
-        job_queue.push(RenderJob {
-            chunk: *chunk,
-            ms: 256,
-        });
+    let LoadedScene {
+        mut scene,
+        mut camera,
+        mut width,
+        mut height,
+        mut samples,
+        mut view,
+    } = load(options.scene.clone(), &mut rng);
+
+    if options.width.is_some() || options.height.is_some() {
+        width = options.width.unwrap_or(width);
+        height = options.height.unwrap_or(height);
+        camera = camera.with_aspect((width as f64) / (height as f64));
     }
 
-    for chunk in &chunks {
-        job_queue.push(RenderJob {
-            chunk: *chunk,
-            ms: 512,
-        });
+    if let Some(exposure) = options.exposure {
+        view.exposure = exposure;
+    }
+    if let Some(tone_mapping) = options.tone_mapping {
+        view.tone_mapping = tone_mapping;
+    }
+    view.dither |= options.dither;
+    scene.spectral |= options.spectral;
+
+    if let Some(spp) = options.samples {
+        samples = if options.output.is_some() {
+            vec![spp]
+        } else {
+            samples.into_iter().filter(|ms| *ms < spp).collect()
+        };
+        if samples.last() != Some(&spp) {
+            samples.push(spp);
+        }
+    } else if options.output.is_some() {
+        samples = samples.last().copied().into_iter().collect();
     }
 
-    for _ in 0..4 {
-        let thread_scene = scene.clone();
-        let thread_buffer = Arc::clone(&buffer);
-        let thread_queue = Arc::clone(&job_queue);
-
-        thread::spawn(move || {
-            println!("starting worker thread");
-
-            let mut rng = rand::thread_rng();
-            loop {
-                if let Ok(job) = thread_queue.pop() {
-                    println!(
-                        "doing render job: {}, {}, {}",
-                        job.chunk.x, job.chunk.y, job.ms
-                    );
-                    renderer::render_chunk(
-                        job.chunk,
-                        WINDOW_WIDTH,
-                        WINDOW_HEIGHT,
-                        &camera,
-                        &thread_scene,
-                        &thread_buffer,
-                        &mut rng,
-                        job.ms,
-                    );
-
-                    println!(
-                        "done render job: {}, {}, {}",
-                        job.chunk.x, job.chunk.y, job.ms
-                    );
-                    println!("remaining jobs: {}", thread_queue.len());
-                }
+    let mut settings = RenderSettings::new(width, height, samples);
+    settings.threads = options.threads;
+    settings.aovs = options.aovs && matches!(format, Some(OutputFormat::Exr(_)));
 
-                if thread_queue.is_empty() {
-                    thread::sleep(std::time::Duration::from_millis(100));
-                }
+    let renderer = Renderer::start(scene, camera, settings);
+
+    if let (Some(output), Some(format)) = (options.output, format) {
+        let mut reported = 0;
+        while !renderer.is_finished() {
+            let (done, total) = renderer.progress();
+            if done != reported {
+                println!("rendered {}/{} jobs", done, total);
+                reported = done;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+
+        let framebuffer = match renderer.wait() {
+            Ok(framebuffer) => framebuffer,
+            Err(_) => {
+                eprintln!("render thread panicked");
+                std::process::exit(1);
             }
-        });
+        };
+
+        if let Err(error) = framebuffer.save(&output, format, &view) {
+            eprintln!("failed to write {}: {}", output.display(), error);
+            std::process::exit(1);
+        }
+
+        println!("saved {}", output.display());
+        return;
     }
 
+    let mut window = Window::new(
+        "Ray Tracer",
+        width,
+        height,
+        WindowOptions {
+            borderless: false,
+            resize: true,
+            scale: minifb::Scale::X1,
+            scale_mode: minifb::ScaleMode::AspectRatioStretch,
+            title: true,
+            topmost: false,
+            transparency: false,
+        },
+    )
+    .unwrap();
+
+    window.limit_update_rate(Some(std::time::Duration::from_millis(10)));
+
     while window.is_open() && !window.is_key_down(Key::Escape) {
-        let pixels: Vec<u32>;
-        {
-            let buffer = &buffer.lock().unwrap();
-            pixels = buffer.to_vec();
-        }
+        let framebuffer = renderer.snapshot();
 
         window
-            .update_with_buffer(&pixels, WINDOW_WIDTH, WINDOW_HEIGHT)
+            .update_with_buffer(
+                &framebuffer.to_argb(&view),
+                framebuffer.width,
+                framebuffer.height,
+            )
             .unwrap();
     }
 }
@@ -194,9 +220,8 @@ fn random_spheres(rng: &mut dyn RngCore) -> Vec<Object> {
         center: Vec3::new(0.0, -1000.0, 0.0),
         radius: 1000.0,
         material: Material::Lambertian(Lambertian {
-            albedo: Color::new(0.5, 0.5, 0.5, 1.0),
+            albedo: Texture::Constant(Color::new(0.5, 0.5, 0.5, 1.0)),
         }),
-        node_index: 0,
     }));
 
     for a in -11..11 {
@@ -211,25 +236,25 @@ fn random_spheres(rng: &mut dyn RngCore) -> Vec<Object> {
             if (center - Vec3::new(4.0, 0.2, 0.0)).magnitude() > 0.9 {
                 let material = if material_rng < 0.8 {
                     Material::Lambertian(Lambertian {
-                        albedo: Color::new(
+                        albedo: Texture::Constant(Color::new(
                             rng.gen::<f32>() * rng.gen::<f32>(),
                             rng.gen::<f32>() * rng.gen::<f32>(),
                             rng.gen::<f32>() * rng.gen::<f32>(),
                             1.0,
-                        ),
+                        )),
                     })
                 } else if material_rng < 0.95 {
                     Material::Metal(Metal {
-                        albedo: Color::new(
+                        albedo: Texture::Constant(Color::new(
                             0.5 * (1.0 + rng.gen::<f32>()),
                             0.5 * (1.0 + rng.gen::<f32>()),
                             0.5 * (1.0 + rng.gen::<f32>()),
                             1.0,
-                        ),
+                        )),
                         fuzz: 0.5 * rng.gen::<f64>(),
                     })
                 } else {
-                    Material::Dialectric(Dialectric { index: 1.5 })
+                    Material::Dialectric(Dialectric::new(1.5))
                 };
 
                 let type_rng: f32 = rng.gen();
@@ -238,7 +263,6 @@ fn random_spheres(rng: &mut dyn RngCore) -> Vec<Object> {
                         center: center,
                         radius: 0.2,
                         material: material,
-                        node_index: 0,
                     }));
                 } else {
                     result.push(Object::MovingSphere(MovingSphere {
@@ -248,7 +272,6 @@ fn random_spheres(rng: &mut dyn RngCore) -> Vec<Object> {
                         time1: 1.0,
                         radius: 0.2,
                         material: material,
-                        node_index: 0,
                     }));
                 }
             };
@@ -258,27 +281,24 @@ fn random_spheres(rng: &mut dyn RngCore) -> Vec<Object> {
     result.push(Object::Sphere(Sphere {
         center: Vec3::new(0.0, 1.0, 0.0),
         radius: 1.0,
-        material: Material::Dialectric(Dialectric { index: 1.5 }),
-        node_index: 0,
+        material: Material::Dialectric(Dialectric::new(1.5)),
     }));
 
     result.push(Object::Sphere(Sphere {
         center: Vec3::new(-4.0, 1.0, 0.0),
         radius: 1.0,
         material: Material::Lambertian(Lambertian {
-            albedo: Color::new(0.4, 0.2, 0.1, 1.0),
+            albedo: Texture::Constant(Color::new(0.4, 0.2, 0.1, 1.0)),
         }),
-        node_index: 0,
     }));
 
     result.push(Object::Sphere(Sphere {
         center: Vec3::new(4.0, 1.0, 0.0),
         radius: 1.0,
         material: Material::Metal(Metal {
-            albedo: Color::new(0.7, 0.6, 0.5, 1.0),
+            albedo: Texture::Constant(Color::new(0.7, 0.6, 0.5, 1.0)),
             fuzz: 0.0,
         }),
-        node_index: 0,
     }));
 
     result
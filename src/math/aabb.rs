@@ -36,61 +36,98 @@ impl AABB {
         }
     }
 
-    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<()> {
-        {
-            // test x
-            let inv_d = 1.0 / ray.direction.x;
-            let mut t0 = (self.min.x - ray.origin.x) / inv_d;
-            let mut t1 = (self.max.x - ray.origin.x) / inv_d;
-
-            if inv_d < 0.0 {
-                std::mem::swap(&mut t0, &mut t1);
+    // Widens every axis thinner than `delta` around its center. Planar shapes have boxes without any
+    // thickness, which a ray travelling inside their plane would reach with undefined slab distances.
+    pub fn pad(&self, delta: f64) -> AABB {
+        let pad_axis = |min: f64, max: f64| {
+            if max - min < delta {
+                let center = 0.5 * (min + max);
+                (center - 0.5 * delta, center + 0.5 * delta)
+            } else {
+                (min, max)
             }
+        };
 
-            let t_min = if t0 > t_min { t0 } else { t_min };
-            let t_max = if t1 < t_max { t1 } else { t_max };
+        let (x0, x1) = pad_axis(self.min.x, self.max.x);
+        let (y0, y1) = pad_axis(self.min.y, self.max.y);
+        let (z0, z1) = pad_axis(self.min.z, self.max.z);
 
-            if t_max <= t_min {
-                return None;
-            }
-        }
+        AABB::from_min_max(Vec3::new(x0, y0, z0), Vec3::new(x1, y1, z1))
+    }
+
+    pub fn grow(&self, point: &Vec3) -> AABB {
+        AABB::combine(self, &AABB::from_min_max(*point, *point))
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        0.5 * (self.min + self.max)
+    }
+
+    pub fn extent(&self) -> Vec3 {
+        self.max - self.min
+    }
 
-        {
-            // test y
-            let inv_d = 1.0 / ray.direction.y;
-            let mut t0 = (self.min.y - ray.origin.y) / inv_d;
-            let mut t1 = (self.max.y - ray.origin.y) / inv_d;
+    pub fn surface_area(&self) -> f64 {
+        let e = self.extent();
+        2.0 * (e.x * e.y + e.y * e.z + e.z * e.x)
+    }
+
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        let inv_direction = Vec3::new(
+            1.0 / ray.direction.x,
+            1.0 / ray.direction.y,
+            1.0 / ray.direction.z,
+        );
+
+        self.hit_inverse(&ray.origin, &inv_direction, t_min, t_max)
+    }
+
+    // The part of [t_min, t_max] that the ray spends inside the box, if any.
+    pub fn clip(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let mut t_min = t_min;
+        let mut t_max = t_max;
 
-            if inv_d < 0.0 {
+        for axis in 0..3 {
+            let inv_direction = 1.0 / ray.direction[axis];
+            let mut t0 = (self.min[axis] - ray.origin[axis]) * inv_direction;
+            let mut t1 = (self.max[axis] - ray.origin[axis]) * inv_direction;
+
+            if inv_direction < 0.0 {
                 std::mem::swap(&mut t0, &mut t1);
             }
 
-            let t_min = if t0 > t_min { t0 } else { t_min };
-            let t_max = if t1 < t_max { t1 } else { t_max };
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
 
             if t_max <= t_min {
                 return None;
             }
         }
 
-        {
-            // test z
-            let inv_d = 1.0 / ray.direction.z;
-            let mut t0 = (self.min.z - ray.origin.z) / inv_d;
-            let mut t1 = (self.max.z - ray.origin.z) / inv_d;
+        Some((t_min, t_max))
+    }
+
+    // Slab test with the reciprocal ray direction precomputed, so traversals can reuse it for every node.
+    pub fn hit_inverse(&self, origin: &Vec3, inv_direction: &Vec3, t_min: f64, t_max: f64) -> bool {
+        let mut t_min = t_min;
+        let mut t_max = t_max;
 
-            if inv_d < 0.0 {
+        for axis in 0..3 {
+            let mut t0 = (self.min[axis] - origin[axis]) * inv_direction[axis];
+            let mut t1 = (self.max[axis] - origin[axis]) * inv_direction[axis];
+
+            if inv_direction[axis] < 0.0 {
                 std::mem::swap(&mut t0, &mut t1);
             }
 
-            let t_min = if t0 > t_min { t0 } else { t_min };
-            let t_max = if t1 < t_max { t1 } else { t_max };
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
 
-            if t_max <= t_min {
-                return None;
+            if t_max < t_min {
+                return false;
             }
         }
 
-        Some(())
+        true
     }
 }
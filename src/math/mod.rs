@@ -1,7 +1,11 @@
 mod aabb;
+mod onb;
 mod ray;
+mod transform;
 mod vec3;
 
 pub use aabb::AABB;
+pub use onb::Onb;
 pub use ray::Ray;
+pub use transform::{Matrix4, Transform};
 pub use vec3::{Vec3, Vec3IntoIterator};
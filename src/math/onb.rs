@@ -0,0 +1,40 @@
+use super::Vec3;
+
+#[derive(Debug, Copy, Clone)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn from_w(n: &Vec3) -> Onb {
+        let w = n.normalize();
+        let a = if w.x.abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = Vec3::cross(&w, &a).normalize();
+        let u = Vec3::cross(&w, &v);
+
+        Onb { u, v, w }
+    }
+
+    pub fn local(&self, a: f64, b: f64, c: f64) -> Vec3 {
+        a * self.u + b * self.v + c * self.w
+    }
+
+    // Coordinates of the world space vector `a` in the basis.
+    pub fn to_local(&self, a: &Vec3) -> Vec3 {
+        Vec3::new(
+            Vec3::dot(a, &self.u),
+            Vec3::dot(a, &self.v),
+            Vec3::dot(a, &self.w),
+        )
+    }
+
+    pub fn to_world(&self, a: &Vec3) -> Vec3 {
+        self.local(a.x, a.y, a.z)
+    }
+}
@@ -0,0 +1,275 @@
+use super::Vec3;
+use std::ops::Mul;
+
+// Row major 4x4 matrix acting on column vectors, so `a * b` applies `b` first.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Matrix4 {
+    pub m: [[f64; 4]; 4],
+}
+
+impl Matrix4 {
+    pub fn identity() -> Matrix4 {
+        Matrix4 {
+            m: [
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    pub fn translation(offset: &Vec3) -> Matrix4 {
+        let mut result = Matrix4::identity();
+        result.m[0][3] = offset.x;
+        result.m[1][3] = offset.y;
+        result.m[2][3] = offset.z;
+        result
+    }
+
+    pub fn scaling(scale: &Vec3) -> Matrix4 {
+        let mut result = Matrix4::identity();
+        result.m[0][0] = scale.x;
+        result.m[1][1] = scale.y;
+        result.m[2][2] = scale.z;
+        result
+    }
+
+    // Counter-clockwise rotation by `degrees` around `axis`, looking down the axis towards the origin.
+    pub fn rotation(axis: &Vec3, degrees: f64) -> Matrix4 {
+        let a = axis.normalize();
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let t = 1.0 - cos;
+
+        Matrix4 {
+            m: [
+                [
+                    t * a.x * a.x + cos,
+                    t * a.x * a.y - sin * a.z,
+                    t * a.x * a.z + sin * a.y,
+                    0.0,
+                ],
+                [
+                    t * a.x * a.y + sin * a.z,
+                    t * a.y * a.y + cos,
+                    t * a.y * a.z - sin * a.x,
+                    0.0,
+                ],
+                [
+                    t * a.x * a.z - sin * a.y,
+                    t * a.y * a.z + sin * a.x,
+                    t * a.z * a.z + cos,
+                    0.0,
+                ],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    pub fn transpose(&self) -> Matrix4 {
+        let mut result = Matrix4::identity();
+        for row in 0..4 {
+            for column in 0..4 {
+                result.m[row][column] = self.m[column][row];
+            }
+        }
+        result
+    }
+
+    // Gauss-Jordan elimination with partial pivoting. Returns `None` for singular matrices.
+    pub fn inverse(&self) -> Option<Matrix4> {
+        let mut a = self.m;
+        let mut result = Matrix4::identity().m;
+
+        for column in 0..4 {
+            let pivot = (column..4)
+                .max_by(|i, j| {
+                    a[*i][column]
+                        .abs()
+                        .partial_cmp(&a[*j][column].abs())
+                        .unwrap()
+                })
+                .unwrap();
+            if a[pivot][column].abs() < 1e-12 {
+                return None;
+            }
+
+            a.swap(column, pivot);
+            result.swap(column, pivot);
+
+            let scale = 1.0 / a[column][column];
+            for k in 0..4 {
+                a[column][k] *= scale;
+                result[column][k] *= scale;
+            }
+
+            for row in 0..4 {
+                if row == column {
+                    continue;
+                }
+
+                let factor = a[row][column];
+                for k in 0..4 {
+                    a[row][k] -= factor * a[column][k];
+                    result[row][k] -= factor * result[column][k];
+                }
+            }
+        }
+
+        Some(Matrix4 { m: result })
+    }
+
+    // Determinant of the upper 3x3 part, the scale factor of volumes under the transform.
+    pub fn linear_determinant(&self) -> f64 {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    pub fn transform_point(&self, p: &Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3],
+            m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3],
+            m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3],
+        )
+    }
+
+    pub fn transform_vector(&self, v: &Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+}
+
+impl Mul for Matrix4 {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self::Output {
+        let mut result = [[0.0; 4]; 4];
+        for (row, result_row) in result.iter_mut().enumerate() {
+            for (column, value) in result_row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[row][k] * other.m[k][column]).sum();
+            }
+        }
+
+        Matrix4 { m: result }
+    }
+}
+
+// An affine transform together with its inverse, which is what rays and normals are mapped with.
+#[derive(Debug, Copy, Clone)]
+pub struct Transform {
+    pub matrix: Matrix4,
+    pub inverse: Matrix4,
+}
+
+impl Transform {
+    pub fn new(matrix: Matrix4) -> Option<Transform> {
+        Some(Transform {
+            matrix,
+            inverse: matrix.inverse()?,
+        })
+    }
+
+    pub fn point(&self, p: &Vec3) -> Vec3 {
+        self.matrix.transform_point(p)
+    }
+
+    pub fn vector(&self, v: &Vec3) -> Vec3 {
+        self.matrix.transform_vector(v)
+    }
+
+    // Normals transform with the inverse transpose to stay perpendicular to the transformed surface. The
+    // result is not normalized.
+    pub fn normal(&self, n: &Vec3) -> Vec3 {
+        let m = &self.inverse.m;
+        Vec3::new(
+            m[0][0] * n.x + m[1][0] * n.y + m[2][0] * n.z,
+            m[0][1] * n.x + m[1][1] * n.y + m[2][1] * n.z,
+            m[0][2] * n.x + m[1][2] * n.y + m[2][2] * n.z,
+        )
+    }
+
+    pub fn inverse_point(&self, p: &Vec3) -> Vec3 {
+        self.inverse.transform_point(p)
+    }
+
+    pub fn inverse_vector(&self, v: &Vec3) -> Vec3 {
+        self.inverse.transform_vector(v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn skewed() -> Matrix4 {
+        Matrix4::translation(&Vec3::new(1.0, -2.0, 0.5))
+            * Matrix4::rotation(&Vec3::new(1.0, 2.0, -0.5), 37.0)
+            * Matrix4::scaling(&Vec3::new(2.0, 0.5, -1.5))
+            * Matrix4::rotation(&Vec3::new(0.0, 1.0, 1.0), -70.0)
+    }
+
+    fn assert_identity(m: &Matrix4) {
+        let identity = Matrix4::identity();
+        for row in 0..4 {
+            for column in 0..4 {
+                assert!((m.m[row][column] - identity.m[row][column]).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn inverse_undoes_the_matrix() {
+        let m = skewed();
+        let inverse = m.inverse().unwrap();
+        assert_identity(&(inverse * m));
+        assert_identity(&(m * inverse));
+
+        // A zero on the diagonal needs the pivoting.
+        let swap = Matrix4::rotation(&Vec3::new(0.0, 0.0, 1.0), 90.0);
+        assert_identity(&(swap.inverse().unwrap() * swap));
+    }
+
+    #[test]
+    fn singular_matrices_have_no_inverse() {
+        assert!(Matrix4::scaling(&Vec3::new(1.0, 0.0, 2.0))
+            .inverse()
+            .is_none());
+        assert!(Transform::new(Matrix4::scaling(&Vec3::new(0.0, 1.0, 1.0))).is_none());
+    }
+
+    #[test]
+    fn normals_stay_perpendicular() {
+        let transform = Transform::new(skewed()).unwrap();
+        let tangents = [Vec3::new(1.0, 2.0, 0.0), Vec3::new(-0.5, 0.3, 1.0)];
+        let normal = Vec3::cross(&tangents[0], &tangents[1]);
+
+        let transformed = transform.normal(&normal);
+        for tangent in &tangents {
+            let tangent = transform.vector(tangent);
+            assert!(Vec3::dot(&transformed, &tangent).abs() < 1e-9);
+        }
+
+        // The plain matrix would tilt the normal under non-uniform scaling.
+        let wrong = transform.vector(&normal);
+        assert!(Vec3::dot(&wrong, &transform.vector(&tangents[0])).abs() > 1e-3);
+    }
+
+    #[test]
+    fn points_map_back_through_the_inverse() {
+        let transform = Transform::new(skewed()).unwrap();
+        let p = Vec3::new(0.3, -4.0, 2.5);
+        let back = transform.inverse_point(&transform.point(&p));
+        assert!((back - p).magnitude() < 1e-12);
+
+        let v = Vec3::new(1.0, 0.0, -1.0);
+        let back = transform.inverse_vector(&transform.vector(&v));
+        assert!((back - v).magnitude() < 1e-12);
+    }
+}
@@ -0,0 +1,199 @@
+use super::{Intersectable, Intersection, Object};
+use crate::math::{Ray, Vec3, AABB};
+use crate::renderer::Material;
+use rand::prelude::*;
+use std::sync::Arc;
+
+// A volume of constant density filling a closed `boundary`. Rays passing through it scatter after an
+// exponentially distributed free-flight distance, and the hit then uses `phase_function` (normally
+// `Isotropic`) as its material.
+#[derive(Clone)]
+pub struct ConstantMedium {
+    pub boundary: Arc<Object>,
+    pub density: f64,
+    pub phase_function: Material,
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Arc<Object>, density: f64, phase_function: Material) -> ConstantMedium {
+        ConstantMedium {
+            boundary,
+            density,
+            phase_function,
+        }
+    }
+
+    // The part of [t_min, t_max] inside the boundary.
+    fn segment(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let enter = self
+            .boundary
+            .intersect(ray, -f64::INFINITY, f64::INFINITY)?;
+        let exit = self
+            .boundary
+            .intersect(ray, enter.distance + 0.0001, f64::INFINITY)?;
+
+        let t0 = enter.distance.max(t_min).max(0.0);
+        let t1 = exit.distance.min(t_max);
+        if t0 >= t1 {
+            return None;
+        }
+
+        Some((t0, t1))
+    }
+}
+
+impl Intersectable for ConstantMedium {
+    fn intersect(&self, _ray: &Ray, _t_min: f64, _t_max: f64) -> Option<Intersection<'_>> {
+        None
+    }
+
+    fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB> {
+        self.boundary.bounding_box(t0, t1)
+    }
+
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rng: &mut dyn RngCore,
+    ) -> Option<Intersection<'_>> {
+        let (t0, t1) = self.segment(ray, t_min, t_max)?;
+
+        let length = ray.direction.magnitude();
+        let inside = (t1 - t0) * length;
+        let flight = -(1.0 - rng.gen::<f64>()).ln() / self.density;
+        if flight > inside {
+            return None;
+        }
+
+        let t = t0 + flight / length;
+        Some(Intersection {
+            distance: t,
+            position: ray.get_point_along(t),
+            normal: Vec3::new(1.0, 0.0, 0.0),
+            u: 0.0,
+            v: 0.0,
+            material: &self.phase_function,
+        })
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64, _rng: &mut dyn RngCore) -> f64 {
+        match self.segment(ray, t_min, t_max) {
+            Some((t0, t1)) => (-self.density * (t1 - t0) * ray.direction.magnitude()).exp(),
+            None => 1.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::math::{Matrix4, Transform};
+    use crate::objects::{cuboid, Group, Instance};
+    use crate::renderer::{Isotropic, Texture};
+    use crate::scene::Scene;
+
+    const DENSITY: f64 = 0.7;
+    const THICKNESS: f64 = 1.5;
+
+    // A medium filling the slab 0 <= z <= THICKNESS.
+    fn slab() -> ConstantMedium {
+        let boundary = cuboid(
+            Vec3::new(-100.0, -100.0, 0.0),
+            Vec3::new(100.0, 100.0, THICKNESS),
+            Material::Isotropic(Isotropic {
+                albedo: Texture::Constant(Color::new(1.0, 1.0, 1.0, 1.0)),
+            }),
+        );
+        let phase_function = Material::Isotropic(Isotropic {
+            albedo: Texture::Constant(Color::new(0.5, 0.5, 0.5, 1.0)),
+        });
+
+        ConstantMedium::new(
+            Arc::new(Object::Group(Arc::new(Group::new(boundary)))),
+            DENSITY,
+            phase_function,
+        )
+    }
+
+    fn scattered_share(object: &dyn Intersectable, ray: &Ray, rng: &mut StdRng) -> f64 {
+        const RAYS: usize = 100_000;
+        let hits = (0..RAYS)
+            .filter(|_| object.hit(ray, 0.001, f64::INFINITY, rng).is_some())
+            .count();
+        hits as f64 / RAYS as f64
+    }
+
+    #[test]
+    fn slab_transmittance_is_exponential_in_the_distance() {
+        let medium = slab();
+        let mut rng = StdRng::seed_from_u64(1);
+
+        let straight = Ray::new(Vec3::new(0.3, 0.2, -1.0), Vec3::new(0.0, 0.0, 1.0));
+        let expected = (-DENSITY * THICKNESS).exp();
+        let transmittance = medium.transmittance(&straight, 0.001, f64::INFINITY, &mut rng);
+        assert!((transmittance - expected).abs() < 1e-9);
+
+        // Slanted rays travel further through the slab, whatever the length of their direction.
+        let slanted = Ray::new(Vec3::new(0.3, 0.2, -1.0), Vec3::new(1.2, 0.0, 1.6));
+        let expected = (-DENSITY * THICKNESS / 0.8).exp();
+        let transmittance = medium.transmittance(&slanted, 0.001, f64::INFINITY, &mut rng);
+        assert!((transmittance - expected).abs() < 1e-9);
+
+        // Shadow rays stop at the light, and rays starting inside only count what lies ahead.
+        let expected = (-DENSITY * 0.5).exp();
+        let transmittance = medium.transmittance(&straight, 0.001, 1.5, &mut rng);
+        assert!((transmittance - expected).abs() < 1e-9);
+        let inside = Ray::new(Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, 1.0));
+        let transmittance = medium.transmittance(&inside, 0.0, f64::INFINITY, &mut rng);
+        assert!((transmittance - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rays_scatter_as_often_as_they_are_attenuated() {
+        let medium = slab();
+        let mut rng = StdRng::seed_from_u64(2);
+        let ray = Ray::new(Vec3::new(0.3, 0.2, -1.0), Vec3::new(0.0, 0.5, 1.0));
+
+        assert!(medium.intersect(&ray, 0.001, f64::INFINITY).is_none());
+
+        let expected = 1.0 - medium.transmittance(&ray, 0.001, f64::INFINITY, &mut rng);
+        let share = scattered_share(&medium, &ray, &mut rng);
+        assert!((share - expected).abs() < 0.01, "{} {}", share, expected);
+
+        for _ in 0..100 {
+            if let Some(hit) = medium.hit(&ray, 0.001, f64::INFINITY, &mut rng) {
+                assert!((0.0..=THICKNESS).contains(&hit.position.z));
+            }
+        }
+    }
+
+    // Volumes inside groups and instances are found by the scene's BVH like any other object.
+    #[test]
+    fn nested_media_are_traversed() {
+        let instance = Instance::new(
+            Arc::new(Object::ConstantMedium(slab())),
+            Transform::new(Matrix4::translation(&Vec3::new(0.0, 0.0, 2.0))).unwrap(),
+        );
+        let group = Group::new(vec![Object::Instance(instance)]);
+        let scene = Scene::create_with_bvh(&[Object::Group(Arc::new(group))], 8);
+        let mut rng = StdRng::seed_from_u64(3);
+
+        let ray = Ray::new(Vec3::new(0.3, 0.2, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(scene.intersect(&ray, 0.001, f64::INFINITY).is_none());
+
+        let transmittance = scene.transmittance(&ray, 0.001, f64::INFINITY, &mut rng);
+        assert!((transmittance - (-DENSITY * THICKNESS).exp()).abs() < 1e-9);
+
+        let share = scattered_share(&scene, &ray, &mut rng);
+        assert!((share - (1.0 - transmittance)).abs() < 0.01);
+
+        for _ in 0..100 {
+            if let Some(hit) = scene.hit(&ray, 0.001, f64::INFINITY, &mut rng) {
+                assert!((2.0..=2.0 + THICKNESS).contains(&hit.position.z));
+            }
+        }
+    }
+}
@@ -0,0 +1,357 @@
+use super::{Intersectable, Intersection};
+use crate::math::{Ray, Vec3, AABB};
+use crate::renderer::Material;
+use rand::prelude::*;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn read_i32(bytes: &[u8], offset: usize) -> i32 {
+    let mut word = [0; 4];
+    word.copy_from_slice(&bytes[offset..(offset + 4)]);
+    i32::from_le_bytes(word)
+}
+
+fn read_f32(bytes: &[u8], offset: usize) -> f32 {
+    let mut word = [0; 4];
+    word.copy_from_slice(&bytes[offset..(offset + 4)]);
+    f32::from_le_bytes(word)
+}
+
+// A dense 3D grid of scalar values, stored with x varying fastest, then y, then z.
+pub struct VoxelGrid {
+    pub nx: usize,
+    pub ny: usize,
+    pub nz: usize,
+    pub values: Vec<f32>,
+
+    max: f32,
+}
+
+impl VoxelGrid {
+    pub fn new(nx: usize, ny: usize, nz: usize, values: Vec<f32>) -> Option<VoxelGrid> {
+        if nx == 0 || ny == 0 || nz == 0 || values.len() != nx * ny * nz {
+            return None;
+        }
+
+        let max = values.iter().cloned().fold(0.0, f32::max);
+        Some(VoxelGrid {
+            nx,
+            ny,
+            nz,
+            values,
+            max,
+        })
+    }
+
+    // Headerless little endian 32 bit floats.
+    pub fn load_raw(path: &Path, nx: usize, ny: usize, nz: usize) -> io::Result<VoxelGrid> {
+        let bytes = fs::read(path)?;
+        if bytes.len() != 4 * nx * ny * nz {
+            return Err(invalid_data(&format!(
+                "expected {} bytes for a {}x{}x{} grid, found {}",
+                4 * nx * ny * nz,
+                nx,
+                ny,
+                nz,
+                bytes.len()
+            )));
+        }
+
+        let values = (0..bytes.len()).step_by(4).map(|i| read_f32(&bytes, i));
+        VoxelGrid::new(nx, ny, nz, values.collect()).ok_or_else(|| invalid_data("empty grid"))
+    }
+
+    // Mitsuba's `.vol` grid format (version 3) with 32 bit float or 8 bit samples. Only the first channel
+    // is used and the bounding box in the header is ignored, as the scene places the grid.
+    pub fn load_vol(path: &Path) -> io::Result<VoxelGrid> {
+        const HEADER_SIZE: usize = 48;
+
+        let bytes = fs::read(path)?;
+        if bytes.len() < HEADER_SIZE || &bytes[0..3] != b"VOL" || bytes[3] != 3 {
+            return Err(invalid_data("not a version 3 .vol file"));
+        }
+
+        let encoding = read_i32(&bytes, 4);
+        let dimension = |offset| read_i32(&bytes, offset).max(0) as usize;
+        let (nx, ny, nz) = (dimension(8), dimension(12), dimension(16));
+        let channels = dimension(20);
+
+        let sample_size = match encoding {
+            1 => 4,
+            3 => 1,
+            _ => return Err(invalid_data("unsupported .vol encoding")),
+        };
+        let count = nx * ny * nz;
+        if channels == 0 || bytes.len() < HEADER_SIZE + count * channels * sample_size {
+            return Err(invalid_data("truncated .vol file"));
+        }
+
+        let values = (0..count).map(|i| {
+            let offset = HEADER_SIZE + i * channels * sample_size;
+            if sample_size == 4 {
+                read_f32(&bytes, offset)
+            } else {
+                bytes[offset] as f32 / 255.0
+            }
+        });
+        VoxelGrid::new(nx, ny, nz, values.collect()).ok_or_else(|| invalid_data("empty grid"))
+    }
+
+    pub fn max(&self) -> f32 {
+        self.max
+    }
+
+    fn voxel(&self, x: i64, y: i64, z: i64) -> f32 {
+        let clamp = |i: i64, size: usize| i.max(0).min(size as i64 - 1) as usize;
+        let (x, y, z) = (clamp(x, self.nx), clamp(y, self.ny), clamp(z, self.nz));
+        self.values[x + self.nx * (y + self.ny * z)]
+    }
+
+    // Trilinear lookup at `p` in [0, 1]^3 with voxel centers at half integer coordinates. The grid is empty
+    // outside of the unit cube.
+    pub fn value(&self, p: &Vec3) -> f64 {
+        if p.x < 0.0 || p.x > 1.0 || p.y < 0.0 || p.y > 1.0 || p.z < 0.0 || p.z > 1.0 {
+            return 0.0;
+        }
+
+        let x = p.x * self.nx as f64 - 0.5;
+        let y = p.y * self.ny as f64 - 0.5;
+        let z = p.z * self.nz as f64 - 0.5;
+        let (x0, y0, z0) = (x.floor(), y.floor(), z.floor());
+        let (fx, fy, fz) = (x - x0, y - y0, z - z0);
+        let (x0, y0, z0) = (x0 as i64, y0 as i64, z0 as i64);
+
+        let mut sum = 0.0;
+        for dz in 0..2 {
+            for dy in 0..2 {
+                for dx in 0..2 {
+                    let weight = (if dx == 0 { 1.0 - fx } else { fx })
+                        * (if dy == 0 { 1.0 - fy } else { fy })
+                        * (if dz == 0 { 1.0 - fz } else { fz });
+                    sum += weight * self.voxel(x0 + dx, y0 + dy, z0 + dz) as f64;
+                }
+            }
+        }
+
+        sum
+    }
+
+    // Lookup at a world space point of a grid stretched over `bounds`.
+    pub fn value_in(&self, bounds: &AABB, p: &Vec3) -> f64 {
+        let e = bounds.extent();
+        let local = *p - bounds.min;
+        self.value(&Vec3::new(local.x / e.x, local.y / e.y, local.z / e.z))
+    }
+}
+
+// A heterogeneous volume whose density is a voxel grid stretched over `bounds` and scaled by `density`.
+// Free-flight distances are sampled with delta tracking and shadow rays estimate the transmittance with
+// ratio tracking, both against the maximum density of the grid as the majorant.
+#[derive(Clone)]
+pub struct GridMedium {
+    pub grid: Arc<VoxelGrid>,
+    pub bounds: AABB,
+    pub density: f64,
+    pub phase_function: Material,
+}
+
+impl GridMedium {
+    pub fn new(
+        grid: Arc<VoxelGrid>,
+        bounds: AABB,
+        density: f64,
+        phase_function: Material,
+    ) -> GridMedium {
+        GridMedium {
+            grid,
+            bounds,
+            density,
+            phase_function,
+        }
+    }
+
+    fn density_at(&self, p: &Vec3) -> f64 {
+        self.density * self.grid.value_in(&self.bounds, p)
+    }
+
+    // Takes exponentially distributed steps against the majorant, calling `collide` with the ratio of the
+    // local density to the majorant at every tentative collision until it returns false, which stops at that
+    // distance, or the ray leaves the volume.
+    fn track(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rng: &mut dyn RngCore,
+        mut collide: impl FnMut(f64, &mut dyn RngCore) -> bool,
+    ) -> Option<f64> {
+        let majorant = self.density * self.grid.max() as f64;
+        if majorant <= 0.0 {
+            return None;
+        }
+
+        let (t0, t1) = self.bounds.clip(ray, t_min.max(0.0), t_max)?;
+        let step = 1.0 / (majorant * ray.direction.magnitude());
+
+        let mut t = t0;
+        loop {
+            t -= (1.0 - rng.gen::<f64>()).ln() * step;
+            if t >= t1 {
+                return None;
+            }
+
+            let density = self.density_at(&ray.get_point_along(t));
+            if !collide(density / majorant, rng) {
+                return Some(t);
+            }
+        }
+    }
+}
+
+impl Intersectable for GridMedium {
+    fn intersect(&self, _ray: &Ray, _t_min: f64, _t_max: f64) -> Option<Intersection<'_>> {
+        None
+    }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<AABB> {
+        Some(self.bounds)
+    }
+
+    // Delta tracking: a tentative collision is real with probability density / majorant, and null
+    // collisions continue along the ray.
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rng: &mut dyn RngCore,
+    ) -> Option<Intersection<'_>> {
+        let t = self.track(ray, t_min, t_max, rng, |real, rng| rng.gen::<f64>() >= real)?;
+
+        Some(Intersection {
+            distance: t,
+            position: ray.get_point_along(t),
+            normal: Vec3::new(1.0, 0.0, 0.0),
+            u: 0.0,
+            v: 0.0,
+            material: &self.phase_function,
+        })
+    }
+
+    // Ratio tracking: every tentative collision multiplies the transmittance by the chance of it being a
+    // null collision.
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64, rng: &mut dyn RngCore) -> f64 {
+        let mut transmittance = 1.0;
+        self.track(ray, t_min, t_max, rng, |real, _| {
+            transmittance *= 1.0 - real;
+            transmittance > 0.0
+        });
+
+        transmittance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::math::{Matrix4, Transform};
+    use crate::objects::{Instance, Object};
+    use crate::renderer::{Isotropic, Texture};
+    use crate::scene::Scene;
+
+    const DENSITY: f64 = 2.0;
+
+    // The grid's left half holds 0.5 and its right half 1.0, so rays through the left half see a density
+    // of half the majorant.
+    fn medium() -> GridMedium {
+        let grid = VoxelGrid::new(2, 1, 1, vec![0.5, 1.0]).unwrap();
+        let bounds = AABB::from_min_max(Vec3::new(0.0, 0.0, 0.0), Vec3::new(2.0, 1.0, 1.0));
+        let phase_function = Material::Isotropic(Isotropic {
+            albedo: Texture::Constant(Color::new(0.5, 0.5, 0.5, 1.0)),
+        });
+
+        GridMedium::new(Arc::new(grid), bounds, DENSITY, phase_function)
+    }
+
+    fn average(runs: usize, mut estimate: impl FnMut() -> f64) -> f64 {
+        (0..runs).map(|_| estimate()).sum::<f64>() / runs as f64
+    }
+
+    #[test]
+    fn lookups_interpolate_between_voxel_centers() {
+        let grid = VoxelGrid::new(2, 1, 1, vec![0.5, 1.0]).unwrap();
+        assert_eq!(grid.max(), 1.0);
+        assert!((grid.value(&Vec3::new(0.25, 0.5, 0.5)) - 0.5).abs() < 1e-12);
+        assert!((grid.value(&Vec3::new(0.5, 0.5, 0.5)) - 0.75).abs() < 1e-12);
+        assert!((grid.value(&Vec3::new(0.9, 0.1, 0.9)) - 1.0).abs() < 1e-12);
+        assert_eq!(grid.value(&Vec3::new(1.1, 0.5, 0.5)), 0.0);
+
+        assert!(VoxelGrid::new(2, 1, 1, vec![0.5]).is_none());
+    }
+
+    #[test]
+    fn tracking_matches_the_optical_depth() {
+        let medium = medium();
+        let mut rng = StdRng::seed_from_u64(1);
+        let ray = Ray::new(Vec3::new(0.2, 0.5, -1.0), Vec3::new(0.0, 0.0, 2.0));
+        let expected = (-DENSITY * 0.5).exp();
+
+        assert!(medium.intersect(&ray, 0.001, f64::INFINITY).is_none());
+
+        let transmittance = average(20_000, || {
+            medium.transmittance(&ray, 0.001, f64::INFINITY, &mut rng)
+        });
+        assert!((transmittance - expected).abs() < 0.01, "{}", transmittance);
+
+        let passed = average(20_000, || {
+            medium.hit(&ray, 0.001, f64::INFINITY, &mut rng).is_none() as usize as f64
+        });
+        assert!((passed - expected).abs() < 0.01, "{}", passed);
+    }
+
+    #[test]
+    fn instanced_grids_are_traversed() {
+        let instance = Instance::new(
+            Arc::new(Object::GridMedium(medium())),
+            Transform::new(Matrix4::translation(&Vec3::new(0.0, 0.0, 2.0))).unwrap(),
+        );
+        let scene = Scene::create_with_bvh(&[Object::Instance(instance)], 8);
+        let mut rng = StdRng::seed_from_u64(2);
+        let ray = Ray::new(Vec3::new(0.2, 0.5, -1.0), Vec3::new(0.0, 0.0, 1.0));
+        let expected = (-DENSITY * 0.5).exp();
+
+        let transmittance = average(20_000, || {
+            scene.transmittance(&ray, 0.001, f64::INFINITY, &mut rng)
+        });
+        assert!((transmittance - expected).abs() < 0.01, "{}", transmittance);
+
+        let passed = average(20_000, || {
+            scene.hit(&ray, 0.001, f64::INFINITY, &mut rng).is_none() as usize as f64
+        });
+        assert!((passed - expected).abs() < 0.01, "{}", passed);
+    }
+
+    #[test]
+    fn raw_grids_need_the_right_size() {
+        let path = std::env::temp_dir().join(format!("grid-{}.raw", std::process::id()));
+        let bytes: Vec<u8> = [0.25f32, 0.5, 0.75, 1.0]
+            .iter()
+            .flat_map(|value| value.to_le_bytes().to_vec())
+            .collect();
+        fs::write(&path, &bytes).unwrap();
+
+        let grid = VoxelGrid::load_raw(&path, 2, 2, 1).unwrap();
+        assert_eq!(grid.values, vec![0.25, 0.5, 0.75, 1.0]);
+        let error = VoxelGrid::load_raw(&path, 2, 2, 2).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,49 @@
+use super::{Intersectable, Intersection, Object};
+use crate::math::{Ray, AABB};
+use crate::scene::Bvh;
+use rand::RngCore;
+
+// A set of objects with its own BVH, so it can be instanced as a single primitive. Every object needs a
+// bounding box. Emitters inside a group are not sampled as lights.
+pub struct Group {
+    objects: Vec<Object>,
+    bvh: Bvh,
+}
+
+impl Group {
+    pub fn new(objects: Vec<Object>) -> Group {
+        let mut objects = objects;
+        let bvh = Bvh::build(&mut objects, 0.0, 1.0);
+
+        Group { objects, bvh }
+    }
+
+    pub fn objects(&self) -> &[Object] {
+        &self.objects
+    }
+}
+
+impl Intersectable for Group {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Intersection<'_>> {
+        self.bvh.intersect(&self.objects, ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<AABB> {
+        self.bvh.bounds()
+    }
+
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rng: &mut dyn RngCore,
+    ) -> Option<Intersection<'_>> {
+        self.bvh.hit(&self.objects, ray, t_min, t_max, rng)
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64, rng: &mut dyn RngCore) -> f64 {
+        self.bvh
+            .transmittance(&self.objects, ray, t_min, t_max, rng)
+    }
+}
@@ -0,0 +1,109 @@
+use super::{Intersectable, Intersection, Object};
+use crate::math::{Ray, Transform, Vec3, AABB};
+use rand::RngCore;
+use std::sync::Arc;
+
+// Places a shared object in the scene through an affine transform. Rays are moved into the object's space
+// instead of moving the object, so any number of instances can reference the same geometry.
+#[derive(Clone)]
+pub struct Instance {
+    pub object: Arc<Object>,
+    pub transform: Transform,
+}
+
+impl Instance {
+    pub fn new(object: Arc<Object>, transform: Transform) -> Instance {
+        Instance { object, transform }
+    }
+
+    // The direction isn't normalized, so distances along the ray are the same in both spaces.
+    fn object_ray(&self, ray: &Ray) -> Ray {
+        Ray::at_time(
+            self.transform.inverse_point(&ray.origin),
+            self.transform.inverse_vector(&ray.direction),
+            ray.time,
+        )
+    }
+
+    fn world_hit<'a>(&self, hit: Intersection<'a>) -> Intersection<'a> {
+        Intersection {
+            position: self.transform.point(&hit.position),
+            normal: self.transform.normal(&hit.normal).normalize(),
+            ..hit
+        }
+    }
+}
+
+impl Intersectable for Instance {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Intersection<'_>> {
+        let hit = self.object.intersect(&self.object_ray(ray), t_min, t_max)?;
+        Some(self.world_hit(hit))
+    }
+
+    fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB> {
+        let bounds = self.object.bounding_box(t0, t1)?;
+
+        let corners = (0..8).map(|i| {
+            Vec3::new(
+                if i & 1 == 0 {
+                    bounds.min.x
+                } else {
+                    bounds.max.x
+                },
+                if i & 2 == 0 {
+                    bounds.min.y
+                } else {
+                    bounds.max.y
+                },
+                if i & 4 == 0 {
+                    bounds.min.z
+                } else {
+                    bounds.max.z
+                },
+            )
+        });
+
+        let first = self.transform.point(&bounds.min);
+        Some(corners.fold(AABB::from_min_max(first, first), |b, corner| {
+            b.grow(&self.transform.point(&corner))
+        }))
+    }
+
+    fn random_direction(&self, origin: &Vec3, time: f64, rng: &mut dyn RngCore) -> Option<Vec3> {
+        let origin = self.transform.inverse_point(origin);
+        let direction = self.object.random_direction(&origin, time, rng)?;
+
+        Some(self.transform.vector(&direction))
+    }
+
+    // The object samples directions in its own space. Mapping a unit direction `w` through the linear part
+    // `L` of the inverse transform and normalizing it changes solid angles by `|det L| / |L w|^3`.
+    fn pdf_value(&self, ray: &Ray) -> f64 {
+        let direction = self.transform.inverse_vector(&ray.direction.normalize());
+        let object_ray = Ray::at_time(
+            self.transform.inverse_point(&ray.origin),
+            direction,
+            ray.time,
+        );
+
+        let pdf = self.object.pdf_value(&object_ray);
+        let length = direction.magnitude();
+        pdf * self.transform.inverse.linear_determinant().abs() / (length * length * length)
+    }
+
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rng: &mut dyn RngCore,
+    ) -> Option<Intersection<'_>> {
+        let hit = self.object.hit(&self.object_ray(ray), t_min, t_max, rng)?;
+        Some(self.world_hit(hit))
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64, rng: &mut dyn RngCore) -> f64 {
+        self.object
+            .transmittance(&self.object_ray(ray), t_min, t_max, rng)
+    }
+}
@@ -1,14 +1,45 @@
 use crate::math::{Ray, Vec3, AABB};
 use crate::renderer::Material;
+use rand::RngCore;
 
-pub struct Intersection {
+pub struct Intersection<'a> {
     pub distance: f64,
     pub position: Vec3,
     pub normal: Vec3,
-    pub material: Material,
+    pub u: f64,
+    pub v: f64,
+    pub material: &'a Material,
 }
 
 pub trait Intersectable {
-    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Intersection>;
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Intersection<'_>>;
     fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB>;
+
+    // Light sampling: pick a direction from `origin` towards the surface, and report the solid angle pdf of
+    // `ray` hitting it. Shapes that can't be sampled as lights keep the defaults.
+    fn random_direction(&self, _origin: &Vec3, _time: f64, _rng: &mut dyn RngCore) -> Option<Vec3> {
+        None
+    }
+
+    fn pdf_value(&self, _ray: &Ray) -> f64 {
+        0.0
+    }
+
+    // Volumes are invisible to `intersect`, since where a ray scatters inside one is picked with the path's
+    // random numbers. `hit` is the closest hit of a path including those scattering points, and
+    // `transmittance` the fraction of light passing between `t_min` and `t_max` without being scattered or
+    // absorbed, which shadow rays are attenuated by.
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        _rng: &mut dyn RngCore,
+    ) -> Option<Intersection<'_>> {
+        self.intersect(ray, t_min, t_max)
+    }
+
+    fn transmittance(&self, _ray: &Ray, _t_min: f64, _t_max: f64, _rng: &mut dyn RngCore) -> f64 {
+        1.0
+    }
 }
@@ -1,28 +1,70 @@
+mod constant_medium;
+mod grid_medium;
+mod group;
+mod instance;
 mod intersectable;
 mod moving_sphere;
+mod quad;
 mod sphere;
+mod triangle;
 
-use crate::math::{Ray, AABB};
+use crate::math::{Ray, Vec3, AABB};
+use crate::renderer::Material;
+use rand::RngCore;
+use std::sync::Arc;
 
+pub use constant_medium::ConstantMedium;
+pub use grid_medium::{GridMedium, VoxelGrid};
+pub use group::Group;
+pub use instance::Instance;
 pub use intersectable::{Intersectable, Intersection};
 pub use moving_sphere::MovingSphere;
+pub use quad::{cuboid, Quad};
 pub use sphere::Sphere;
+pub use triangle::{Triangle, TriangleMesh};
 
-use bvh::aabb::{Bounded, AABB as BVH_AABB};
-use bvh::bounding_hierarchy::BHShape;
-use bvh::nalgebra::Point3;
+// Minimum thickness of the bounding box of planar shapes.
+const PADDING: f64 = 0.0001;
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub enum Object {
     Sphere(Sphere),
     MovingSphere(MovingSphere),
+    Triangle(Triangle),
+    Quad(Quad),
+    Group(Arc<Group>),
+    Instance(Instance),
+    ConstantMedium(ConstantMedium),
+    GridMedium(GridMedium),
+}
+
+impl Object {
+    // Groups are made of several objects and have no single material.
+    pub fn material(&self) -> Option<&Material> {
+        match *self {
+            Object::Sphere(ref s) => Some(&s.material),
+            Object::MovingSphere(ref ms) => Some(&ms.material),
+            Object::Triangle(ref t) => Some(&t.mesh.material),
+            Object::Quad(ref q) => Some(&q.material),
+            Object::Group(_) => None,
+            Object::Instance(ref i) => i.object.material(),
+            Object::ConstantMedium(ref m) => Some(&m.phase_function),
+            Object::GridMedium(ref m) => Some(&m.phase_function),
+        }
+    }
 }
 
 impl Intersectable for Object {
-    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Intersection> {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Intersection<'_>> {
         match *self {
             Object::Sphere(ref s) => s.intersect(ray, t_min, t_max),
             Object::MovingSphere(ref ms) => ms.intersect(ray, t_min, t_max),
+            Object::Triangle(ref t) => t.intersect(ray, t_min, t_max),
+            Object::Quad(ref q) => q.intersect(ray, t_min, t_max),
+            Object::Group(ref g) => g.intersect(ray, t_min, t_max),
+            Object::Instance(ref i) => i.intersect(ray, t_min, t_max),
+            Object::ConstantMedium(ref m) => m.intersect(ray, t_min, t_max),
+            Object::GridMedium(ref m) => m.intersect(ray, t_min, t_max),
         }
     }
 
@@ -30,37 +72,214 @@ impl Intersectable for Object {
         match *self {
             Object::Sphere(ref s) => s.bounding_box(t0, t1),
             Object::MovingSphere(ref ms) => ms.bounding_box(t0, t1),
+            Object::Triangle(ref t) => t.bounding_box(t0, t1),
+            Object::Quad(ref q) => q.bounding_box(t0, t1),
+            Object::Group(ref g) => g.bounding_box(t0, t1),
+            Object::Instance(ref i) => i.bounding_box(t0, t1),
+            Object::ConstantMedium(ref m) => m.bounding_box(t0, t1),
+            Object::GridMedium(ref m) => m.bounding_box(t0, t1),
         }
     }
-}
-
-impl Bounded for Object {
-    fn aabb(&self) -> BVH_AABB {
-        let aabb = if let Some(bounding_box) = self.bounding_box(0.0, 1.0) {
-            bounding_box
-        } else {
-            AABB::new()
-        };
 
-        let min = Point3::new(aabb.min.x as f32, aabb.min.y as f32, aabb.min.z as f32);
-        let max = Point3::new(aabb.max.x as f32, aabb.max.y as f32, aabb.max.z as f32);
+    fn random_direction(&self, origin: &Vec3, time: f64, rng: &mut dyn RngCore) -> Option<Vec3> {
+        match *self {
+            Object::Sphere(ref s) => s.random_direction(origin, time, rng),
+            Object::MovingSphere(ref ms) => ms.random_direction(origin, time, rng),
+            Object::Triangle(ref t) => t.random_direction(origin, time, rng),
+            Object::Quad(ref q) => q.random_direction(origin, time, rng),
+            Object::Group(ref g) => g.random_direction(origin, time, rng),
+            Object::Instance(ref i) => i.random_direction(origin, time, rng),
+            Object::ConstantMedium(ref m) => m.random_direction(origin, time, rng),
+            Object::GridMedium(ref m) => m.random_direction(origin, time, rng),
+        }
+    }
 
-        BVH_AABB::with_bounds(min, max)
+    fn pdf_value(&self, ray: &Ray) -> f64 {
+        match *self {
+            Object::Sphere(ref s) => s.pdf_value(ray),
+            Object::MovingSphere(ref ms) => ms.pdf_value(ray),
+            Object::Triangle(ref t) => t.pdf_value(ray),
+            Object::Quad(ref q) => q.pdf_value(ray),
+            Object::Group(ref g) => g.pdf_value(ray),
+            Object::Instance(ref i) => i.pdf_value(ray),
+            Object::ConstantMedium(ref m) => m.pdf_value(ray),
+            Object::GridMedium(ref m) => m.pdf_value(ray),
+        }
     }
-}
 
-impl BHShape for Object {
-    fn set_bh_node_index(&mut self, index: usize) {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rng: &mut dyn RngCore,
+    ) -> Option<Intersection<'_>> {
         match *self {
-            Object::Sphere(mut s) => s.node_index = index,
-            Object::MovingSphere(mut ms) => ms.node_index = index,
+            Object::Sphere(ref s) => s.hit(ray, t_min, t_max, rng),
+            Object::MovingSphere(ref ms) => ms.hit(ray, t_min, t_max, rng),
+            Object::Triangle(ref t) => t.hit(ray, t_min, t_max, rng),
+            Object::Quad(ref q) => q.hit(ray, t_min, t_max, rng),
+            Object::Group(ref g) => g.hit(ray, t_min, t_max, rng),
+            Object::Instance(ref i) => i.hit(ray, t_min, t_max, rng),
+            Object::ConstantMedium(ref m) => m.hit(ray, t_min, t_max, rng),
+            Object::GridMedium(ref m) => m.hit(ray, t_min, t_max, rng),
         }
     }
 
-    fn bh_node_index(&self) -> usize {
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64, rng: &mut dyn RngCore) -> f64 {
         match *self {
-            Object::Sphere(ref s) => s.node_index,
-            Object::MovingSphere(ref ms) => ms.node_index,
+            Object::Sphere(ref s) => s.transmittance(ray, t_min, t_max, rng),
+            Object::MovingSphere(ref ms) => ms.transmittance(ray, t_min, t_max, rng),
+            Object::Triangle(ref t) => t.transmittance(ray, t_min, t_max, rng),
+            Object::Quad(ref q) => q.transmittance(ray, t_min, t_max, rng),
+            Object::Group(ref g) => g.transmittance(ray, t_min, t_max, rng),
+            Object::Instance(ref i) => i.transmittance(ray, t_min, t_max, rng),
+            Object::ConstantMedium(ref m) => m.transmittance(ray, t_min, t_max, rng),
+            Object::GridMedium(ref m) => m.transmittance(ray, t_min, t_max, rng),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::math::{Matrix4, Onb, Transform};
+    use crate::renderer::{Lambertian, Texture};
+    use rand::prelude::*;
+    use std::f64::consts::PI;
+    use std::sync::Arc;
+
+    fn material() -> Material {
+        Material::Lambertian(Lambertian {
+            albedo: Texture::Constant(Color::new(0.5, 0.5, 0.5, 1.0)),
+        })
+    }
+
+    // Integrates the light pdf over the cone of directions around its bounding box, both in total and split
+    // into octants and rings around the light, and compares each part with the share of directions the light
+    // samples there.
+    fn assert_pdf_matches_sampling(object: &Object, origin: Vec3, seed: u64) {
+        const SAMPLES: usize = 400_000;
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        let bounds = object.bounding_box(0.0, 1.0).unwrap();
+        let axis = ((bounds.min + bounds.max) / 2.0 - origin).normalize();
+        let widest = (0..8)
+            .map(|corner| {
+                let pick =
+                    |bit: usize, min: f64, max: f64| if corner & bit == 0 { min } else { max };
+                let corner = Vec3::new(
+                    pick(1, bounds.min.x, bounds.max.x),
+                    pick(2, bounds.min.y, bounds.max.y),
+                    pick(4, bounds.min.z, bounds.max.z),
+                );
+                1.0 - Vec3::dot(&(corner - origin).normalize(), &axis)
+            })
+            .fold(0.0, f64::max);
+        // Directions past a right angle would no longer bound the box by its corners.
+        assert!(widest < 1.0);
+
+        let bin = |direction: Vec3| {
+            let direction = direction.normalize();
+            let offset = direction - axis;
+            let octant = (offset.x > 0.0) as usize
+                + 2 * (offset.y > 0.0) as usize
+                + 4 * (offset.z > 0.0) as usize;
+            let ring = ((1.0 - Vec3::dot(&direction, &axis)) / widest * 4.0).min(3.0) as usize;
+            octant * 4 + ring
+        };
+
+        let onb = Onb::from_w(&axis);
+        let cone = 2.0 * PI * widest;
+        let mut integral = [0.0; 32];
+        for _ in 0..SAMPLES {
+            let z = 1.0 - rng.gen::<f64>() * widest;
+            let phi = 2.0 * PI * rng.gen::<f64>();
+            let r = (1.0 - z * z).sqrt();
+            let direction = onb.local(r * phi.cos(), r * phi.sin(), z);
+            let pdf = object.pdf_value(&Ray::new(origin, direction));
+            integral[bin(direction)] += cone * pdf / SAMPLES as f64;
+        }
+
+        let mut sampled = [0.0; 32];
+        for _ in 0..SAMPLES {
+            let direction = object.random_direction(&origin, 0.0, &mut rng).unwrap();
+            assert!(object.pdf_value(&Ray::new(origin, direction)) > 0.0);
+            sampled[bin(direction)] += 1.0 / SAMPLES as f64;
+        }
+
+        let total: f64 = integral.iter().sum();
+        assert!((total - 1.0).abs() < 0.02, "pdf integrates to {}", total);
+        for (integral, sampled) in integral.iter().zip(&sampled) {
+            assert!(
+                (integral - sampled).abs() < 0.01,
+                "bin integrates to {} but is sampled {}",
+                integral,
+                sampled
+            );
+        }
+    }
+
+    #[test]
+    fn sphere_pdf_matches_sampling() {
+        let sphere = Object::Sphere(Sphere {
+            center: Vec3::new(1.5, 1.0, 2.0),
+            radius: 1.2,
+            material: material(),
+        });
+        assert_pdf_matches_sampling(&sphere, Vec3::new(0.0, 0.0, 0.0), 1);
+    }
+
+    #[test]
+    fn triangle_pdf_matches_sampling() {
+        let positions = vec![
+            Vec3::new(2.0, -1.0, 1.0),
+            Vec3::new(-1.0, 2.0, 1.2),
+            Vec3::new(-1.0, -1.0, 1.5),
+        ];
+        let mesh = TriangleMesh::new(positions, vec![[0, 1, 2]], material());
+        let triangle = mesh.into_objects().remove(0);
+        assert_pdf_matches_sampling(&triangle, Vec3::new(0.2, 0.1, -0.3), 2);
+    }
+
+    #[test]
+    fn quad_pdf_matches_sampling() {
+        let quad = Object::Quad(Quad::new(
+            Vec3::new(-1.0, -0.5, 1.0),
+            Vec3::new(2.0, 0.5, 0.5),
+            Vec3::new(0.3, 1.5, -0.2),
+            material(),
+        ));
+        assert_pdf_matches_sampling(&quad, Vec3::new(0.1, 0.0, -0.5), 3);
+    }
+
+    // The pdf of an instance is the object's pdf scaled by the Jacobian of the change of direction, which
+    // only comes out right when non-uniform scales and rotations are accounted for.
+    #[test]
+    fn instanced_pdf_matches_sampling() {
+        let transform = Transform::new(
+            Matrix4::translation(&Vec3::new(0.5, 1.0, 2.0))
+                * Matrix4::rotation(&Vec3::new(1.0, 1.0, 0.0), 30.0)
+                * Matrix4::scaling(&Vec3::new(1.5, 0.5, 1.0)),
+        )
+        .unwrap();
+
+        let sphere = Object::Sphere(Sphere {
+            center: Vec3::new(0.0, 0.0, 0.0),
+            radius: 1.0,
+            material: material(),
+        });
+        let instance = Object::Instance(Instance::new(Arc::new(sphere), transform));
+        assert_pdf_matches_sampling(&instance, Vec3::new(0.0, -0.5, -0.5), 4);
+
+        let quad = Object::Quad(Quad::new(
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            material(),
+        ));
+        let instance = Object::Instance(Instance::new(Arc::new(quad), transform));
+        assert_pdf_matches_sampling(&instance, Vec3::new(0.0, -0.5, -0.5), 5);
+    }
+}
@@ -1,8 +1,10 @@
+use super::sphere::{sample_sphere_cone, sphere_cone_pdf, sphere_uv};
 use super::{Intersectable, Intersection};
 use crate::math::{Ray, Vec3, AABB};
 use crate::renderer::Material;
+use rand::RngCore;
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct MovingSphere {
     pub center0: Vec3,
     pub center1: Vec3,
@@ -10,7 +12,6 @@ pub struct MovingSphere {
     pub time1: f64,
     pub radius: f64,
     pub material: Material,
-    pub node_index: usize,
 }
 
 impl MovingSphere {
@@ -25,7 +26,7 @@ impl MovingSphere {
 }
 
 impl Intersectable for MovingSphere {
-    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Intersection> {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Intersection<'_>> {
         let direction = ray.origin - self.center(ray.time);
         let a = Vec3::dot(&ray.direction, &ray.direction);
         let b = Vec3::dot(&direction, &ray.direction);
@@ -37,22 +38,30 @@ impl Intersectable for MovingSphere {
 
             if t < t_max && t > t_min {
                 let p = ray.get_point_along(t);
+                let normal = (p - self.center(ray.time)) / self.radius;
+                let (u, v) = sphere_uv(&normal);
                 return Some(Intersection {
                     distance: t,
                     position: p,
-                    normal: (p - self.center(ray.time)) / self.radius,
-                    material: self.material,
+                    normal,
+                    u,
+                    v,
+                    material: &self.material,
                 });
             }
 
             let t = (-b + discriminant.sqrt()) / a;
             if t < t_max && t > t_min {
                 let p = ray.get_point_along(t);
+                let normal = (p - self.center(ray.time)) / self.radius;
+                let (u, v) = sphere_uv(&normal);
                 return Some(Intersection {
                     distance: t,
                     position: p,
-                    normal: (p - self.center(ray.time)) / self.radius,
-                    material: self.material,
+                    normal,
+                    u,
+                    v,
+                    material: &self.material,
                 });
             }
         }
@@ -73,4 +82,16 @@ impl Intersectable for MovingSphere {
 
         Some(AABB::combine(&box0, &box1))
     }
+
+    fn random_direction(&self, origin: &Vec3, time: f64, rng: &mut dyn RngCore) -> Option<Vec3> {
+        sample_sphere_cone(origin, &self.center(time), self.radius, rng)
+    }
+
+    fn pdf_value(&self, ray: &Ray) -> f64 {
+        if self.intersect(ray, 0.001, f64::INFINITY).is_none() {
+            return 0.0;
+        }
+
+        sphere_cone_pdf(&ray.origin, &self.center(ray.time), self.radius)
+    }
 }
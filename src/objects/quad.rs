@@ -0,0 +1,279 @@
+use super::{Intersectable, Intersection, Object, PADDING};
+use crate::math::{Ray, Vec3, AABB};
+use crate::renderer::Material;
+use rand::prelude::*;
+
+// A parallelogram spanned by the edges `u` and `v` from the corner `q`. The normal is `u x v`.
+#[derive(Clone)]
+pub struct Quad {
+    pub q: Vec3,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub material: Material,
+
+    normal: Vec3,
+    d: f64,
+    w: Vec3,
+    area: f64,
+}
+
+impl Quad {
+    pub fn new(q: Vec3, u: Vec3, v: Vec3, material: Material) -> Quad {
+        let n = Vec3::cross(&u, &v);
+        let normal = n.normalize();
+
+        Quad {
+            q,
+            u,
+            v,
+            material,
+            normal,
+            d: Vec3::dot(&normal, &q),
+            w: n / Vec3::dot(&n, &n),
+            area: n.magnitude(),
+        }
+    }
+
+    // An axis-aligned rectangle between two opposite corners, which must share exactly one coordinate. The
+    // normal points along the positive direction of that axis.
+    pub fn rect(a: Vec3, b: Vec3, material: Material) -> Option<Quad> {
+        let axis = (0..3).find(|axis| a[*axis] == b[*axis])?;
+        let (i, j) = ((axis + 1) % 3, (axis + 2) % 3);
+        if a[i] == b[i] || a[j] == b[j] {
+            return None;
+        }
+
+        let min = Vec3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+        let extent = Vec3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)) - min;
+        let edge = |k: usize| {
+            let mut e = [0.0; 3];
+            e[k] = extent[k];
+            Vec3::new(e[0], e[1], e[2])
+        };
+
+        Some(Quad::new(min, edge(i), edge(j), material))
+    }
+}
+
+// The six faces of the box between two opposite corners, with their normals pointing out.
+pub fn cuboid(a: Vec3, b: Vec3, material: Material) -> Vec<Object> {
+    let min = Vec3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+    let max = Vec3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
+
+    let dx = Vec3::new(max.x - min.x, 0.0, 0.0);
+    let dy = Vec3::new(0.0, max.y - min.y, 0.0);
+    let dz = Vec3::new(0.0, 0.0, max.z - min.z);
+
+    vec![
+        Quad::new(Vec3::new(min.x, min.y, max.z), dx, dy, material.clone()),
+        Quad::new(Vec3::new(max.x, min.y, max.z), -dz, dy, material.clone()),
+        Quad::new(Vec3::new(max.x, min.y, min.z), -dx, dy, material.clone()),
+        Quad::new(Vec3::new(min.x, min.y, min.z), dz, dy, material.clone()),
+        Quad::new(Vec3::new(min.x, max.y, max.z), dx, -dz, material.clone()),
+        Quad::new(Vec3::new(min.x, min.y, min.z), dx, dz, material),
+    ]
+    .into_iter()
+    .map(Object::Quad)
+    .collect()
+}
+
+impl Intersectable for Quad {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Intersection<'_>> {
+        let denominator = Vec3::dot(&self.normal, &ray.direction);
+        if denominator.abs() < 1e-12 {
+            return None;
+        }
+
+        let t = (self.d - Vec3::dot(&self.normal, &ray.origin)) / denominator;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+
+        // Coordinates of the hit along the two edges; both are in [0, 1] inside the quad.
+        let p = ray.get_point_along(t);
+        let planar = p - self.q;
+        let alpha = Vec3::dot(&self.w, &Vec3::cross(&planar, &self.v));
+        let beta = Vec3::dot(&self.w, &Vec3::cross(&self.u, &planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+
+        Some(Intersection {
+            distance: t,
+            position: p,
+            normal: self.normal,
+            u: alpha,
+            v: beta,
+            material: &self.material,
+        })
+    }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<AABB> {
+        let diagonal0 = AABB::from_min_max(self.q, self.q).grow(&(self.q + self.u + self.v));
+        let diagonal1 =
+            AABB::from_min_max(self.q + self.u, self.q + self.u).grow(&(self.q + self.v));
+
+        Some(AABB::combine(&diagonal0, &diagonal1).pad(PADDING))
+    }
+
+    fn random_direction(&self, origin: &Vec3, _time: f64, rng: &mut dyn RngCore) -> Option<Vec3> {
+        let point = self.q + rng.gen::<f64>() * self.u + rng.gen::<f64>() * self.v;
+        Some(point - *origin)
+    }
+
+    // Converts the uniform area density of the quad into a solid angle density as seen from the ray.
+    fn pdf_value(&self, ray: &Ray) -> f64 {
+        let hit = match self.intersect(ray, 0.001, f64::INFINITY) {
+            Some(hit) => hit,
+            None => return 0.0,
+        };
+
+        let distance2 = hit.distance * hit.distance * ray.direction.sqr_magnitude();
+        let cosine = (Vec3::dot(&self.normal, &ray.direction) / ray.direction.magnitude()).abs();
+        if cosine <= 0.0 {
+            return 0.0;
+        }
+
+        distance2 / (cosine * self.area)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::renderer::{Lambertian, Texture};
+
+    fn material() -> Material {
+        Material::Lambertian(Lambertian {
+            albedo: Texture::Constant(Color::new(0.5, 0.5, 0.5, 1.0)),
+        })
+    }
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).magnitude() < 1e-9
+    }
+
+    fn contains(bounds: &AABB, point: Vec3) -> bool {
+        (0..3).all(|axis| bounds.min[axis] <= point[axis] && point[axis] <= bounds.max[axis])
+    }
+
+    #[test]
+    fn quads_hit_inside_their_edges() {
+        let quad = Quad::new(
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.5, 1.0, 0.0),
+            material(),
+        );
+
+        let ray = Ray::new(Vec3::new(2.5, 0.5, 3.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = quad.intersect(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((hit.distance - 3.0).abs() < 1e-9);
+        assert!(close(hit.normal, Vec3::new(0.0, 0.0, 1.0)));
+        assert!((hit.u - 0.625).abs() < 1e-9 && (hit.v - 0.5).abs() < 1e-9);
+
+        assert!(quad.intersect(&ray, 0.001, 2.5).is_none());
+
+        // Outside the parallelogram, though inside its bounding rectangle.
+        let outside = Ray::new(Vec3::new(1.1, 0.9, 3.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(quad.intersect(&outside, 0.001, f64::INFINITY).is_none());
+
+        let parallel = Ray::new(Vec3::new(0.0, 0.5, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(quad.intersect(&parallel, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn bounds_cover_every_corner() {
+        let quad = Quad::new(
+            Vec3::new(1.0, -1.0, 0.5),
+            Vec3::new(1.0, 2.0, 0.0),
+            Vec3::new(-0.5, 0.0, 3.0),
+            material(),
+        );
+        let bounds = quad.bounding_box(0.0, 1.0).unwrap();
+        for corner in &[
+            quad.q,
+            quad.q + quad.u,
+            quad.q + quad.v,
+            quad.q + quad.u + quad.v,
+        ] {
+            assert!(contains(&bounds, *corner));
+        }
+
+        // Axis-aligned quads are flat, which the padding keeps from confusing the box tests.
+        let flat = Quad::rect(
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            material(),
+        )
+        .unwrap()
+        .bounding_box(0.0, 1.0)
+        .unwrap();
+        assert!(flat.max.z - flat.min.z > 0.0);
+    }
+
+    #[test]
+    fn rects_face_along_their_shared_axis() {
+        let rect = Quad::rect(
+            Vec3::new(2.0, 1.0, 3.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            material(),
+        )
+        .unwrap();
+        assert!(close(rect.normal, Vec3::new(0.0, 1.0, 0.0)));
+        assert!(close(rect.q, Vec3::new(0.0, 1.0, 0.0)));
+
+        let ray = Ray::new(Vec3::new(0.5, 4.0, 2.4), Vec3::new(0.0, -1.0, 0.0));
+        let hit = rect.intersect(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((hit.distance - 3.0).abs() < 1e-9);
+        assert!((0.0..=1.0).contains(&hit.u) && (0.0..=1.0).contains(&hit.v));
+        assert!(close(hit.position, Vec3::new(0.5, 1.0, 2.4)));
+
+        let corners = |a: (f64, f64, f64), b: (f64, f64, f64)| {
+            Quad::rect(
+                Vec3::new(a.0, a.1, a.2),
+                Vec3::new(b.0, b.1, b.2),
+                material(),
+            )
+        };
+        assert!(corners((0.0, 0.0, 0.0), (1.0, 1.0, 1.0)).is_none());
+        assert!(corners((0.0, 0.0, 0.0), (1.0, 0.0, 0.0)).is_none());
+        assert!(corners((0.0, 0.0, 0.0), (0.0, 1.0, 1.0)).is_some());
+    }
+
+    #[test]
+    fn boxes_are_closed_with_outward_normals() {
+        let (min, max) = (Vec3::new(-1.0, 0.0, 2.0), Vec3::new(1.0, 3.0, 4.0));
+        let faces = cuboid(max, min, material());
+        assert_eq!(faces.len(), 6);
+
+        let bounds = faces
+            .iter()
+            .map(|face| face.bounding_box(0.0, 1.0).unwrap())
+            .fold(AABB::from_min_max(min, min), |a, b| AABB::combine(&a, &b));
+        assert!(close(bounds.min, min - Vec3::new_xyz(0.5 * PADDING)));
+        assert!(close(bounds.max, max + Vec3::new_xyz(0.5 * PADDING)));
+
+        let center = (min + max) / 2.0;
+        for axis in 0..3 {
+            for &sign in &[-1.0, 1.0] {
+                let mut direction = [0.0; 3];
+                direction[axis] = sign;
+                let direction = Vec3::new(direction[0], direction[1], direction[2]);
+
+                // From outside the nearest face is hit, from inside the face the ray leaves through.
+                for &(origin, outward) in &[(center - 10.0 * direction, -1.0), (center, 1.0)] {
+                    let ray = Ray::new(origin, direction);
+                    let hit = faces
+                        .iter()
+                        .filter_map(|face| face.intersect(&ray, 0.001, f64::INFINITY))
+                        .min_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap())
+                        .unwrap();
+                    assert!(close(hit.normal, outward * direction));
+                    assert!(contains(&bounds, hit.position));
+                }
+            }
+        }
+    }
+}
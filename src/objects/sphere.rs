@@ -1,13 +1,59 @@
 use super::{Intersectable, Intersection};
-use crate::math::{Ray, Vec3, AABB};
+use crate::math::{Onb, Ray, Vec3, AABB};
 use crate::renderer::Material;
+use rand::prelude::*;
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct Sphere {
     pub center: Vec3,
     pub radius: f64,
     pub material: Material,
-    pub node_index: usize,
+}
+
+pub fn sphere_uv(normal: &Vec3) -> (f64, f64) {
+    let theta = (-normal.y).acos();
+    let phi = (-normal.z).atan2(normal.x) + std::f64::consts::PI;
+
+    (
+        phi / (2.0 * std::f64::consts::PI),
+        theta / std::f64::consts::PI,
+    )
+}
+
+fn cos_theta_max(origin: &Vec3, center: &Vec3, radius: f64) -> Option<f64> {
+    let distance2 = (*center - *origin).sqr_magnitude();
+    if distance2 <= radius * radius {
+        return None;
+    }
+
+    Some((1.0 - radius * radius / distance2).sqrt())
+}
+
+// Uniformly samples the cone of directions from `origin` that hit the sphere. Points inside the sphere
+// can't be sampled this way and return `None`.
+pub fn sample_sphere_cone(
+    origin: &Vec3,
+    center: &Vec3,
+    radius: f64,
+    rng: &mut dyn RngCore,
+) -> Option<Vec3> {
+    let cos_theta_max = cos_theta_max(origin, center, radius)?;
+
+    let r1: f64 = rng.gen();
+    let r2: f64 = rng.gen();
+    let z = 1.0 + r1 * (cos_theta_max - 1.0);
+    let phi = 2.0 * std::f64::consts::PI * r2;
+    let sin_theta = (1.0 - z * z).max(0.0).sqrt();
+
+    let onb = Onb::from_w(&(*center - *origin));
+    Some(onb.local(phi.cos() * sin_theta, phi.sin() * sin_theta, z))
+}
+
+pub fn sphere_cone_pdf(origin: &Vec3, center: &Vec3, radius: f64) -> f64 {
+    match cos_theta_max(origin, center, radius) {
+        Some(cos_theta_max) => 1.0 / (2.0 * std::f64::consts::PI * (1.0 - cos_theta_max)),
+        None => 0.0,
+    }
 }
 
 impl Sphere {
@@ -17,7 +63,7 @@ impl Sphere {
 }
 
 impl Intersectable for Sphere {
-    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Intersection> {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Intersection<'_>> {
         let direction = ray.origin - self.center;
         let a = Vec3::dot(&ray.direction, &ray.direction);
         let b = Vec3::dot(&direction, &ray.direction);
@@ -29,22 +75,30 @@ impl Intersectable for Sphere {
 
             if t < t_max && t > t_min {
                 let p = ray.get_point_along(t);
+                let normal = (p - self.center) / self.radius;
+                let (u, v) = sphere_uv(&normal);
                 return Some(Intersection {
                     distance: t,
                     position: p,
-                    normal: (p - self.center) / self.radius,
-                    material: self.material,
+                    normal,
+                    u,
+                    v,
+                    material: &self.material,
                 });
             }
 
             let t = (-b + discriminant.sqrt()) / a;
             if t < t_max && t > t_min {
                 let p = ray.get_point_along(t);
+                let normal = (p - self.center) / self.radius;
+                let (u, v) = sphere_uv(&normal);
                 return Some(Intersection {
                     distance: t,
                     position: p,
-                    normal: (p - self.center) / self.radius,
-                    material: self.material,
+                    normal,
+                    u,
+                    v,
+                    material: &self.material,
                 });
             }
         }
@@ -52,10 +106,22 @@ impl Intersectable for Sphere {
         None
     }
 
-    fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB> {
+    fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<AABB> {
         Some(AABB::from_min_max(
             self.center - Vec3::new_xyz(self.radius),
             self.center + Vec3::new_xyz(self.radius),
         ))
     }
+
+    fn random_direction(&self, origin: &Vec3, _time: f64, rng: &mut dyn RngCore) -> Option<Vec3> {
+        sample_sphere_cone(origin, &self.center, self.radius, rng)
+    }
+
+    fn pdf_value(&self, ray: &Ray) -> f64 {
+        if self.intersect(ray, 0.001, f64::INFINITY).is_none() {
+            return 0.0;
+        }
+
+        sphere_cone_pdf(&ray.origin, &self.center, self.radius)
+    }
 }
@@ -0,0 +1,302 @@
+use super::{Intersectable, Intersection, Object, PADDING};
+use crate::math::{Ray, Vec3, AABB};
+use crate::renderer::Material;
+use rand::prelude::*;
+use std::sync::Arc;
+
+// Vertex attributes are stored per vertex and shared by all triangles referencing them. `normals` and
+// `uvs` are either empty or the same length as `positions`.
+pub struct TriangleMesh {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f64, f64)>,
+    pub indices: Vec<[usize; 3]>,
+    pub material: Material,
+}
+
+impl TriangleMesh {
+    pub fn new(positions: Vec<Vec3>, indices: Vec<[usize; 3]>, material: Material) -> TriangleMesh {
+        TriangleMesh {
+            positions,
+            normals: vec![],
+            uvs: vec![],
+            indices,
+            material,
+        }
+    }
+
+    pub fn into_objects(self) -> Vec<Object> {
+        let mesh = Arc::new(self);
+
+        (0..mesh.indices.len())
+            .map(|index| {
+                Object::Triangle(Triangle {
+                    mesh: Arc::clone(&mesh),
+                    index,
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone)]
+pub struct Triangle {
+    pub mesh: Arc<TriangleMesh>,
+    pub index: usize,
+}
+
+impl Triangle {
+    pub fn vertices(&self) -> (Vec3, Vec3, Vec3) {
+        let [i0, i1, i2] = self.mesh.indices[self.index];
+        let positions = &self.mesh.positions;
+
+        (positions[i0], positions[i1], positions[i2])
+    }
+
+    fn interpolate_normal(&self, b0: f64, b1: f64, b2: f64, geometric_normal: Vec3) -> Vec3 {
+        if self.mesh.normals.is_empty() {
+            return geometric_normal;
+        }
+
+        let [i0, i1, i2] = self.mesh.indices[self.index];
+        let normals = &self.mesh.normals;
+        let normal = (b0 * normals[i0] + b1 * normals[i1] + b2 * normals[i2]).normalize();
+
+        // Keep the shading normal in the same hemisphere as the surface so that materials relying on the
+        // normal to tell inside from outside stay consistent.
+        if Vec3::dot(&normal, &geometric_normal) < 0.0 {
+            -normal
+        } else {
+            normal
+        }
+    }
+
+    fn interpolate_uv(&self, b0: f64, b1: f64, b2: f64) -> (f64, f64) {
+        if self.mesh.uvs.is_empty() {
+            return (b1, b2);
+        }
+
+        let [i0, i1, i2] = self.mesh.indices[self.index];
+        let uvs = &self.mesh.uvs;
+
+        (
+            b0 * uvs[i0].0 + b1 * uvs[i1].0 + b2 * uvs[i2].0,
+            b0 * uvs[i0].1 + b1 * uvs[i1].1 + b2 * uvs[i2].1,
+        )
+    }
+}
+
+fn max_dimension(v: &Vec3) -> usize {
+    let (x, y, z) = (v.x.abs(), v.y.abs(), v.z.abs());
+
+    if x > y && x > z {
+        0
+    } else if y > z {
+        1
+    } else {
+        2
+    }
+}
+
+impl Intersectable for Triangle {
+    // Watertight ray/triangle intersection (Woop, Benthin & Wald 2013). The triangle is transformed into a
+    // ray-aligned space where the ray points along +z, and the 2D edge functions are evaluated there, so
+    // rays hitting a shared edge can never slip between two neighbouring triangles.
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Intersection<'_>> {
+        let (p0, p1, p2) = self.vertices();
+
+        let kz = max_dimension(&ray.direction);
+        let mut kx = (kz + 1) % 3;
+        let mut ky = (kx + 1) % 3;
+        if ray.direction[kz] < 0.0 {
+            std::mem::swap(&mut kx, &mut ky);
+        }
+
+        let sx = ray.direction[kx] / ray.direction[kz];
+        let sy = ray.direction[ky] / ray.direction[kz];
+        let sz = 1.0 / ray.direction[kz];
+
+        let a = p0 - ray.origin;
+        let b = p1 - ray.origin;
+        let c = p2 - ray.origin;
+
+        let ax = a[kx] - sx * a[kz];
+        let ay = a[ky] - sy * a[kz];
+        let bx = b[kx] - sx * b[kz];
+        let by = b[ky] - sy * b[kz];
+        let cx = c[kx] - sx * c[kz];
+        let cy = c[ky] - sy * c[kz];
+
+        let e0 = cx * by - cy * bx;
+        let e1 = ax * cy - ay * cx;
+        let e2 = bx * ay - by * ax;
+
+        if (e0 < 0.0 || e1 < 0.0 || e2 < 0.0) && (e0 > 0.0 || e1 > 0.0 || e2 > 0.0) {
+            return None;
+        }
+
+        let det = e0 + e1 + e2;
+        if det == 0.0 {
+            return None;
+        }
+
+        let az = sz * a[kz];
+        let bz = sz * b[kz];
+        let cz = sz * c[kz];
+
+        let t = (e0 * az + e1 * bz + e2 * cz) / det;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+
+        let b0 = e0 / det;
+        let b1 = e1 / det;
+        let b2 = e2 / det;
+
+        let geometric_normal = Vec3::cross(&(p1 - p0), &(p2 - p0)).normalize();
+        let normal = self.interpolate_normal(b0, b1, b2, geometric_normal);
+        let (u, v) = self.interpolate_uv(b0, b1, b2);
+
+        Some(Intersection {
+            distance: t,
+            position: b0 * p0 + b1 * p1 + b2 * p2,
+            normal,
+            u,
+            v,
+            material: &self.mesh.material,
+        })
+    }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<AABB> {
+        let (p0, p1, p2) = self.vertices();
+
+        let min = Vec3::new(
+            p0.x.min(p1.x).min(p2.x),
+            p0.y.min(p1.y).min(p2.y),
+            p0.z.min(p1.z).min(p2.z),
+        );
+        let max = Vec3::new(
+            p0.x.max(p1.x).max(p2.x),
+            p0.y.max(p1.y).max(p2.y),
+            p0.z.max(p1.z).max(p2.z),
+        );
+
+        Some(AABB::from_min_max(min, max).pad(PADDING))
+    }
+
+    fn random_direction(&self, origin: &Vec3, _time: f64, rng: &mut dyn RngCore) -> Option<Vec3> {
+        let (p0, p1, p2) = self.vertices();
+
+        let su = rng.gen::<f64>().sqrt();
+        let b0 = 1.0 - su;
+        let b1 = rng.gen::<f64>() * su;
+        let point = b0 * p0 + b1 * p1 + (1.0 - b0 - b1) * p2;
+
+        Some(point - *origin)
+    }
+
+    // Converts the uniform area density of the triangle into a solid angle density as seen from the ray.
+    fn pdf_value(&self, ray: &Ray) -> f64 {
+        let hit = match self.intersect(ray, 0.001, f64::INFINITY) {
+            Some(hit) => hit,
+            None => return 0.0,
+        };
+
+        let (p0, p1, p2) = self.vertices();
+        let cross = Vec3::cross(&(p1 - p0), &(p2 - p0));
+        let area = 0.5 * cross.magnitude();
+
+        let distance2 = hit.distance * hit.distance * ray.direction.sqr_magnitude();
+        let cosine =
+            (Vec3::dot(&cross, &ray.direction) / (2.0 * area * ray.direction.magnitude())).abs();
+
+        if cosine <= 0.0 {
+            return 0.0;
+        }
+
+        distance2 / (cosine * area)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::renderer::{Lambertian, Texture};
+
+    // A closed, skewed octahedron around `center()`, so every edge and vertex is shared by several triangles.
+    fn octahedron() -> (Vec<Vec3>, Vec<Object>) {
+        let skew = |x: f64, y: f64, z: f64| {
+            center() + Vec3::new(x + 0.3 * y, y + 0.2 * z - 0.1 * x, z + 0.4 * x)
+        };
+        let positions = vec![
+            skew(1.0, 0.0, 0.0),
+            skew(-1.0, 0.0, 0.0),
+            skew(0.0, 1.0, 0.0),
+            skew(0.0, -1.0, 0.0),
+            skew(0.0, 0.0, 1.0),
+            skew(0.0, 0.0, -1.0),
+        ];
+        let mut indices = vec![];
+        for &x in &[0, 1] {
+            for &y in &[2, 3] {
+                for &z in &[4, 5] {
+                    indices.push([x, y, z]);
+                }
+            }
+        }
+
+        let material = Material::Lambertian(Lambertian {
+            albedo: Texture::Constant(Color::new(0.5, 0.5, 0.5, 1.0)),
+        });
+        let mesh = TriangleMesh::new(positions.clone(), indices, material);
+        (positions, mesh.into_objects())
+    }
+
+    fn center() -> Vec3 {
+        Vec3::new(0.3, -0.2, 0.1)
+    }
+
+    // Shoots a ray from outside the octahedron through `target` on its surface towards the inside.
+    fn hits(objects: &[Object], target: Vec3, rng: &mut StdRng) -> bool {
+        let outwards = (target - center()).normalize();
+        let jitter = Vec3::new(
+            rng.gen_range(-0.3, 0.3),
+            rng.gen_range(-0.3, 0.3),
+            rng.gen_range(-0.3, 0.3),
+        );
+        let origin = target + rng.gen_range(0.5, 5.0) * (outwards + jitter);
+        let ray = Ray::new(origin, (target - origin).normalize());
+
+        // Rays slipping through would still leave through the far side, so the hit must be at the target.
+        let distance = (target - origin).magnitude();
+        objects
+            .iter()
+            .filter_map(|object| object.intersect(&ray, 0.001, f64::INFINITY))
+            .any(|hit| (hit.distance - distance).abs() < 1e-6)
+    }
+
+    #[test]
+    fn rays_through_shared_edges_hit() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let (positions, objects) = octahedron();
+        for _ in 0..20_000 {
+            // Opposite vertices aren't joined by an edge.
+            let start = rng.gen_range(0, 6);
+            let end = (start / 2 * 2 + rng.gen_range(2, 6)) % 6;
+            let (start, end) = (positions[start], positions[end]);
+            let target = start + rng.gen::<f64>() * (end - start);
+            assert!(hits(&objects, target, &mut rng));
+        }
+    }
+
+    #[test]
+    fn rays_through_shared_vertices_hit() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let (positions, objects) = octahedron();
+        for _ in 0..20_000 {
+            let target = positions[rng.gen_range(0, 6)];
+            assert!(hits(&objects, target, &mut rng));
+        }
+    }
+}
@@ -0,0 +1,690 @@
+use super::coating::Coating;
+use super::microfacet::{fresnel_conductor, Ggx};
+use super::principled::PrincipledLobe;
+use super::thin_film::{dielectric_fresnel, ThinFilm};
+use crate::color::Color;
+use crate::math::{Onb, Vec3};
+use rand::prelude::*;
+use std::f64::consts::PI;
+
+fn random_unit_sphere(rng: &mut dyn RngCore) -> Vec3 {
+    let mut p: Vec3;
+    while {
+        p = 2.0 * Vec3::new(rng.gen(), rng.gen(), rng.gen()) - Vec3::new(1.0, 1.0, 1.0);
+
+        p.sqr_magnitude() >= 1.0
+    } {}
+
+    p
+}
+
+// Cosine distributed direction in the hemisphere around +z.
+pub(super) fn cosine_hemisphere(rng: &mut dyn RngCore) -> Vec3 {
+    let r2 = rng.gen::<f64>();
+    let phi = 2.0 * PI * rng.gen::<f64>();
+    let r = r2.sqrt();
+
+    Vec3::new(r * phi.cos(), r * phi.sin(), (1.0 - r2).max(0.0).sqrt())
+}
+
+fn black() -> Color {
+    Color::new(0.0, 0.0, 0.0, 0.0)
+}
+
+pub(super) fn complement(c: &Color) -> Color {
+    Color::new(1.0 - c.r, 1.0 - c.g, 1.0 - c.b, 1.0)
+}
+
+pub(super) fn average(c: &Color) -> f64 {
+    ((c.r + c.g + c.b) / 3.0) as f64
+}
+
+pub struct BsdfSample {
+    pub direction: Vec3,
+    // The BSDF times the cosine divided by the pdf, which is what the path throughput is multiplied by.
+    pub weight: Color,
+    pub pdf: f64,
+    // Sampled from a delta distribution, whose pdf can't be compared with light sampling.
+    pub specular: bool,
+    // Passed through the surface to its other side.
+    pub transmitted: bool,
+}
+
+// Scattering at a point with the textures already evaluated. Directions are unit vectors in the local
+// shading frame, where the normal is +z. `wo` points back along the incoming ray and `wi` is the scattered
+// direction, towards where the light comes from.
+#[derive(Copy, Clone)]
+pub enum Lobe {
+    Lambertian(Color),
+    // Mirror reflection perturbed by a random offset of up to `fuzz`.
+    Metal {
+        albedo: Color,
+        fuzz: f64,
+    },
+    // Glass like interface with GGX microfacets, refracting into a material with the given index from air.
+    // A thin film on the outside colours the reflections, seen at `wavelength` in spectral mode.
+    Dielectric {
+        index: f64,
+        ggx: Ggx,
+        film: Option<ThinFilm>,
+        wavelength: Option<f64>,
+    },
+    // Rough metal with the complex index of refraction `eta + i k` per channel.
+    Conductor {
+        eta: Color,
+        k: Color,
+        ggx: Ggx,
+    },
+    // Henyey-Greenstein phase function of a medium, ignoring the frame. `g` is zero for isotropic media.
+    Phase {
+        albedo: Color,
+        g: f64,
+    },
+    Principled(PrincipledLobe),
+}
+
+impl Lobe {
+    fn is_specular(&self) -> bool {
+        match self {
+            Lobe::Metal { .. } => true,
+            Lobe::Dielectric { ggx, .. } | Lobe::Conductor { ggx, .. } => ggx.is_smooth(),
+            _ => false,
+        }
+    }
+
+    // Reflectance of a dielectric seen from above after `outside`, where the index is `above`.
+    fn fresnel(&self, cosine: f64, above: f64, eta: f64) -> Color {
+        match *self {
+            Lobe::Dielectric {
+                film, wavelength, ..
+            } => dielectric_fresnel(cosine, above, above * eta, film, wavelength),
+            _ => black(),
+        }
+    }
+
+    // The BSDF times the cosine of `wi`.
+    pub(super) fn eval(&self, wo: &Vec3, wi: &Vec3) -> Color {
+        match *self {
+            Lobe::Lambertian(albedo) => {
+                if wo.z * wi.z <= 0.0 {
+                    return black();
+                }
+                albedo * (wi.z.abs() / PI)
+            }
+            Lobe::Conductor { eta, k, ggx } => {
+                if ggx.is_smooth() || wo.z * wi.z <= 0.0 {
+                    return black();
+                }
+
+                let (wo, wi) = (upper(wo), upper(wi));
+                let h = (wo + wi).normalize();
+                let fresnel = fresnel_conductor(Vec3::dot(&wo, &h), &eta, &k);
+                fresnel * (ggx.d(&h) * ggx.g(&wo, &wi) / (4.0 * wo.z))
+            }
+            Lobe::Dielectric { index, ggx, .. } => {
+                if ggx.is_smooth() {
+                    return black();
+                }
+
+                let above = above(wo, index);
+                let (wo, wi, eta) = outside(wo, wi, index);
+                match dielectric(&ggx, &wo, &wi, eta) {
+                    Some((h, DielectricEvent::Reflection)) => {
+                        let fresnel = self.fresnel(Vec3::dot(&wo, &h), above, eta);
+                        fresnel * (ggx.d(&h) * ggx.g(&wo, &wi) / (4.0 * wo.z))
+                    }
+                    Some((h, DielectricEvent::Transmission)) => {
+                        let (cos_o, cos_i) = (Vec3::dot(&wo, &h), Vec3::dot(&wi, &h));
+                        let fresnel = self.fresnel(cos_o, above, eta);
+                        let denominator = cos_i + cos_o / eta;
+                        complement(&fresnel)
+                            * (ggx.d(&h) * ggx.g(&wo, &wi) * (cos_i * cos_o).abs()
+                                / (denominator * denominator * wo.z * eta * eta))
+                    }
+                    None => black(),
+                }
+            }
+            Lobe::Phase { albedo, g } => albedo * phase(g, -Vec3::dot(wo, wi)),
+            Lobe::Principled(principled) => principled.eval(wo, wi),
+            _ => black(),
+        }
+    }
+
+    pub(super) fn pdf(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        match *self {
+            Lobe::Lambertian(_) => {
+                if wo.z * wi.z <= 0.0 {
+                    return 0.0;
+                }
+                wi.z.abs() / PI
+            }
+            Lobe::Conductor { ggx, .. } => {
+                if ggx.is_smooth() || wo.z * wi.z <= 0.0 {
+                    return 0.0;
+                }
+
+                let (wo, wi) = (upper(wo), upper(wi));
+                let h = (wo + wi).normalize();
+                ggx.pdf_visible(&wo, &h) / (4.0 * Vec3::dot(&wo, &h))
+            }
+            Lobe::Dielectric { index, ggx, .. } => {
+                if ggx.is_smooth() {
+                    return 0.0;
+                }
+
+                let above = above(wo, index);
+                let (wo, wi, eta) = outside(wo, wi, index);
+                match dielectric(&ggx, &wo, &wi, eta) {
+                    Some((h, DielectricEvent::Reflection)) => {
+                        let cos_o = Vec3::dot(&wo, &h);
+                        let reflectance = average(&self.fresnel(cos_o, above, eta));
+                        reflectance * ggx.pdf_visible(&wo, &h) / (4.0 * cos_o)
+                    }
+                    Some((h, DielectricEvent::Transmission)) => {
+                        let (cos_o, cos_i) = (Vec3::dot(&wo, &h), Vec3::dot(&wi, &h));
+                        let denominator = cos_i + cos_o / eta;
+                        (1.0 - average(&self.fresnel(cos_o, above, eta)))
+                            * ggx.pdf_visible(&wo, &h)
+                            * cos_i.abs()
+                            / (denominator * denominator)
+                    }
+                    None => 0.0,
+                }
+            }
+            Lobe::Phase { g, .. } => phase(g, -Vec3::dot(wo, wi)),
+            Lobe::Principled(principled) => principled.pdf(wo, wi),
+            _ => 0.0,
+        }
+    }
+
+    pub(super) fn sample(&self, wo: &Vec3, rng: &mut dyn RngCore) -> Option<BsdfSample> {
+        match *self {
+            Lobe::Lambertian(albedo) => {
+                let mut wi = cosine_hemisphere(rng);
+                if wo.z < 0.0 {
+                    wi.z = -wi.z;
+                }
+
+                Some(BsdfSample {
+                    direction: wi,
+                    weight: albedo,
+                    pdf: wi.z.abs() / PI,
+                    specular: false,
+                    transmitted: false,
+                })
+            }
+            Lobe::Metal { albedo, fuzz } => {
+                let wi = Vec3::new(-wo.x, -wo.y, wo.z) + fuzz * random_unit_sphere(rng);
+                if wi.z * wo.z <= 0.0 {
+                    return None;
+                }
+
+                Some(BsdfSample {
+                    direction: wi.normalize(),
+                    weight: albedo,
+                    pdf: 0.0,
+                    specular: true,
+                    transmitted: false,
+                })
+            }
+            Lobe::Dielectric { index, ggx, .. } => {
+                // Sampled as seen from above, with `eta` the ratio of the indices below and above.
+                let flip = if wo.z < 0.0 { -1.0 } else { 1.0 };
+                let above = above(wo, index);
+                let (wo, _, eta) = outside(wo, wo, index);
+
+                let h = if ggx.is_smooth() {
+                    Vec3::new(0.0, 0.0, 1.0)
+                } else {
+                    ggx.sample_visible(&wo, rng.gen(), rng.gen())
+                };
+                let cos_o = Vec3::dot(&wo, &h);
+                // Reflection is chosen by the average reflectance, which only differs from the
+                // reflectance of each channel under a thin film.
+                let fresnel = self.fresnel(cos_o, above, eta);
+                let reflectance = average(&fresnel);
+
+                let (wi, weight, pdf, transmitted) = if rng.gen::<f64>() < reflectance {
+                    let wi = Vec3::reflect(&-wo, &h);
+                    if wi.z <= 0.0 {
+                        return None;
+                    }
+
+                    let fresnel = fresnel * (1.0 / reflectance);
+                    if ggx.is_smooth() {
+                        (wi, fresnel, 0.0, false)
+                    } else {
+                        let pdf = reflectance * ggx.pdf_visible(&wo, &h) / (4.0 * cos_o);
+                        (wi, fresnel * (ggx.g(&wo, &wi) / ggx.g1(&wo)), pdf, false)
+                    }
+                } else {
+                    let wi = Vec3::refract(&-wo, &h, 1.0 / eta)?;
+                    if wi.z >= 0.0 {
+                        return None;
+                    }
+
+                    // Radiance is squeezed into a narrower cone on the denser side.
+                    let transmittance = complement(&fresnel) * (1.0 / (1.0 - reflectance));
+                    let scale = 1.0 / (eta * eta);
+                    if ggx.is_smooth() {
+                        (wi, transmittance * scale, 0.0, true)
+                    } else {
+                        let cos_i = Vec3::dot(&wi, &h);
+                        let denominator = cos_i + cos_o / eta;
+                        let pdf = (1.0 - reflectance) * ggx.pdf_visible(&wo, &h) * cos_i.abs()
+                            / (denominator * denominator);
+                        let weight = scale * ggx.g(&wo, &wi) / ggx.g1(&wo);
+                        (wi, transmittance * weight, pdf, true)
+                    }
+                };
+
+                Some(BsdfSample {
+                    direction: Vec3::new(wi.x, wi.y, flip * wi.z),
+                    weight,
+                    pdf,
+                    specular: ggx.is_smooth(),
+                    transmitted,
+                })
+            }
+            Lobe::Conductor { eta, k, ggx } => {
+                // Both sides of the surface reflect, so the lobe is evaluated as seen from above.
+                let flip = if wo.z < 0.0 { -1.0 } else { 1.0 };
+                let wo = upper(wo);
+
+                if ggx.is_smooth() {
+                    return Some(BsdfSample {
+                        direction: Vec3::new(-wo.x, -wo.y, flip * wo.z),
+                        weight: fresnel_conductor(wo.z, &eta, &k),
+                        pdf: 0.0,
+                        specular: true,
+                        transmitted: false,
+                    });
+                }
+
+                let h = ggx.sample_visible(&wo, rng.gen(), rng.gen());
+                let wi = Vec3::reflect(&-wo, &h);
+                if wi.z <= 0.0 {
+                    return None;
+                }
+
+                let cosine = Vec3::dot(&wo, &h);
+                let weight = fresnel_conductor(cosine, &eta, &k) * (ggx.g(&wo, &wi) / ggx.g1(&wo));
+
+                Some(BsdfSample {
+                    direction: Vec3::new(wi.x, wi.y, flip * wi.z),
+                    weight,
+                    pdf: ggx.pdf_visible(&wo, &h) / (4.0 * cosine),
+                    specular: false,
+                    transmitted: false,
+                })
+            }
+            Lobe::Principled(principled) => principled.sample(wo, rng),
+            Lobe::Phase { albedo, g } => {
+                let cosine = sample_phase(g, rng.gen());
+                let sine = (1.0 - cosine * cosine).max(0.0).sqrt();
+                let phi = 2.0 * PI * rng.gen::<f64>();
+                let wi = Onb::from_w(&-*wo).local(sine * phi.cos(), sine * phi.sin(), cosine);
+
+                Some(BsdfSample {
+                    direction: wi,
+                    weight: albedo,
+                    pdf: phase(g, cosine),
+                    specular: false,
+                    transmitted: false,
+                })
+            }
+        }
+    }
+}
+
+enum DielectricEvent {
+    Reflection,
+    Transmission,
+}
+
+// The index on the side of a dielectric where `wo` is, with air outside.
+fn above(wo: &Vec3, index: f64) -> f64 {
+    if wo.z >= 0.0 {
+        1.0
+    } else {
+        index
+    }
+}
+
+// Turns a dielectric around so that `wo` is above the surface, returning both directions and the ratio of
+// the index below the surface to the index above it.
+fn outside(wo: &Vec3, wi: &Vec3, index: f64) -> (Vec3, Vec3, f64) {
+    if wo.z >= 0.0 {
+        (*wo, *wi, index)
+    } else {
+        let flip = |w: &Vec3| Vec3::new(w.x, w.y, -w.z);
+        (flip(wo), flip(wi), 1.0 / index)
+    }
+}
+
+// The microfacet normal that reflects or refracts `wo` (above the surface) into `wi`, or `None` if the
+// pair can't be connected by a facet facing both of them.
+fn dielectric(ggx: &Ggx, wo: &Vec3, wi: &Vec3, eta: f64) -> Option<(Vec3, DielectricEvent)> {
+    if wo.z <= 0.0 || wi.z == 0.0 {
+        return None;
+    }
+
+    let (h, event) = if wi.z > 0.0 {
+        (*wo + *wi, DielectricEvent::Reflection)
+    } else {
+        (*wo + *wi * eta, DielectricEvent::Transmission)
+    };
+    if h.sqr_magnitude() == 0.0 {
+        return None;
+    }
+
+    let h = h.normalize();
+    let h = if h.z < 0.0 { -h } else { h };
+    if Vec3::dot(&h, wo) <= 0.0 || Vec3::dot(&h, wi) * wi.z <= 0.0 || ggx.d(&h) <= 0.0 {
+        return None;
+    }
+
+    Some((h, event))
+}
+
+// Mirrors a direction into the upper hemisphere.
+pub(super) fn upper(w: &Vec3) -> Vec3 {
+    Vec3::new(w.x, w.y, w.z.abs())
+}
+
+// Henyey-Greenstein density of scattering by an angle with the given cosine from the direction of travel.
+fn phase(g: f64, cosine: f64) -> f64 {
+    let denominator = 1.0 + g * g - 2.0 * g * cosine;
+    (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+}
+
+// Inverts the cumulative distribution of the Henyey-Greenstein phase function.
+fn sample_phase(g: f64, xi: f64) -> f64 {
+    if g.abs() < 1e-3 {
+        return 1.0 - 2.0 * xi;
+    }
+
+    let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * xi);
+    ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+}
+
+// A lobe placed in the shading frame around a normal, optionally under a coating. Directions passed in and
+// out are world space unit vectors.
+pub struct Bsdf {
+    pub frame: Onb,
+    pub lobe: Lobe,
+    pub coating: Option<Coating>,
+}
+
+impl Bsdf {
+    pub fn new(normal: &Vec3, lobe: Lobe) -> Bsdf {
+        Bsdf {
+            frame: Onb::from_w(normal),
+            lobe,
+            coating: None,
+        }
+    }
+
+    pub fn is_specular(&self) -> bool {
+        self.lobe.is_specular() && self.coating.iter().all(Coating::is_smooth)
+    }
+
+    pub fn eval(&self, wo: &Vec3, wi: &Vec3) -> Color {
+        let (wo, wi) = (self.frame.to_local(wo), self.frame.to_local(wi));
+        match &self.coating {
+            Some(coating) => coating.eval(&self.lobe, &wo, &wi),
+            None => self.lobe.eval(&wo, &wi),
+        }
+    }
+
+    pub fn pdf(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        let (wo, wi) = (self.frame.to_local(wo), self.frame.to_local(wi));
+        match &self.coating {
+            Some(coating) => coating.pdf(&self.lobe, &wo, &wi),
+            None => self.lobe.pdf(&wo, &wi),
+        }
+    }
+
+    pub fn sample(&self, wo: &Vec3, rng: &mut dyn RngCore) -> Option<BsdfSample> {
+        let wo = self.frame.to_local(wo);
+        let sample = match &self.coating {
+            Some(coating) => coating.sample(&self.lobe, &wo, rng)?,
+            None => self.lobe.sample(&wo, rng)?,
+        };
+
+        Some(BsdfSample {
+            direction: self.frame.to_world(&sample.direction),
+            ..sample
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::material::Principled;
+    use crate::renderer::texture::Texture;
+
+    fn gray(value: f32) -> Color {
+        Color::new(value, value, value, 1.0)
+    }
+
+    fn bsdf(lobe: Lobe) -> Bsdf {
+        Bsdf::new(&Vec3::new(0.0, 0.0, 1.0), lobe)
+    }
+
+    fn coated(lobe: Lobe, roughness: f64) -> Bsdf {
+        Bsdf {
+            coating: Some(Coating {
+                index: 1.5,
+                ggx: Ggx::from_roughness(roughness),
+                tint: Color::new(0.9, 0.8, 0.7, 1.0),
+                film: None,
+                wavelength: None,
+            }),
+            ..bsdf(lobe)
+        }
+    }
+
+    fn dielectric(roughness: f64, film: Option<ThinFilm>) -> Lobe {
+        Lobe::Dielectric {
+            index: 1.5,
+            ggx: Ggx::from_roughness(roughness),
+            film,
+            wavelength: None,
+        }
+    }
+
+    fn principled(metallic: f64, transmission: f64) -> Lobe {
+        let base_color = Color::new(0.8, 0.4, 0.2, 1.0);
+        let mut material = Principled::new(Texture::Constant(base_color));
+        material.sheen = 1.0;
+        material.clearcoat = 0.5;
+        material.clearcoat_roughness = 0.2;
+        material.transmission = transmission;
+        Lobe::Principled(PrincipledLobe::new(&material, base_color, metallic, 0.5))
+    }
+
+    fn microfacet_lobes() -> Vec<(&'static str, Bsdf)> {
+        let conductor = |roughness| Lobe::Conductor {
+            eta: Color::new(0.2, 0.9, 1.1, 1.0),
+            k: Color::new(3.9, 2.4, 1.9, 1.0),
+            ggx: Ggx::from_roughness(roughness),
+        };
+
+        vec![
+            ("conductor", bsdf(conductor(0.4))),
+            ("rough conductor", bsdf(conductor(0.9))),
+            ("dielectric", bsdf(dielectric(0.5, None))),
+            (
+                "dielectric with film",
+                bsdf(dielectric(
+                    0.5,
+                    Some(ThinFilm {
+                        thickness: 400.0,
+                        index: 1.3,
+                    }),
+                )),
+            ),
+            ("principled", bsdf(principled(0.3, 0.0))),
+            ("principled glass", bsdf(principled(0.0, 0.8))),
+            ("coated conductor", coated(conductor(0.6), 0.5)),
+            ("coated", coated(Lobe::Lambertian(gray(0.5)), 0.3)),
+            ("smooth coat", coated(Lobe::Lambertian(gray(0.5)), 0.0)),
+        ]
+    }
+
+    fn diffuse_lobes() -> Vec<(&'static str, Bsdf)> {
+        vec![
+            ("lambertian", bsdf(Lobe::Lambertian(gray(0.5)))),
+            (
+                "isotropic",
+                bsdf(Lobe::Phase {
+                    albedo: gray(0.7),
+                    g: 0.0,
+                }),
+            ),
+            (
+                "forward phase",
+                bsdf(Lobe::Phase {
+                    albedo: gray(0.7),
+                    g: 0.4,
+                }),
+            ),
+            (
+                "backward phase",
+                bsdf(Lobe::Phase {
+                    albedo: gray(0.7),
+                    g: -0.8,
+                }),
+            ),
+        ]
+    }
+
+    fn lobes() -> Vec<(&'static str, Bsdf)> {
+        let mut lobes = microfacet_lobes();
+        lobes.extend(diffuse_lobes());
+        lobes
+    }
+
+    fn directions() -> Vec<Vec3> {
+        [0.2f64, 0.9, 1.4, 2.0, 2.9]
+            .iter()
+            .map(|theta| Vec3::new(theta.sin(), 0.0, theta.cos()))
+            .collect()
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= 1e-4 * a.abs().max(b.abs()).max(1.0)
+    }
+
+    // Integrates the pdf for `wo` over a jittered grid of directions, uniform in the cosine and the angle
+    // around the normal.
+    fn integrate_pdf(bsdf: &Bsdf, wo: &Vec3, rng: &mut StdRng) -> f64 {
+        let n = 300;
+        let mut total = 0.0;
+        for i in 0..n {
+            for j in 0..n {
+                let z = 1.0 - 2.0 * (i as f64 + rng.gen::<f64>()) / n as f64;
+                let phi = 2.0 * PI * (j as f64 + rng.gen::<f64>()) / n as f64;
+                let r = (1.0 - z * z).max(0.0).sqrt();
+                total += bsdf.pdf(wo, &Vec3::new(r * phi.cos(), r * phi.sin(), z));
+            }
+        }
+
+        4.0 * PI * total / (n * n) as f64
+    }
+
+    #[test]
+    fn sample_weights_are_eval_over_pdf() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for (name, bsdf) in lobes() {
+            for wo in directions() {
+                for _ in 0..2000 {
+                    let sample = match bsdf.sample(&wo, &mut rng) {
+                        Some(sample) if !sample.specular => sample,
+                        _ => continue,
+                    };
+
+                    let pdf = bsdf.pdf(&wo, &sample.direction);
+                    assert!(
+                        close(sample.pdf, pdf),
+                        "{}: pdf {} != {}",
+                        name,
+                        sample.pdf,
+                        pdf
+                    );
+
+                    let eval = bsdf.eval(&wo, &sample.direction);
+                    let channels = [
+                        (sample.weight.r, eval.r),
+                        (sample.weight.g, eval.g),
+                        (sample.weight.b, eval.b),
+                    ];
+                    for &(weight, eval) in &channels {
+                        let (weighted, eval) = (weight as f64 * pdf, eval as f64);
+                        assert!(close(weighted, eval), "{}: {} != {}", name, weighted, eval);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pdfs_integrate_to_one() {
+        let mut rng = StdRng::seed_from_u64(2);
+        for (name, bsdf) in diffuse_lobes() {
+            for wo in directions() {
+                let integral = integrate_pdf(&bsdf, &wo, &mut rng);
+                assert!(
+                    (integral - 1.0).abs() < 0.02,
+                    "{}: pdf integrates to {}",
+                    name,
+                    integral
+                );
+            }
+        }
+    }
+
+    // Microfacet lobes and coats lose the samples that end up below the surface.
+    #[test]
+    fn microfacet_pdfs_integrate_to_at_most_one() {
+        let mut rng = StdRng::seed_from_u64(4);
+        for (name, bsdf) in microfacet_lobes() {
+            for wo in directions() {
+                let integral = integrate_pdf(&bsdf, &wo, &mut rng);
+                assert!(integral <= 1.02, "{}: pdf integrates to {}", name, integral);
+            }
+        }
+    }
+
+    #[test]
+    fn dielectrics_reflect_or_refract() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let bsdf = bsdf(dielectric(0.0, None));
+
+        for wo in directions() {
+            let (sine, eta) = (wo.x, if wo.z > 0.0 { 1.0 / 1.5 } else { 1.5 });
+            let mut reflected = 0;
+            for _ in 0..1000 {
+                let sample = bsdf.sample(&wo, &mut rng).unwrap();
+                let wi = sample.direction;
+                assert!(sample.specular && (wi.magnitude() - 1.0).abs() < 1e-9);
+
+                if wi.z * wo.z > 0.0 {
+                    assert!((wi - Vec3::new(-wo.x, -wo.y, wo.z)).magnitude() < 1e-9);
+                    reflected += 1;
+                } else {
+                    assert!((wi.x + eta * sine).abs() < 1e-9, "{} {}", wi.x, sine);
+                }
+            }
+
+            // Past the critical angle everything is reflected.
+            if eta * sine.abs() > 1.0 {
+                assert_eq!(reflected, 1000);
+            } else {
+                assert!(reflected < 1000);
+            }
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use super::bsdf::{average, complement, upper, BsdfSample, Lobe};
+use super::microfacet::Ggx;
+use super::thin_film::{dielectric_fresnel, ThinFilm};
+use crate::color::Color;
+use crate::math::Vec3;
+use rand::prelude::*;
+
+// A clear dielectric layer over a base lobe, like lacquer or varnish, on both sides of the surface. Light
+// reaching the base crosses the coat's interface on the way in and out, losing what the interface reflects,
+// and is absorbed by `tint`, the colour left after crossing the coat once straight down. The coat is thin,
+// so the base sees the directions unrefracted.
+#[derive(Copy, Clone)]
+pub struct Coating {
+    pub index: f64,
+    pub ggx: Ggx,
+    pub tint: Color,
+    pub film: Option<ThinFilm>,
+    pub wavelength: Option<f64>,
+}
+
+impl Coating {
+    pub fn is_smooth(&self) -> bool {
+        self.ggx.is_smooth()
+    }
+
+    // Reflectance of the coat for light arriving from outside at an angle with the given cosine.
+    fn fresnel(&self, cosine: f64) -> Color {
+        dielectric_fresnel(cosine, 1.0, self.index, self.film, self.wavelength)
+    }
+
+    // The fraction of the base's emission that leaves through the coat at an angle with the given cosine.
+    pub fn emission(&self, cosine: f64) -> Color {
+        complement(&self.fresnel(cosine)) * self.tint
+    }
+
+    // The fraction of light arriving along `wo` that reaches the base and leaves again along `wi`.
+    fn transmittance(&self, wo: &Vec3, wi: &Vec3) -> Color {
+        let length = |w: &Vec3| {
+            let sin2 = (1.0 - w.z * w.z) / (self.index * self.index);
+            1.0 / (1.0 - sin2).max(0.0).sqrt().max(1e-3)
+        };
+        let length = (length(wo) + length(wi)) as f32;
+        let absorbed = Color::new(
+            self.tint.r.powf(length),
+            self.tint.g.powf(length),
+            self.tint.b.powf(length),
+            1.0,
+        );
+
+        complement(&self.fresnel(wo.z.abs())) * complement(&self.fresnel(wi.z.abs())) * absorbed
+    }
+
+    pub(super) fn eval(&self, base: &Lobe, wo: &Vec3, wi: &Vec3) -> Color {
+        let value = base.eval(wo, wi) * self.transmittance(wo, wi);
+        if self.is_smooth() || wo.z * wi.z <= 0.0 {
+            return value;
+        }
+
+        let (wo, wi) = (upper(wo), upper(wi));
+        let h = (wo + wi).normalize();
+        let fresnel = self.fresnel(Vec3::dot(&wo, &h));
+        value + fresnel * (self.ggx.d(&h) * self.ggx.g(&wo, &wi) / (4.0 * wo.z))
+    }
+
+    // The coat is sampled by its reflectance towards `wo`, and the base otherwise.
+    pub(super) fn pdf(&self, base: &Lobe, wo: &Vec3, wi: &Vec3) -> f64 {
+        let reflectance = average(&self.fresnel(wo.z.abs()));
+        let pdf = (1.0 - reflectance) * base.pdf(wo, wi);
+        if self.is_smooth() || wo.z * wi.z <= 0.0 {
+            return pdf;
+        }
+
+        let (wo, wi) = (upper(wo), upper(wi));
+        let h = (wo + wi).normalize();
+        let cos_o = Vec3::dot(&wo, &h);
+        if cos_o <= 0.0 {
+            return pdf;
+        }
+
+        pdf + reflectance * self.ggx.pdf_visible(&wo, &h) / (4.0 * cos_o)
+    }
+
+    pub(super) fn sample(
+        &self,
+        base: &Lobe,
+        wo: &Vec3,
+        rng: &mut dyn RngCore,
+    ) -> Option<BsdfSample> {
+        let reflectance = average(&self.fresnel(wo.z.abs()));
+
+        let wi = if rng.gen::<f64>() < reflectance {
+            let flip = if wo.z < 0.0 { -1.0 } else { 1.0 };
+            let up = upper(wo);
+
+            if self.is_smooth() {
+                return Some(BsdfSample {
+                    direction: Vec3::new(-wo.x, -wo.y, wo.z),
+                    weight: self.fresnel(up.z) * (1.0 / reflectance),
+                    pdf: 0.0,
+                    specular: true,
+                    transmitted: false,
+                });
+            }
+
+            let h = self.ggx.sample_visible(&up, rng.gen(), rng.gen());
+            let wi = Vec3::reflect(&-up, &h);
+            if wi.z <= 0.0 {
+                return None;
+            }
+            Vec3::new(wi.x, wi.y, flip * wi.z)
+        } else {
+            let sample = base.sample(wo, rng)?;
+            if sample.specular {
+                let transmittance = self.transmittance(wo, &sample.direction);
+                return Some(BsdfSample {
+                    weight: sample.weight * transmittance * (1.0 / (1.0 - reflectance)),
+                    ..sample
+                });
+            }
+            sample.direction
+        };
+
+        let pdf = self.pdf(base, wo, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(BsdfSample {
+            direction: wi,
+            weight: self.eval(base, wo, &wi) * (1.0 / pdf),
+            pdf,
+            specular: false,
+            transmitted: wo.z * wi.z < 0.0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Ray;
+    use crate::objects::Intersection;
+    use crate::renderer::material::{Coated, DiffuseLight, Material};
+    use crate::renderer::texture::Texture;
+
+    // The base's emission loses what the coat reflects back and what its tint absorbs on the way out.
+    #[test]
+    fn coated_emission_shines_through_the_coat() {
+        let tint = Color::new(0.9, 0.8, 0.7, 1.0);
+        let light = Material::DiffuseLight(DiffuseLight {
+            emit: Texture::Constant(Color::new(4.0, 4.0, 4.0, 1.0)),
+        });
+        let coated = Material::Coated(Coated {
+            index: 1.5,
+            roughness: 0.2,
+            tint,
+            film: None,
+            base: Box::new(light),
+        });
+        assert!(coated.is_light());
+
+        let intersection = Intersection {
+            distance: 1.0,
+            position: Vec3::new(0.0, 0.0, 0.0),
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            u: 0.0,
+            v: 0.0,
+            material: &coated,
+        };
+        let straight = Ray::new(Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -2.0));
+        let emitted = coated.emitted(&straight, &intersection);
+        let expected = tint * (4.0f32 * 0.96);
+        assert!((emitted.r - expected.r).abs() < 1e-5);
+        assert!((emitted.g - expected.g).abs() < 1e-5);
+        assert!((emitted.b - expected.b).abs() < 1e-5);
+
+        // More is reflected back at grazing angles.
+        let grazing = Ray::new(Vec3::new(-1.0, 0.0, 0.1), Vec3::new(1.0, 0.0, -0.1));
+        assert!(coated.emitted(&grazing, &intersection).r < emitted.r);
+    }
+}
@@ -0,0 +1,313 @@
+use super::color_from_direction;
+use super::exr_reader::read_exr;
+use super::sky::Sky;
+use super::texture::{ImageTexture, WrapMode};
+use crate::color::Color;
+use crate::math::{Ray, Vec3};
+use image::hdr::HdrDecoder;
+use image::ImageError;
+use rand::prelude::*;
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+
+// Index of the interval of a cumulative distribution (starting at zero) that `value` falls into.
+fn find_interval(cdf: &[f64], value: f64) -> usize {
+    let index = cdf.partition_point(|c| *c <= value);
+    index.max(1).min(cdf.len() - 1) - 1
+}
+
+// An equirectangular (latitude-longitude) image of the light arriving from every direction. The center of
+// the image looks down -z with +y at the top, and `rotation` turns the map around the y axis. Directions
+// are importance sampled by picking a row from the marginal distribution of the row sums and then a pixel
+// from that row's distribution, with pixels weighted by their luminance and solid angle.
+pub struct EnvironmentMap {
+    pub image: ImageTexture,
+    pub rotation: f64,
+    pub intensity: f32,
+
+    marginal: Vec<f64>,
+    conditional: Vec<Vec<f64>>,
+    weights: Vec<f64>,
+    total: f64,
+}
+
+impl EnvironmentMap {
+    pub fn new(image: ImageTexture, rotation: f64, intensity: f32) -> EnvironmentMap {
+        let (width, height) = (image.width, image.height);
+
+        let mut weights = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let sine = (PI * (y as f64 + 0.5) / height as f64).sin();
+            for x in 0..width {
+                weights.push(image.pixels[x + y * width].luminance().max(0.0) as f64 * sine);
+            }
+        }
+
+        // A black map is sampled uniformly instead.
+        if weights.iter().all(|w| *w <= 0.0) {
+            weights.iter_mut().for_each(|w| *w = 1.0);
+        }
+
+        let cdf = |values: &[f64]| {
+            let mut cdf = vec![0.0];
+            for value in values {
+                cdf.push(cdf.last().unwrap() + value);
+            }
+            cdf
+        };
+
+        let conditional: Vec<Vec<f64>> = weights.chunks(width.max(1)).map(cdf).collect();
+        let row_sums: Vec<f64> = conditional.iter().map(|row| *row.last().unwrap()).collect();
+        let marginal = cdf(&row_sums);
+
+        EnvironmentMap {
+            image,
+            rotation,
+            intensity,
+            total: *marginal.last().unwrap(),
+            marginal,
+            conditional,
+            weights,
+        }
+    }
+
+    // Float data is read from `.hdr` and `.exr` files, anything else is treated as an sRGB encoded image.
+    pub fn load(path: &Path, rotation: f64, intensity: f32) -> image::ImageResult<EnvironmentMap> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+
+        let (width, height, pixels) = match extension.as_deref() {
+            Some("hdr") => {
+                let decoder = HdrDecoder::new(BufReader::new(File::open(path)?))?;
+                let metadata = decoder.metadata();
+                let pixels = decoder
+                    .read_image_hdr()?
+                    .iter()
+                    .map(|p| Color::new(p[0], p[1], p[2], 1.0))
+                    .collect();
+                (metadata.width as usize, metadata.height as usize, pixels)
+            }
+            Some("exr") => {
+                let (width, height, channels) = read_exr(path).map_err(ImageError::IoError)?;
+                let plane = |name: &str| {
+                    channels
+                        .iter()
+                        .find(|(channel, _)| channel == name)
+                        .map(|(_, values)| values)
+                };
+                let (r, g, b) = match (plane("R"), plane("G"), plane("B"), plane("Y")) {
+                    (Some(r), Some(g), Some(b), _) => (r, g, b),
+                    (_, _, _, Some(y)) => (y, y, y),
+                    _ => {
+                        return Err(ImageError::IoError(std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            "EXR: no R, G and B or Y channels",
+                        )))
+                    }
+                };
+                let pixels = (0..width * height)
+                    .map(|i| Color::new(r[i], g[i], b[i], 1.0))
+                    .collect();
+                (width, height, pixels)
+            }
+            _ => {
+                let image = ImageTexture::load(path, WrapMode::Repeat)?;
+                (image.width, image.height, image.pixels)
+            }
+        };
+
+        let image = ImageTexture {
+            width,
+            height,
+            pixels,
+            wrap: WrapMode::Repeat,
+        };
+        Ok(EnvironmentMap::new(image, rotation, intensity))
+    }
+
+    // Turns a world direction into map coordinates, `u` around the horizon and `theta` down from the top.
+    fn to_map(&self, direction: &Vec3) -> (f64, f64) {
+        let d = direction.normalize();
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let (x, z) = (cos * d.x - sin * d.z, sin * d.x + cos * d.z);
+
+        let u = 0.5 + x.atan2(-z) / (2.0 * PI);
+        let theta = d.y.clamp(-1.0, 1.0).acos();
+        (u, theta)
+    }
+
+    // The inverse of `to_map`.
+    fn direction(&self, u: f64, theta: f64) -> Vec3 {
+        let phi = 2.0 * PI * (u - 0.5);
+        let (x, y, z) = (
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            -theta.sin() * phi.cos(),
+        );
+
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        Vec3::new(cos * x + sin * z, y, -sin * x + cos * z)
+    }
+
+    pub fn value(&self, direction: &Vec3) -> Color {
+        let (u, theta) = self.to_map(direction);
+        self.image.sample(u, 1.0 - theta / PI) * self.intensity
+    }
+
+    pub fn random_direction(&self, rng: &mut dyn RngCore) -> Vec3 {
+        let y = find_interval(&self.marginal, rng.gen::<f64>() * self.total);
+        let row = &self.conditional[y];
+        let x = find_interval(row, rng.gen::<f64>() * row.last().unwrap());
+
+        let u = (x as f64 + rng.gen::<f64>()) / self.image.width as f64;
+        let v = (y as f64 + rng.gen::<f64>()) / self.image.height as f64;
+        self.direction(u, PI * v)
+    }
+
+    // The pixel probability spread over the pixel's area in the map, converted to solid angle with the
+    // `2 pi^2 sin(theta)` Jacobian of the equirectangular mapping.
+    pub fn pdf_value(&self, direction: &Vec3) -> f64 {
+        let (width, height) = (self.image.width, self.image.height);
+        let (u, theta) = self.to_map(direction);
+        let sine = theta.sin();
+        if sine <= 0.0 || self.total <= 0.0 {
+            return 0.0;
+        }
+
+        let x = ((u.rem_euclid(1.0) * width as f64) as usize).min(width - 1);
+        let y = ((theta / PI * height as f64) as usize).min(height - 1);
+        let probability = self.weights[x + y * width] / self.total;
+        probability * (width * height) as f64 / (2.0 * PI * PI * sine)
+    }
+}
+
+// The light arriving from infinitely far away, seen by rays that leave the scene.
+#[derive(Clone)]
+pub enum Environment {
+    Constant(Color),
+    // The white to blue sky of the book.
+    Gradient,
+    Map(Arc<EnvironmentMap>),
+    Sky(Arc<Sky>),
+}
+
+impl Environment {
+    pub fn value(&self, direction: &Vec3) -> Color {
+        match self {
+            Environment::Constant(color) => *color,
+            Environment::Gradient => color_from_direction(&Ray::new(Vec3::zero(), *direction)),
+            Environment::Map(map) => map.value(direction),
+            Environment::Sky(sky) => sky.value(direction),
+        }
+    }
+
+    // Only maps and the sun are sampled by next event estimation, the smooth environments are found well
+    // enough by scattered rays.
+    pub fn is_sampled(&self) -> bool {
+        matches!(self, Environment::Map(_) | Environment::Sky(_))
+    }
+
+    pub fn random_direction(&self, rng: &mut dyn RngCore) -> Option<Vec3> {
+        match self {
+            Environment::Map(map) => Some(map.random_direction(rng)),
+            Environment::Sky(sky) => Some(sky.random_direction(rng)),
+            _ => None,
+        }
+    }
+
+    pub fn pdf_value(&self, direction: &Vec3) -> f64 {
+        match self {
+            Environment::Map(map) => map.pdf_value(direction),
+            Environment::Sky(sky) => sky.pdf_value(direction),
+            _ => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: usize = 8;
+    const HEIGHT: usize = 4;
+    // Sub-pixel steps used to integrate over the map.
+    const STEPS: usize = 8;
+
+    fn map() -> EnvironmentMap {
+        let pixels = (0..(WIDTH * HEIGHT))
+            .map(|i| {
+                let value = ((i * 7) % 5) as f32 * 0.5;
+                Color::new(value, 0.5 * value, 2.0 * value, 1.0)
+            })
+            .collect();
+        let image = ImageTexture {
+            width: WIDTH,
+            height: HEIGHT,
+            pixels,
+            wrap: WrapMode::Repeat,
+        };
+        EnvironmentMap::new(image, 30.0, 1.0)
+    }
+
+    // The integral of `pdf_value` over the solid angle each pixel covers.
+    fn pixel_probabilities(map: &EnvironmentMap) -> Vec<f64> {
+        let (du, dtheta) = (1.0 / (WIDTH * STEPS) as f64, PI / (HEIGHT * STEPS) as f64);
+        let mut probabilities = vec![0.0; WIDTH * HEIGHT];
+        for y in 0..(HEIGHT * STEPS) {
+            for x in 0..(WIDTH * STEPS) {
+                let (u, theta) = ((x as f64 + 0.5) * du, (y as f64 + 0.5) * dtheta);
+                let pdf = map.pdf_value(&map.direction(u, theta));
+                probabilities[x / STEPS + (y / STEPS) * WIDTH] +=
+                    pdf * theta.sin() * 2.0 * PI * du * dtheta;
+            }
+        }
+        probabilities
+    }
+
+    #[test]
+    fn map_coordinates_round_trip() {
+        let map = map();
+        for &(u, theta) in &[(0.1, 0.3), (0.5, 1.5), (0.9, 2.8)] {
+            let (u2, theta2) = map.to_map(&map.direction(u, theta));
+            assert!((u2 - u).abs() < 1e-9 && (theta2 - theta).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn pdf_integrates_to_one() {
+        let total: f64 = pixel_probabilities(&map()).iter().sum();
+        assert!((total - 1.0).abs() < 1e-9, "{}", total);
+    }
+
+    #[test]
+    fn samples_follow_the_pdf() {
+        const SAMPLES: usize = 200_000;
+        let map = map();
+        let mut rng = StdRng::seed_from_u64(1);
+
+        let mut counts = [0; WIDTH * HEIGHT];
+        for _ in 0..SAMPLES {
+            let direction = map.random_direction(&mut rng);
+            assert!((direction.magnitude() - 1.0).abs() < 1e-9);
+            let (u, theta) = map.to_map(&direction);
+            let x = ((u.rem_euclid(1.0) * WIDTH as f64) as usize).min(WIDTH - 1);
+            let y = ((theta / PI * HEIGHT as f64) as usize).min(HEIGHT - 1);
+            counts[x + y * WIDTH] += 1;
+        }
+
+        for (count, probability) in counts.iter().zip(pixel_probabilities(&map)) {
+            let share = *count as f64 / SAMPLES as f64;
+            assert!(
+                (share - probability).abs() < 0.005,
+                "{} {}",
+                share,
+                probability
+            );
+        }
+    }
+}
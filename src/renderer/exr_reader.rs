@@ -0,0 +1,419 @@
+use miniz_oxide::inflate::decompress_to_vec_zlib;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Larger images are rejected instead of trusting the header with the allocation.
+const MAX_PIXELS: usize = 1 << 28;
+
+fn half_to_f32(half: u16) -> f32 {
+    let sign = ((half & 0x8000) as u32) << 16;
+    let exponent = ((half >> 10) & 0x1f) as u32;
+    let mantissa = (half & 0x3ff) as u32;
+
+    let bits = match exponent {
+        0 if mantissa == 0 => sign,
+        // Subnormal halves are normal floats.
+        0 => {
+            let shift = mantissa.leading_zeros() - 21;
+            sign | ((113 - shift) << 23) | (((mantissa << shift) & 0x3ff) << 13)
+        }
+        0x1f => sign | 0x7f80_0000 | (mantissa << 13),
+        _ => sign | ((exponent + 112) << 23) | (mantissa << 13),
+    };
+
+    f32::from_bits(bits)
+}
+
+fn invalid_exr(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("EXR: {}", message))
+}
+
+struct ExrReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> ExrReader<'a> {
+    fn take(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        let end = self
+            .offset
+            .checked_add(count)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| invalid_exr("unexpected end of file"))?;
+
+        let bytes = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        let mut word = [0; 4];
+        word.copy_from_slice(self.take(4)?);
+        Ok(i32::from_le_bytes(word))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        let mut word = [0; 8];
+        word.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(word))
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let length = self
+            .bytes
+            .get(self.offset..)
+            .unwrap_or(&[])
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or_else(|| invalid_exr("unterminated string"))?;
+        let string = String::from_utf8_lossy(self.take(length)?).into_owned();
+        self.offset += 1;
+        Ok(string)
+    }
+}
+
+// Undoes the byte reordering and delta encoding applied before RLE and ZIP compression.
+fn unpredict(data: &mut Vec<u8>) {
+    for i in 1..data.len() {
+        data[i] = data[i - 1].wrapping_add(data[i]).wrapping_sub(128);
+    }
+
+    let half = data.len().div_ceil(2);
+    let mut interleaved = Vec::with_capacity(data.len());
+    for i in 0..half {
+        interleaved.push(data[i]);
+        if half + i < data.len() {
+            interleaved.push(data[half + i]);
+        }
+    }
+    *data = interleaved;
+}
+
+fn rle_decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut result = vec![];
+    let mut i = 0;
+    while i < data.len() {
+        let count = data[i] as i8;
+        i += 1;
+        if count < 0 {
+            let end = i + (-(count as i32)) as usize;
+            result.extend_from_slice(
+                data.get(i..end)
+                    .ok_or_else(|| invalid_exr("bad RLE data"))?,
+            );
+            i = end;
+        } else {
+            let value = *data.get(i).ok_or_else(|| invalid_exr("bad RLE data"))?;
+            result.extend(std::iter::repeat_n(value, count as usize + 1));
+            i += 1;
+        }
+    }
+
+    Ok(result)
+}
+
+// A channel name and its plane of values.
+pub type ExrChannel = (String, Vec<f32>);
+
+// Reads a single part scanline OpenEXR file, uncompressed or with RLE, ZIPS or ZIP compression, into one
+// plane of `width * height` values per channel, top row first. This is the inverse of `write_exr`.
+pub fn read_exr(path: &Path) -> io::Result<(usize, usize, Vec<ExrChannel>)> {
+    let bytes = fs::read(path)?;
+    let mut reader = ExrReader {
+        bytes: &bytes,
+        offset: 0,
+    };
+
+    if reader.take(4)? != [0x76, 0x2f, 0x31, 0x01] {
+        return Err(invalid_exr("not an OpenEXR file"));
+    }
+    if reader.i32()? & 0x1a00 != 0 {
+        return Err(invalid_exr("only single part scanline files are supported"));
+    }
+
+    let mut channels: Vec<(String, i32)> = vec![];
+    let mut compression = None;
+    let mut window = None;
+    loop {
+        let name = reader.string()?;
+        if name.is_empty() {
+            break;
+        }
+        reader.string()?;
+        let size = reader.i32()?.max(0) as usize;
+        let mut value = ExrReader {
+            bytes: reader.take(size)?,
+            offset: 0,
+        };
+
+        match name.as_str() {
+            "channels" => loop {
+                let channel = value.string()?;
+                if channel.is_empty() {
+                    break;
+                }
+                let pixel_type = value.i32()?;
+                value.take(4)?;
+                if value.i32()? != 1 || value.i32()? != 1 {
+                    return Err(invalid_exr("subsampled channels are not supported"));
+                }
+                channels.push((channel, pixel_type));
+            },
+            "compression" => compression = Some(value.take(1)?[0]),
+            "dataWindow" => {
+                window = Some([value.i32()?, value.i32()?, value.i32()?, value.i32()?]);
+            }
+            _ => {}
+        }
+    }
+
+    let [x_min, y_min, x_max, y_max] = window.ok_or_else(|| invalid_exr("missing dataWindow"))?;
+    let size = |min: i32, max: i32| {
+        max.checked_sub(min)
+            .and_then(|size| size.checked_add(1))
+            .filter(|size| *size > 0)
+            .map(|size| size as usize)
+    };
+    let (width, height) = match (size(x_min, x_max), size(y_min, y_max)) {
+        (Some(width), Some(height))
+            if width
+                .checked_mul(height)
+                .is_some_and(|pixels| pixels <= MAX_PIXELS) =>
+        {
+            (width, height)
+        }
+        _ => return Err(invalid_exr("empty or oversized dataWindow")),
+    };
+    let lines_per_block = match compression {
+        Some(0) | Some(1) | Some(2) => 1,
+        Some(3) => 16,
+        _ => return Err(invalid_exr("unsupported compression")),
+    };
+
+    let sample_size = |pixel_type: i32| if pixel_type == 1 { 2 } else { 4 };
+    let line_size: usize = channels.iter().map(|c| width * sample_size(c.1)).sum();
+
+    let blocks = height.div_ceil(lines_per_block);
+    let mut offsets = vec![];
+    for _ in 0..blocks {
+        offsets.push(reader.u64()? as usize);
+    }
+
+    let mut planes = vec![vec![0.0; width * height]; channels.len()];
+    for offset in offsets {
+        reader.offset = offset;
+        let y0 = match reader.i32()?.checked_sub(y_min) {
+            Some(y0) if y0 >= 0 && (y0 as usize) < height => y0 as usize,
+            _ => return Err(invalid_exr("scanline block outside the dataWindow")),
+        };
+        let size = reader.i32()?.max(0) as usize;
+        let data = reader.take(size)?;
+
+        let lines = lines_per_block.min(height.saturating_sub(y0));
+        let expected = lines * line_size;
+        let data = if size == expected {
+            data.to_vec()
+        } else {
+            let mut data = match compression {
+                Some(1) => rle_decompress(data)?,
+                _ => decompress_to_vec_zlib(data).map_err(|_| invalid_exr("bad ZIP data"))?,
+            };
+            unpredict(&mut data);
+            data
+        };
+        if data.len() < expected {
+            return Err(invalid_exr("truncated scanline block"));
+        }
+
+        let mut line = ExrReader {
+            bytes: &data,
+            offset: 0,
+        };
+        for y in y0..(y0 + lines) {
+            for (plane, (_, pixel_type)) in planes.iter_mut().zip(&channels) {
+                for value in &mut plane[(y * width)..((y + 1) * width)] {
+                    let sample = line.take(sample_size(*pixel_type))?;
+                    *value = match pixel_type {
+                        0 => {
+                            u32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]) as f32
+                        }
+                        1 => half_to_f32(u16::from_le_bytes([sample[0], sample[1]])),
+                        _ => f32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]),
+                    };
+                }
+            }
+        }
+    }
+
+    let channels = channels.into_iter().map(|c| c.0).zip(planes).collect();
+    Ok((width, height, channels))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::{write_exr, ExrPixelType};
+    use miniz_oxide::deflate::compress_to_vec_zlib;
+
+    fn u64_at(bytes: &[u8], offset: usize) -> usize {
+        let mut word = [0; 8];
+        word.copy_from_slice(&bytes[offset..(offset + 8)]);
+        u64::from_le_bytes(word) as usize
+    }
+
+    // The reordering and delta encoding `unpredict` undoes.
+    fn predict(data: &[u8]) -> Vec<u8> {
+        let mut reordered: Vec<u8> = data.iter().step_by(2).copied().collect();
+        reordered.extend(data.iter().skip(1).step_by(2));
+
+        let mut predicted = reordered.clone();
+        for i in 1..reordered.len() {
+            predicted[i] = reordered[i]
+                .wrapping_sub(reordered[i - 1])
+                .wrapping_add(128);
+        }
+        predicted
+    }
+
+    // Repeats runs of equal bytes and copies everything else as literals.
+    fn rle_compress(data: &[u8]) -> Vec<u8> {
+        let mut result = vec![];
+        let mut i = 0;
+        while i < data.len() {
+            let run = data[i..]
+                .iter()
+                .take(128)
+                .take_while(|b| **b == data[i])
+                .count();
+            if run > 2 {
+                result.push((run - 1) as u8);
+                result.push(data[i]);
+                i += run;
+            } else {
+                let count = (data.len() - i).min(127).min(run.max(1));
+                result.push((-(count as i8)) as u8);
+                result.extend_from_slice(&data[i..(i + count)]);
+                i += count;
+            }
+        }
+        result
+    }
+
+    // Rewrites an uncompressed file from `write_exr` with one line per block in another compression.
+    fn recompress(
+        bytes: &[u8],
+        height: usize,
+        compression: u8,
+        compress: fn(&[u8]) -> Vec<u8>,
+    ) -> Vec<u8> {
+        let attribute = b"compression\0compression\0\x01\0\0\0";
+        let at = bytes
+            .windows(attribute.len())
+            .position(|w| w == attribute)
+            .unwrap();
+        let table = (0..bytes.len())
+            .find(|offset| u64_at(bytes, *offset) == offset + 8 * height)
+            .unwrap();
+
+        let mut result = bytes[..(table + 8 * height)].to_vec();
+        result[at + attribute.len()] = compression;
+        let mut blocks = vec![];
+        for y in 0..height {
+            let offset = u64_at(bytes, table + 8 * y);
+            let size = i32::from_le_bytes([
+                bytes[offset + 4],
+                bytes[offset + 5],
+                bytes[offset + 6],
+                bytes[offset + 7],
+            ]);
+            let data = compress(&predict(&bytes[(offset + 8)..(offset + 8 + size as usize)]));
+
+            let start = result.len() + blocks.len();
+            result[(table + 8 * y)..(table + 8 * y + 8)]
+                .copy_from_slice(&(start as u64).to_le_bytes());
+            blocks.extend_from_slice(&(y as i32).to_le_bytes());
+            blocks.extend_from_slice(&(data.len() as i32).to_le_bytes());
+            blocks.extend_from_slice(&data);
+        }
+        result.extend(blocks);
+        result
+    }
+
+    #[test]
+    fn halves_convert_exactly() {
+        for half in 0..=u16::MAX {
+            let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+            let exponent = ((half >> 10) & 0x1f) as i32;
+            let mantissa = (half & 0x3ff) as f64;
+            let value = half_to_f32(half);
+            match exponent {
+                0 => assert_eq!(value as f64, sign * mantissa * 2f64.powi(-24)),
+                0x1f if mantissa == 0.0 => assert_eq!(value as f64, sign * f64::INFINITY),
+                0x1f => assert!(value.is_nan()),
+                _ => assert_eq!(
+                    value as f64,
+                    sign * (1.0 + mantissa / 1024.0) * 2f64.powi(exponent - 15)
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn compressed_files_read_like_uncompressed_ones() {
+        let (width, height) = (7, 4);
+        let channels: Vec<ExrChannel> = ["B", "G", "R"]
+            .iter()
+            .enumerate()
+            .map(|(c, name)| {
+                let plane = (0..(width * height))
+                    .map(|i| {
+                        if i % 3 == 0 {
+                            0.0
+                        } else {
+                            (i * (c + 1)) as f32 * 0.37
+                        }
+                    })
+                    .collect();
+                (name.to_string(), plane)
+            })
+            .collect();
+        let path = std::env::temp_dir().join(format!("exr-reader-{}.exr", std::process::id()));
+
+        for &pixel_type in &[ExrPixelType::Float, ExrPixelType::Half] {
+            write_exr(&path, width, height, &channels, pixel_type).unwrap();
+            let bytes = fs::read(&path).unwrap();
+            let expected = read_exr(&path).unwrap();
+
+            let rle = recompress(&bytes, height, 1, rle_compress);
+            let zip = recompress(&bytes, height, 2, |data| compress_to_vec_zlib(data, 6));
+            for compressed in &[rle, zip] {
+                assert_ne!(compressed, &bytes);
+                fs::write(&path, compressed).unwrap();
+                assert_eq!(read_exr(&path).unwrap(), expected);
+            }
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn malformed_files_are_rejected() {
+        let path = std::env::temp_dir().join(format!("exr-malformed-{}.exr", std::process::id()));
+        let channels = vec![("Y".to_string(), vec![1.0; 12])];
+        write_exr(&path, 4, 3, &channels, ExrPixelType::Float).unwrap();
+        let bytes = fs::read(&path).unwrap();
+
+        let mut not_exr = bytes.clone();
+        not_exr[0] = b'P';
+        for broken in &[
+            not_exr,
+            bytes[..(bytes.len() - 10)].to_vec(),
+            bytes[..40].to_vec(),
+        ] {
+            fs::write(&path, broken).unwrap();
+            let error = read_exr(&path).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,227 @@
+use super::output::{self, OutputFormat};
+use super::view::ViewTransform;
+use crate::color::Color;
+use crate::math::Vec3;
+use image::Rgb;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+pub type SharedBuffer = Arc<Mutex<AccumulationBuffer>>;
+
+// Sums of the first hit normal and distance of the camera rays. Only rays that hit something count towards
+// `hits`.
+struct AovBuffer {
+    normals: Vec<Vec3>,
+    depths: Vec<f64>,
+    hits: Vec<u32>,
+}
+
+// Running sums of linear radiance and the number of samples taken for every pixel. Render jobs add into it,
+// so each pass refines the result of the previous ones.
+pub struct AccumulationBuffer {
+    pub width: usize,
+    pub height: usize,
+    sums: Vec<Color>,
+    samples: Vec<u32>,
+    aovs: Option<AovBuffer>,
+}
+
+impl AccumulationBuffer {
+    pub fn new(width: usize, height: usize) -> AccumulationBuffer {
+        AccumulationBuffer {
+            width,
+            height,
+            sums: vec![Color::new(0.0, 0.0, 0.0, 0.0); width * height],
+            samples: vec![0; width * height],
+            aovs: None,
+        }
+    }
+
+    // Also keeps track of the normal and depth layers.
+    pub fn with_aovs(width: usize, height: usize) -> AccumulationBuffer {
+        AccumulationBuffer {
+            aovs: Some(AovBuffer {
+                normals: vec![Vec3::new(0.0, 0.0, 0.0); width * height],
+                depths: vec![0.0; width * height],
+                hits: vec![0; width * height],
+            }),
+            ..AccumulationBuffer::new(width, height)
+        }
+    }
+
+    pub fn has_aovs(&self) -> bool {
+        self.aovs.is_some()
+    }
+
+    pub fn add(&mut self, x: usize, y: usize, sum: Color, samples: u32) {
+        let index = x + y * self.width;
+        self.sums[index] = self.sums[index] + sum;
+        self.samples[index] += samples;
+    }
+
+    pub fn add_aovs(&mut self, x: usize, y: usize, normal: Vec3, depth: f64, hits: u32) {
+        let index = x + y * self.width;
+        if let Some(aovs) = &mut self.aovs {
+            aovs.normals[index] = aovs.normals[index] + normal;
+            aovs.depths[index] += depth;
+            aovs.hits[index] += hits;
+        }
+    }
+
+    pub fn samples(&self, x: usize, y: usize) -> u32 {
+        self.samples[x + y * self.width]
+    }
+
+    pub fn resolve(&self) -> Framebuffer {
+        let pixels = self
+            .sums
+            .iter()
+            .zip(&self.samples)
+            .map(|(sum, samples)| {
+                if *samples == 0 {
+                    Color::new(0.0, 0.0, 0.0, 1.0)
+                } else {
+                    let color = *sum * (1.0 / *samples as f32);
+                    Color::new(color.r, color.g, color.b, 1.0)
+                }
+            })
+            .collect();
+
+        let layers = match &self.aovs {
+            Some(aovs) => aovs.resolve(),
+            None => vec![],
+        };
+
+        Framebuffer {
+            width: self.width,
+            height: self.height,
+            pixels,
+            layers,
+        }
+    }
+}
+
+impl AovBuffer {
+    // Pixels where no camera ray hit anything get a zero normal and infinite depth.
+    fn resolve(&self) -> Vec<Layer> {
+        let mut normal = vec![vec![], vec![], vec![]];
+        let mut depth = vec![];
+
+        for ((sum, distance), hits) in self.normals.iter().zip(&self.depths).zip(&self.hits) {
+            if *hits == 0 {
+                normal.iter_mut().for_each(|channel| channel.push(0.0));
+                depth.push(f32::INFINITY);
+                continue;
+            }
+
+            let n = sum.normalize();
+            normal[0].push(n.x as f32);
+            normal[1].push(n.y as f32);
+            normal[2].push(n.z as f32);
+            depth.push((*distance / *hits as f64) as f32);
+        }
+
+        let mut normal = normal.into_iter();
+        vec![
+            Layer {
+                name: "normal".to_string(),
+                channels: ["X", "Y", "Z"]
+                    .iter()
+                    .map(|name| (name.to_string(), normal.next().unwrap()))
+                    .collect(),
+            },
+            Layer {
+                name: "depth".to_string(),
+                channels: vec![("Z".to_string(), depth)],
+            },
+        ]
+    }
+}
+
+// An extra image stored next to the colour, with one plane of values per named channel.
+pub struct Layer {
+    pub name: String,
+    pub channels: Vec<(String, Vec<f32>)>,
+}
+
+// The averaged image in linear, unclamped radiance, plus any extra layers the renderer produced.
+pub struct Framebuffer {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+    pub layers: Vec<Layer>,
+}
+
+impl Framebuffer {
+    pub fn to_argb(&self, view: &ViewTransform) -> Vec<u32> {
+        self.pixels
+            .iter()
+            .enumerate()
+            .map(|(i, pixel)| {
+                let [r, g, b] = view.to_rgb8(pixel, i % self.width, i / self.width);
+                u32::from_be_bytes([255, r, g, b])
+            })
+            .collect()
+    }
+
+    pub fn save_png(&self, path: &Path, view: &ViewTransform) -> image::ImageResult<()> {
+        let mut bytes: Vec<u8> = Vec::with_capacity(self.pixels.len() * 3);
+        for (i, pixel) in self.pixels.iter().enumerate() {
+            bytes.extend_from_slice(&view.to_rgb8(pixel, i % self.width, i / self.width));
+        }
+
+        image::save_buffer(
+            path,
+            &bytes,
+            self.width as u32,
+            self.height as u32,
+            image::ColorType::Rgb8,
+        )
+    }
+
+    // Only EXR files can hold the extra layers; the other formats store the colour alone. The view transform
+    // is only used for PNG files, the floating point formats keep the linear radiance.
+    pub fn save(
+        &self,
+        path: &Path,
+        format: OutputFormat,
+        view: &ViewTransform,
+    ) -> image::ImageResult<()> {
+        match format {
+            OutputFormat::Png => self.save_png(path, view),
+            OutputFormat::Exr(pixel_type) => {
+                let mut channels = vec![
+                    ("R".to_string(), self.pixels.iter().map(|p| p.r).collect()),
+                    ("G".to_string(), self.pixels.iter().map(|p| p.g).collect()),
+                    ("B".to_string(), self.pixels.iter().map(|p| p.b).collect()),
+                ];
+                for layer in &self.layers {
+                    for (name, values) in &layer.channels {
+                        channels.push((format!("{}.{}", layer.name, name), values.clone()));
+                    }
+                }
+
+                output::write_exr(path, self.width, self.height, &channels, pixel_type)?;
+                Ok(())
+            }
+            OutputFormat::Hdr => {
+                // Radiance files can't store negative values.
+                let pixels: Vec<Rgb<f32>> = self
+                    .pixels
+                    .iter()
+                    .map(|p| Rgb([p.r.max(0.0), p.g.max(0.0), p.b.max(0.0)]))
+                    .collect();
+
+                let file = BufWriter::new(File::create(path)?);
+                image::hdr::HDREncoder::new(file).encode(&pixels, self.width, self.height)
+            }
+            OutputFormat::Pfm => {
+                let pixels: Vec<[f32; 3]> = self.pixels.iter().map(|p| [p.r, p.g, p.b]).collect();
+                output::write_pfm(path, self.width, self.height, &pixels)?;
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use super::framebuffer::{AccumulationBuffer, Framebuffer, SharedBuffer};
+use super::render_chunk;
+use crate::scene::{Camera, Scene};
+use crossbeam_queue::SegQueue;
+use rand::prelude::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+pub type SharedScene = Arc<Scene>;
+
+#[derive(Copy, Clone)]
+pub struct Chunk {
+    pub x: usize,
+    pub y: usize,
+    pub w: usize,
+    pub h: usize,
+}
+
+#[derive(Copy, Clone)]
+pub struct RenderJob {
+    pub chunk: Chunk,
+    pub ms: u32,
+}
+
+// `samples` is the schedule of per-pixel sample counts to reach. Every pass only renders the samples missing
+// to its target, adding them to what the previous passes produced. `aovs` adds normal and depth layers to
+// the framebuffer.
+#[derive(Clone)]
+pub struct RenderSettings {
+    pub width: usize,
+    pub height: usize,
+    pub samples: Vec<u32>,
+    pub threads: usize,
+    pub chunk_width: usize,
+    pub chunk_height: usize,
+    pub aovs: bool,
+}
+
+impl RenderSettings {
+    pub fn new(width: usize, height: usize, samples: Vec<u32>) -> RenderSettings {
+        RenderSettings {
+            width,
+            height,
+            samples,
+            threads: 4,
+            chunk_width: 128,
+            chunk_height: 128,
+            aovs: false,
+        }
+    }
+}
+
+pub struct Renderer {
+    settings: RenderSettings,
+    buffer: SharedBuffer,
+    total_jobs: usize,
+    finished_jobs: Arc<AtomicUsize>,
+    workers: Vec<thread::JoinHandle<()>>,
+}
+
+impl Renderer {
+    // Splits the image into chunks and queues one job per chunk for every entry in the sample schedule, then
+    // starts the worker threads. Each pass only renders the samples missing to reach its entry and adds them
+    // to the linear accumulation buffer, so the image refines over time.
+    pub fn start(scene: Scene, camera: Camera, settings: RenderSettings) -> Renderer {
+        let scene: SharedScene = Arc::new(scene);
+        let buffer = if settings.aovs {
+            AccumulationBuffer::with_aovs(settings.width, settings.height)
+        } else {
+            AccumulationBuffer::new(settings.width, settings.height)
+        };
+        let buffer: SharedBuffer = Arc::new(Mutex::new(buffer));
+
+        let chunks_x = settings.width.div_ceil(settings.chunk_width);
+        let chunks_y = settings.height.div_ceil(settings.chunk_height);
+        let mut chunks: Vec<Chunk> = (0..(chunks_x * chunks_y))
+            .map(|i| Chunk {
+                x: (i % chunks_x) * settings.chunk_width,
+                y: (i / chunks_x) * settings.chunk_height,
+                w: settings.chunk_width,
+                h: settings.chunk_height,
+            })
+            .collect();
+
+        chunks.shuffle(&mut rand::thread_rng());
+
+        let job_queue = Arc::new(SegQueue::<RenderJob>::new());
+        let mut rendered = 0;
+        for target in &settings.samples {
+            if *target <= rendered {
+                continue;
+            }
+
+            for chunk in &chunks {
+                job_queue.push(RenderJob {
+                    chunk: *chunk,
+                    ms: *target - rendered,
+                });
+            }
+            rendered = *target;
+        }
+
+        let total_jobs = job_queue.len();
+        let finished_jobs = Arc::new(AtomicUsize::new(0));
+
+        let workers = (0..settings.threads.max(1))
+            .map(|_| {
+                let thread_scene = Arc::clone(&scene);
+                let thread_buffer = Arc::clone(&buffer);
+                let thread_queue = Arc::clone(&job_queue);
+                let thread_finished = Arc::clone(&finished_jobs);
+                let (width, height) = (settings.width, settings.height);
+
+                thread::spawn(move || {
+                    let mut rng = rand::thread_rng();
+                    while let Ok(job) = thread_queue.pop() {
+                        render_chunk(
+                            job.chunk,
+                            width,
+                            height,
+                            &camera,
+                            &thread_scene,
+                            &thread_buffer,
+                            &mut rng,
+                            job.ms,
+                        );
+
+                        thread_finished.fetch_add(1, Ordering::SeqCst);
+                    }
+                })
+            })
+            .collect();
+
+        Renderer {
+            settings,
+            buffer,
+            total_jobs,
+            finished_jobs,
+            workers,
+        }
+    }
+
+    pub fn render(
+        scene: Scene,
+        camera: Camera,
+        settings: RenderSettings,
+    ) -> thread::Result<Framebuffer> {
+        Renderer::start(scene, camera, settings).wait()
+    }
+
+    pub fn settings(&self) -> &RenderSettings {
+        &self.settings
+    }
+
+    pub fn progress(&self) -> (usize, usize) {
+        (self.finished_jobs.load(Ordering::SeqCst), self.total_jobs)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished_jobs.load(Ordering::SeqCst) >= self.total_jobs
+    }
+
+    pub fn snapshot(&self) -> Framebuffer {
+        self.buffer.lock().unwrap().resolve()
+    }
+
+    pub fn wait(self) -> thread::Result<Framebuffer> {
+        for worker in self.workers {
+            worker.join()?;
+        }
+
+        let framebuffer = self.buffer.lock().unwrap().resolve();
+        Ok(framebuffer)
+    }
+}
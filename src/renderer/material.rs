@@ -1,160 +1,394 @@
+use super::bsdf::{Bsdf, Lobe};
+use super::coating::Coating;
+use super::microfacet::Ggx;
+use super::principled::PrincipledLobe;
+use super::spectrum::Dispersion;
+use super::texture::Texture;
+use super::thin_film::ThinFilm;
 use crate::color::Color;
 use crate::math::{Ray, Vec3};
 use crate::objects::Intersection;
-use rand::prelude::*;
 
-fn random_unit_sphere(rng: &mut dyn RngCore) -> Vec3 {
-    let mut p: Vec3;
-    while {
-        p = 2.0 * Vec3::new(rng.gen(), rng.gen(), rng.gen()) - Vec3::new(1.0, 1.0, 1.0);
-
-        p.sqr_magnitude() >= 1.0
-    } {}
-
-    p
-}
-
-fn schlick(cosine: f64, index: f64) -> f64 {
-    let r0 = (1.0 - index) / (1.0 + index);
-    let r0 = r0 * r0;
-
-    r0 + (1.0 - r0) * ((1.0 - cosine).powf(5.0))
+fn albedo(texture: &Texture, intersection: &Intersection) -> Color {
+    texture.value(intersection.u, intersection.v, &intersection.position)
 }
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct Lambertian {
-    pub albedo: Color,
+    pub albedo: Texture,
 }
 
 impl Lambertian {
-    pub fn scatter(
-        &self,
-        ray: &Ray,
-        intersection: &Intersection,
-        rng: &mut dyn RngCore,
-    ) -> Option<(Color, Ray)> {
-        let target = intersection.position + intersection.normal + random_unit_sphere(rng);
-        let scattered = Ray::at_time(
-            intersection.position,
-            target - intersection.position,
-            ray.time,
-        );
-
-        Some((self.albedo, scattered))
+    pub fn bsdf(&self, intersection: &Intersection) -> Bsdf {
+        Bsdf::new(
+            &intersection.normal,
+            Lobe::Lambertian(albedo(&self.albedo, intersection)),
+        )
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct Metal {
-    pub albedo: Color,
+    pub albedo: Texture,
     pub fuzz: f64,
 }
 
 impl Metal {
-    pub fn new(albedo: Color, fuzz: f64) -> Metal {
+    pub fn new(albedo: Texture, fuzz: f64) -> Metal {
         Metal {
             albedo: albedo,
             fuzz: if fuzz < 1.0 { fuzz } else { 1.0 },
         }
     }
 
-    pub fn scatter(
-        &self,
-        ray: &Ray,
-        intersection: &Intersection,
-        rng: &mut dyn RngCore,
-    ) -> Option<(Color, Ray)> {
-        let reflection = Vec3::reflect(&ray.direction.normalize(), &intersection.normal);
-        let scattered = Ray::at_time(
-            intersection.position,
-            reflection + self.fuzz * random_unit_sphere(rng),
-            ray.time,
-        );
-
-        if Vec3::dot(&scattered.direction, &intersection.normal) > 0.0 {
-            Some((self.albedo, scattered))
-        } else {
-            None
-        }
+    pub fn bsdf(&self, intersection: &Intersection) -> Bsdf {
+        Bsdf::new(
+            &intersection.normal,
+            Lobe::Metal {
+                albedo: albedo(&self.albedo, intersection),
+                fuzz: self.fuzz,
+            },
+        )
+    }
+}
+
+// Measured complex indices of refraction of metals at the red, green and blue wavelengths.
+#[derive(Clone, Copy)]
+pub enum ConductorPreset {
+    Gold,
+    Copper,
+    Aluminium,
+    Silver,
+}
+
+impl ConductorPreset {
+    pub fn eta_k(&self) -> (Color, Color) {
+        let (eta, k) = match self {
+            ConductorPreset::Gold => ((0.143, 0.374, 1.442), (3.983, 2.385, 1.603)),
+            ConductorPreset::Copper => ((0.200, 0.924, 1.102), (3.912, 2.452, 2.142)),
+            ConductorPreset::Aluminium => ((1.657, 0.880, 0.521), (9.224, 6.270, 4.837)),
+            ConductorPreset::Silver => ((0.155, 0.117, 0.138), (4.828, 3.122, 2.147)),
+        };
+
+        (
+            Color::new(eta.0, eta.1, eta.2, 1.0),
+            Color::new(k.0, k.1, k.2, 1.0),
+        )
     }
 }
 
+// A metal whose microfacets follow the GGX distribution, reflecting with the Fresnel term of its complex
+// index of refraction. A `roughness` of zero is a perfect mirror.
+#[derive(Clone, Copy)]
+pub struct Conductor {
+    pub eta: Color,
+    pub k: Color,
+    pub roughness: f64,
+}
+
+impl Conductor {
+    pub fn preset(preset: ConductorPreset, roughness: f64) -> Conductor {
+        let (eta, k) = preset.eta_k();
+        Conductor { eta, k, roughness }
+    }
+
+    pub fn bsdf(&self, intersection: &Intersection) -> Bsdf {
+        Bsdf::new(
+            &intersection.normal,
+            Lobe::Conductor {
+                eta: self.eta,
+                k: self.k,
+                ggx: Ggx::from_roughness(self.roughness),
+            },
+        )
+    }
+}
+
+// Glass, water and other transparent materials. A `roughness` above zero frosts the surface, and light
+// travelling through the inside is absorbed by `absorption` per unit of distance, which tints thick glass
+// more than thin glass. In spectral mode `dispersion` replaces `index`, splitting white light into colours.
+// A `film` on the surface gives iridescent reflections, and with an `index` of 1 makes a soap bubble.
 #[derive(Clone, Copy)]
 pub struct Dialectric {
     pub index: f64,
+    pub roughness: f64,
+    pub absorption: Color,
+    pub dispersion: Option<Dispersion>,
+    pub film: Option<ThinFilm>,
 }
 
 impl Dialectric {
-    pub fn scatter(
-        &self,
-        ray: &Ray,
-        intersection: &Intersection,
-        rng: &mut dyn RngCore,
-    ) -> Option<(Color, Ray)> {
-        let reflected = Vec3::reflect(&ray.direction, &intersection.normal);
-
-        let dir = if Vec3::dot(&ray.direction, &intersection.normal) > 0.0 {
-            (
-                self.index,
-                -intersection.normal,
-                self.index
-                    * (Vec3::dot(&ray.direction, &intersection.normal) / ray.direction.magnitude()),
-            )
-        } else {
-            (
-                1.0 / self.index,
-                intersection.normal,
-                -Vec3::dot(&ray.direction, &intersection.normal) / ray.direction.magnitude(),
-            )
+    // Smooth and clear.
+    pub fn new(index: f64) -> Dialectric {
+        Dialectric {
+            index,
+            roughness: 0.0,
+            absorption: Color::new(0.0, 0.0, 0.0, 1.0),
+            dispersion: None,
+            film: None,
+        }
+    }
+
+    // `wavelength` is in nanometres, or `None` when rendering in RGB.
+    pub fn bsdf(&self, intersection: &Intersection, wavelength: Option<f64>) -> Bsdf {
+        let index = match (self.dispersion, wavelength) {
+            (Some(dispersion), Some(wavelength)) => dispersion.index(wavelength),
+            _ => self.index,
         };
 
-        let attenuation = Color::new(1.0, 1.0, 1.0, 1.0);
-
-        match dir {
-            (ni_over_nt, outward_normal, cosine) => {
-                if let Some(refracted) = Vec3::refract(&ray.direction, &outward_normal, ni_over_nt)
-                {
-                    let prob = schlick(cosine, self.index);
-                    if rng.gen::<f64>() < prob {
-                        Some((
-                            attenuation,
-                            Ray::at_time(intersection.position, reflected, ray.time),
-                        ))
-                    } else {
-                        Some((
-                            attenuation,
-                            Ray::at_time(intersection.position, refracted, ray.time),
-                        ))
-                    }
-                } else {
-                    Some((
-                        attenuation,
-                        Ray::at_time(intersection.position, reflected, ray.time),
-                    ))
-                }
+        Bsdf::new(
+            &intersection.normal,
+            Lobe::Dielectric {
+                index,
+                ggx: Ggx::from_roughness(self.roughness),
+                film: self.film,
+                wavelength,
+            },
+        )
+    }
+}
+
+// One material covering plastics, metals, glass and everything in between, following Disney's principled
+// BSDF and the glTF metallic-roughness conventions: `metallic` and `roughness` are multiplied by the blue
+// and green channels of the optional `metallic_roughness` texture. Parameters run from 0 to 1 except for
+// `ior`, and a `specular` of 0.5 is the reflectance of an index of 1.5.
+#[derive(Clone)]
+pub struct Principled {
+    pub base_color: Texture,
+    pub metallic: f64,
+    pub roughness: f64,
+    pub metallic_roughness: Option<Texture>,
+    pub specular: f64,
+    pub specular_tint: f64,
+    pub sheen: f64,
+    pub sheen_tint: f64,
+    pub clearcoat: f64,
+    pub clearcoat_roughness: f64,
+    pub transmission: f64,
+    pub ior: f64,
+    pub emission: Texture,
+    pub emission_strength: f32,
+}
+
+impl Principled {
+    pub fn new(base_color: Texture) -> Principled {
+        Principled {
+            base_color,
+            metallic: 0.0,
+            roughness: 0.5,
+            metallic_roughness: None,
+            specular: 0.5,
+            specular_tint: 0.0,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            clearcoat: 0.0,
+            clearcoat_roughness: 0.03,
+            transmission: 0.0,
+            ior: 1.5,
+            emission: Texture::Constant(Color::new(0.0, 0.0, 0.0, 1.0)),
+            emission_strength: 1.0,
+        }
+    }
+
+    pub fn bsdf(&self, intersection: &Intersection) -> Bsdf {
+        let (metallic, roughness) = match &self.metallic_roughness {
+            Some(texture) => {
+                let value = albedo(texture, intersection);
+                (
+                    self.metallic * value.b as f64,
+                    self.roughness * value.g as f64,
+                )
+            }
+            None => (self.metallic, self.roughness),
+        };
+
+        Bsdf::new(
+            &intersection.normal,
+            Lobe::Principled(PrincipledLobe::new(
+                self,
+                albedo(&self.base_color, intersection),
+                metallic,
+                roughness,
+            )),
+        )
+    }
+
+    pub fn emitted(&self, intersection: &Intersection) -> Color {
+        albedo(&self.emission, intersection) * self.emission_strength
+    }
+
+    // Only a constant black emission is known not to emit anywhere.
+    pub fn is_emissive(&self) -> bool {
+        match self.emission {
+            Texture::Constant(color) => {
+                self.emission_strength > 0.0 && color.r.max(color.g).max(color.b) > 0.0
             }
+            _ => self.emission_strength > 0.0,
         }
     }
 }
 
-#[derive(Copy, Clone)]
+// A clear coat over another material, like varnished wood or lacquered paint. The coat reflects with the
+// Fresnel term of its `index`, or of a thin `film` on top, and the base only receives what the coat lets
+// through, tinted by `tint` each way. The base's emission crosses the coat once on its way out.
+#[derive(Clone)]
+pub struct Coated {
+    pub index: f64,
+    pub roughness: f64,
+    pub tint: Color,
+    pub film: Option<ThinFilm>,
+    pub base: Box<Material>,
+}
+
+impl Coated {
+    fn coating(&self, wavelength: Option<f64>) -> Coating {
+        Coating {
+            index: self.index,
+            ggx: Ggx::from_roughness(self.roughness),
+            tint: self.tint,
+            film: self.film,
+            wavelength,
+        }
+    }
+
+    pub fn bsdf(&self, intersection: &Intersection, wavelength: Option<f64>) -> Option<Bsdf> {
+        let mut bsdf = self.base.bsdf(intersection, wavelength)?;
+        bsdf.coating = Some(self.coating(wavelength));
+        Some(bsdf)
+    }
+
+    pub fn emitted(&self, ray: &Ray, intersection: &Intersection) -> Color {
+        let cosine = Vec3::dot(&ray.direction.normalize(), &intersection.normal).abs();
+        self.base.emitted(ray, intersection) * self.coating(None).emission(cosine)
+    }
+}
+
+#[derive(Clone)]
+pub struct DiffuseLight {
+    pub emit: Texture,
+}
+
+impl DiffuseLight {
+    pub fn emitted(&self, intersection: &Intersection) -> Color {
+        self.emit
+            .value(intersection.u, intersection.v, &intersection.position)
+    }
+}
+
+// Phase function of participating media, scattering equally into every direction.
+#[derive(Clone)]
+pub struct Isotropic {
+    pub albedo: Texture,
+}
+
+impl Isotropic {
+    pub fn bsdf(&self, intersection: &Intersection) -> Bsdf {
+        Bsdf::new(
+            &intersection.normal,
+            Lobe::Phase {
+                albedo: albedo(&self.albedo, intersection),
+                g: 0.0,
+            },
+        )
+    }
+}
+
+// Anisotropic phase function of participating media. Positive `g` scatters mostly forward and negative `g`
+// mostly backward. `emission` is added at every scattering event, which is how glowing media like fire
+// are described.
+#[derive(Clone)]
+pub struct HenyeyGreenstein {
+    pub albedo: Texture,
+    pub g: f64,
+    pub emission: Texture,
+}
+
+impl HenyeyGreenstein {
+    pub fn bsdf(&self, intersection: &Intersection) -> Bsdf {
+        Bsdf::new(
+            &intersection.normal,
+            Lobe::Phase {
+                albedo: albedo(&self.albedo, intersection),
+                g: self.g,
+            },
+        )
+    }
+
+    pub fn emitted(&self, intersection: &Intersection) -> Color {
+        self.emission
+            .value(intersection.u, intersection.v, &intersection.position)
+    }
+}
+
+#[derive(Clone)]
 pub enum Material {
     Lambertian(Lambertian),
     Metal(Metal),
+    Conductor(Conductor),
     Dialectric(Dialectric),
+    DiffuseLight(DiffuseLight),
+    Isotropic(Isotropic),
+    HenyeyGreenstein(HenyeyGreenstein),
+    Principled(Principled),
+    Coated(Coated),
 }
 
 impl Material {
-    pub fn scatter(
-        &self,
-        ray: &Ray,
-        intersection: &Intersection,
-        rng: &mut dyn RngCore,
-    ) -> Option<(Color, Ray)> {
+    // The scattering function at the intersection for light of the given wavelength (in nanometres, or
+    // `None` in RGB mode), or `None` for materials that only emit light.
+    pub fn bsdf(&self, intersection: &Intersection, wavelength: Option<f64>) -> Option<Bsdf> {
+        match self {
+            Material::Lambertian(l) => Some(l.bsdf(intersection)),
+            Material::Metal(m) => Some(m.bsdf(intersection)),
+            Material::Conductor(c) => Some(c.bsdf(intersection)),
+            Material::Dialectric(d) => Some(d.bsdf(intersection, wavelength)),
+            Material::DiffuseLight(_) => None,
+            Material::Isotropic(i) => Some(i.bsdf(intersection)),
+            Material::HenyeyGreenstein(h) => Some(h.bsdf(intersection)),
+            Material::Principled(p) => Some(p.bsdf(intersection)),
+            Material::Coated(c) => c.bsdf(intersection, wavelength),
+        }
+    }
+
+    // Lights are sampled by next event estimation, so the integrator weighs their emission by multiple
+    // importance sampling. Other emitters, like glowing media, are only found by scattering.
+    pub fn is_light(&self) -> bool {
+        match self {
+            Material::DiffuseLight(_) => true,
+            Material::Principled(p) => p.is_emissive(),
+            Material::Coated(c) => c.base.is_light(),
+            _ => false,
+        }
+    }
+
+    // Whether the scattering depends on the wavelength, so spectral paths can only follow one wavelength
+    // through it.
+    pub fn is_dispersive(&self) -> bool {
+        match self {
+            Material::Dialectric(d) => d.dispersion.is_some() || d.film.is_some(),
+            Material::Coated(c) => c.film.is_some() || c.base.is_dispersive(),
+            _ => false,
+        }
+    }
+
+    // The absorption coefficient of the inside of a closed surface of this material, for materials that
+    // let light through.
+    pub fn absorption(&self) -> Option<Color> {
+        match self {
+            Material::Dialectric(d) => Some(d.absorption),
+            Material::Coated(c) => c.base.absorption(),
+            _ => None,
+        }
+    }
+
+    // The light leaving the intersection back along `ray`.
+    pub fn emitted(&self, ray: &Ray, intersection: &Intersection) -> Color {
         match self {
-            Material::Lambertian(l) => l.scatter(ray, intersection, rng),
-            Material::Metal(m) => m.scatter(ray, intersection, rng),
-            Material::Dialectric(d) => d.scatter(ray, intersection, rng),
+            Material::DiffuseLight(l) => l.emitted(intersection),
+            Material::HenyeyGreenstein(h) => h.emitted(intersection),
+            Material::Principled(p) => p.emitted(intersection),
+            Material::Coated(c) => c.emitted(ray, intersection),
+            _ => Color::new(0.0, 0.0, 0.0, 0.0),
         }
     }
 }
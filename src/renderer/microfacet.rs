@@ -0,0 +1,265 @@
+use crate::color::Color;
+use crate::math::Vec3;
+use std::f64::consts::PI;
+
+// Below this roughness the distribution is too sharp to evaluate and surfaces are treated as smooth.
+pub const MIN_ALPHA: f64 = 1e-3;
+
+// Isotropic GGX (Trowbridge-Reitz) distribution of microfacet normals in the local shading frame, with the
+// width `alpha` being the square of the perceptual roughness.
+#[derive(Copy, Clone)]
+pub struct Ggx {
+    pub alpha: f64,
+}
+
+impl Ggx {
+    pub fn from_roughness(roughness: f64) -> Ggx {
+        let roughness = roughness.clamp(0.0, 1.0);
+        Ggx {
+            alpha: roughness * roughness,
+        }
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.alpha < MIN_ALPHA
+    }
+
+    // Density of microfacets facing `h`.
+    pub fn d(&self, h: &Vec3) -> f64 {
+        if h.z <= 0.0 {
+            return 0.0;
+        }
+
+        let a2 = self.alpha * self.alpha;
+        let t = h.z * h.z * (a2 - 1.0) + 1.0;
+        a2 / (PI * t * t)
+    }
+
+    // Smith's auxiliary function, the ratio of hidden to visible microfacet area seen from `w`.
+    fn lambda(&self, w: &Vec3) -> f64 {
+        let cos2 = w.z * w.z;
+        if cos2 <= 0.0 {
+            return f64::INFINITY;
+        }
+
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+        0.5 * ((1.0 + self.alpha * self.alpha * tan2).sqrt() - 1.0)
+    }
+
+    pub fn g1(&self, w: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // Height correlated masking and shadowing.
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Samples a microfacet normal visible from `wo` (in the upper hemisphere) in proportion to its
+    // projected area, following Heitz's "Sampling the GGX Distribution of Visible Normals".
+    pub fn sample_visible(&self, wo: &Vec3, u1: f64, u2: f64) -> Vec3 {
+        let v = Vec3::new(self.alpha * wo.x, self.alpha * wo.y, wo.z).normalize();
+
+        let length2 = v.x * v.x + v.y * v.y;
+        let t1 = if length2 > 0.0 {
+            Vec3::new(-v.y, v.x, 0.0) / length2.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = Vec3::cross(&v, &t1);
+
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + v.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * r * phi.sin();
+        let n = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * v;
+
+        Vec3::new(self.alpha * n.x, self.alpha * n.y, n.z.max(0.0)).normalize()
+    }
+
+    // Density of `sample_visible` picking `h`.
+    pub fn pdf_visible(&self, wo: &Vec3, h: &Vec3) -> f64 {
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+
+        self.g1(wo) * Vec3::dot(wo, h).max(0.0) * self.d(h) / wo.z
+    }
+}
+
+// Unpolarized Fresnel reflectance of an interface between dielectrics, seen from the side where the light
+// arrives at an angle with the given cosine. `eta` is the ratio of the index on the far side to the index on
+// the near side, and light beyond the critical angle is reflected completely.
+pub fn fresnel_dielectric(cosine: f64, eta: f64) -> f64 {
+    let cos_i = cosine.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    0.5 * (parallel * parallel + perpendicular * perpendicular)
+}
+
+// Unpolarized Fresnel reflectance of a conductor with complex index of refraction `eta + i k` seen from air
+// at an angle with the given cosine.
+fn conductor_channel(cosine: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cosine * cosine;
+    let sin2 = 1.0 - cos2;
+    let (eta2, k2) = (eta * eta, k * k);
+
+    let t0 = eta2 - k2 - sin2;
+    let a2b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let t1 = a2b2 + cos2;
+    let a = (0.5 * (a2b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cosine * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    0.5 * (rp + rs)
+}
+
+pub fn fresnel_conductor(cosine: f64, eta: &Color, k: &Color) -> Color {
+    let cosine = cosine.clamp(0.0, 1.0);
+    let channel = |eta: f32, k: f32| conductor_channel(cosine, eta as f64, k as f64) as f32;
+
+    Color::new(
+        channel(eta.r, k.r),
+        channel(eta.g, k.g),
+        channel(eta.b, k.b),
+        1.0,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+
+    const ALPHAS: [f64; 3] = [0.2, 0.5, 1.0];
+
+    fn directions() -> Vec<Vec3> {
+        [0.0f64, 0.7, 1.3, 1.55]
+            .iter()
+            .map(|theta| Vec3::new(theta.sin(), 0.0, theta.cos()))
+            .collect()
+    }
+
+    // Integrates `f` over the upper hemisphere on a grid uniform in the cosine and the angle around +z.
+    fn integrate(f: impl Fn(&Vec3) -> f64) -> f64 {
+        let n = 400;
+        let mut total = 0.0;
+        for i in 0..n {
+            for j in 0..n {
+                let z = (i as f64 + 0.5) / n as f64;
+                let phi = 2.0 * PI * (j as f64 + 0.5) / n as f64;
+                let r = (1.0 - z * z).sqrt();
+                total += f(&Vec3::new(r * phi.cos(), r * phi.sin(), z));
+            }
+        }
+
+        2.0 * PI * total / (n * n) as f64
+    }
+
+    #[test]
+    fn projected_normals_cover_the_surface_once() {
+        for &alpha in &ALPHAS {
+            let ggx = Ggx { alpha };
+            let area = integrate(|h| ggx.d(h) * h.z);
+            assert!((area - 1.0).abs() < 0.01, "{}: {}", alpha, area);
+        }
+    }
+
+    #[test]
+    fn visible_normal_pdfs_integrate_to_one() {
+        for &alpha in &ALPHAS {
+            let ggx = Ggx { alpha };
+            for wo in directions() {
+                let integral = integrate(|h| ggx.pdf_visible(&wo, h));
+                assert!((integral - 1.0).abs() < 0.01, "{}: {}", alpha, integral);
+            }
+        }
+    }
+
+    // Compares how often sampled normals fall into bands of the cosine with the pdf integrated over them.
+    #[test]
+    fn visible_normals_follow_their_pdf() {
+        const SAMPLES: usize = 100_000;
+        const BANDS: usize = 8;
+        let mut rng = StdRng::seed_from_u64(1);
+
+        for &alpha in &ALPHAS {
+            let ggx = Ggx { alpha };
+            for wo in directions() {
+                let mut counts = [0; BANDS];
+                for _ in 0..SAMPLES {
+                    let h = ggx.sample_visible(&wo, rng.gen(), rng.gen());
+                    assert!((h.magnitude() - 1.0).abs() < 1e-9 && h.z >= 0.0);
+                    counts[((h.z * BANDS as f64) as usize).min(BANDS - 1)] += 1;
+                }
+
+                for (band, count) in counts.iter().enumerate() {
+                    let expected = integrate(|h| {
+                        let inside = ((h.z * BANDS as f64) as usize).min(BANDS - 1) == band;
+                        if inside {
+                            ggx.pdf_visible(&wo, h)
+                        } else {
+                            0.0
+                        }
+                    });
+                    let share = *count as f64 / SAMPLES as f64;
+                    assert!((share - expected).abs() < 0.01, "{} {}", share, expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn dielectric_fresnel_at_normal_incidence() {
+        for &eta in &[1.33f64, 1.5, 2.4] {
+            let expected = ((eta - 1.0) / (eta + 1.0)).powi(2);
+            assert!((fresnel_dielectric(1.0, eta) - expected).abs() < 1e-12);
+            assert!((fresnel_dielectric(1.0, 1.0 / eta) - expected).abs() < 1e-12);
+        }
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-12);
+    }
+
+    #[test]
+    fn dielectric_fresnel_reflects_beyond_the_critical_angle() {
+        assert_eq!(fresnel_dielectric(0.1, 1.0 / 1.5), 1.0);
+        assert!(fresnel_dielectric(0.1, 1.5) < 1.0);
+    }
+
+    #[test]
+    fn conductor_fresnel_at_normal_incidence() {
+        let eta = Color::new(0.2, 0.9, 1.1, 1.0);
+        let k = Color::new(3.9, 2.4, 1.9, 1.0);
+        let fresnel = fresnel_conductor(1.0, &eta, &k);
+
+        let channels = [
+            (fresnel.r, eta.r, k.r),
+            (fresnel.g, eta.g, k.g),
+            (fresnel.b, eta.b, k.b),
+        ];
+        for &(value, eta, k) in &channels {
+            let expected = ((eta - 1.0).powi(2) + k * k) / ((eta + 1.0).powi(2) + k * k);
+            assert!((value - expected).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn conductor_fresnel_without_absorption_is_dielectric() {
+        let eta = Color::new(1.5, 1.5, 1.5, 1.0);
+        let k = Color::new(0.0, 0.0, 0.0, 1.0);
+        for &cosine in &[1.0, 0.7, 0.3, 0.05] {
+            let value = fresnel_conductor(cosine, &eta, &k).r as f64;
+            assert!((value - fresnel_dielectric(cosine, 1.5)).abs() < 1e-5);
+        }
+    }
+}
@@ -1,28 +1,350 @@
+mod bsdf;
+mod coating;
+mod environment;
+mod exr_reader;
+mod framebuffer;
+mod jobs;
 mod material;
+mod microfacet;
+mod output;
+mod principled;
+mod sky;
+mod spectrum;
+mod texture;
+mod thin_film;
+mod view;
 
 use crate::color::Color;
 use crate::math::{Ray, Vec3};
-use crate::objects::Intersectable;
-use crate::scene::{Camera, Scene};
-use crate::{Chunk, SharedBuffer, SharedScene};
+use crate::objects::{Intersectable, Intersection};
+use crate::scene::{BounceLimits, Camera, Scene};
 use rand::prelude::*;
+use std::ops::{Add, Mul};
 
-pub use material::{Dialectric, Lambertian, Material, Metal};
+pub use bsdf::{Bsdf, BsdfSample, Lobe};
+pub use coating::Coating;
+pub use environment::{Environment, EnvironmentMap};
+pub use exr_reader::{read_exr, ExrChannel};
+pub use framebuffer::{AccumulationBuffer, Framebuffer, Layer, SharedBuffer};
+pub use jobs::{Chunk, RenderJob, RenderSettings, Renderer, SharedScene};
+pub use material::{
+    Coated, Conductor, ConductorPreset, Dialectric, DiffuseLight, HenyeyGreenstein, Isotropic,
+    Lambertian, Material, Metal, Principled,
+};
+pub use microfacet::Ggx;
+pub use output::{write_exr, write_pfm, ExrPixelType, OutputFormat};
+pub use principled::PrincipledLobe;
+pub use sky::Sky;
+pub use spectrum::{Dispersion, SampledSpectrum, SampledWavelengths};
+pub use texture::{ImageTexture, Perlin, Texture, WrapMode};
+pub use thin_film::ThinFilm;
+pub use view::{ToneMapping, ViewTransform};
 
-pub fn get_color(ray: &Ray, scene: &Scene, rng: &mut dyn RngCore, depth: u32) -> Color {
-    if let Some(i) = scene.intersect(ray, 0.001, std::f64::INFINITY) {
-        if depth >= scene.max_recursion {
-            return Color::new(0.0, 0.0, 0.0, 1.0);
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 {
+        a / (a + b)
+    } else {
+        0.0
+    }
+}
+
+// The light found by next event estimation, kept as separate factors because in spectral mode each is
+// converted on its own: the spectrum of a product of colours isn't the product of their spectra.
+struct DirectLight {
+    // The BSDF times the cosine, weighted against BSDF sampling and attenuated by volumes on the way.
+    bsdf: Color,
+    emitted: Color,
+    // What is left after absorption inside the glass the point lies in.
+    absorption: Color,
+}
+
+// Next event estimation: samples a direction towards one of the scene lights and weights the light it
+// sees against the chance of the BSDF sampling the same direction.
+fn sample_lights(
+    ray: &Ray,
+    i: &Intersection,
+    bsdf: &Bsdf,
+    absorption: Option<&Color>,
+    scene: &Scene,
+    rng: &mut dyn RngCore,
+) -> Option<DirectLight> {
+    if bsdf.is_specular() {
+        return None;
+    }
+
+    let direction = scene.random_direction(&i.position, ray.time, rng)?;
+
+    let wo = -ray.direction.normalize();
+    let wi = direction.normalize();
+    let light_ray = Ray::at_time(i.position, direction, ray.time);
+    let light_pdf = scene.pdf_value(&light_ray);
+    let scatter_pdf = bsdf.pdf(&wo, &wi);
+    if light_pdf <= 0.0 || scatter_pdf <= 0.0 {
+        return None;
+    }
+
+    // Directions that leave the scene see the environment, which only counts here when it is sampled.
+    let (emitted, distance) = match scene.intersect(&light_ray, 0.001, f64::INFINITY) {
+        Some(light) if light.material.is_light() => {
+            (light.material.emitted(&light_ray, &light), light.distance)
+        }
+        Some(_) => return None,
+        None if scene.environment.is_sampled() => {
+            (scene.environment.value(&direction), f64::INFINITY)
+        }
+        None => return None,
+    };
+
+    // Volumes don't block shadow rays, they only attenuate them.
+    let transmittance = scene.transmittance(&light_ray, 0.001, distance, rng);
+    let weight = power_heuristic(light_pdf, scatter_pdf) * transmittance / light_pdf;
+    let absorption = match absorption {
+        Some(absorption) => beer_lambert(absorption, distance * direction.magnitude()),
+        None => Color::new(1.0, 1.0, 1.0, 1.0),
+    };
+
+    Some(DirectLight {
+        bsdf: bsdf.eval(&wo, &wi) * weight,
+        emitted,
+        absorption,
+    })
+}
+
+// The fraction of light left after travelling `length` through a material with the given absorption.
+fn beer_lambert(absorption: &Color, length: f64) -> Color {
+    let channel = |a: f32| {
+        if a > 0.0 {
+            (-(a as f64) * length).exp() as f32
+        } else {
+            1.0
+        }
+    };
+    Color::new(
+        channel(absorption.r),
+        channel(absorption.g),
+        channel(absorption.b),
+        1.0,
+    )
+}
+
+// Paths are only ended by Russian roulette once they have made this many bounces.
+const ROULETTE_DEPTH: u32 = 3;
+
+// What paths carry: RGB colours, or in spectral mode the values of a spectrum at the sampled wavelengths.
+// Materials and lights are described in RGB and converted as the path meets them.
+trait Channels {
+    type Value: Copy
+        + Add<Output = Self::Value>
+        + Mul<Output = Self::Value>
+        + Mul<f64, Output = Self::Value>;
+
+    fn convert(&self, color: &Color) -> Self::Value;
+    fn max(value: &Self::Value) -> f64;
+    // The wavelength dispersive materials refract, in nanometres.
+    fn wavelength(&self) -> Option<f64>;
+    fn disperse(&mut self);
+}
+
+struct Rgb;
+
+impl Channels for Rgb {
+    type Value = Color;
+
+    fn convert(&self, color: &Color) -> Color {
+        *color
+    }
+
+    fn max(value: &Color) -> f64 {
+        value.r.max(value.g).max(value.b) as f64
+    }
+
+    fn wavelength(&self) -> Option<f64> {
+        None
+    }
+
+    fn disperse(&mut self) {}
+}
+
+impl Channels for SampledWavelengths {
+    type Value = SampledSpectrum;
+
+    fn convert(&self, color: &Color) -> SampledSpectrum {
+        self.upsample(color)
+    }
+
+    fn max(value: &SampledSpectrum) -> f64 {
+        value.0.iter().cloned().fold(0.0, f64::max)
+    }
+
+    fn wavelength(&self) -> Option<f64> {
+        Some(self.hero())
+    }
+
+    fn disperse(&mut self) {
+        self.terminate_secondary();
+    }
+}
+
+// The light arriving at the camera along `ray`. In spectral mode every sample traces its own set of
+// wavelengths, which the result is converted from.
+pub fn get_color(ray: &Ray, scene: &Scene, rng: &mut dyn RngCore) -> Color {
+    if scene.spectral {
+        let mut wavelengths = SampledWavelengths::sample(rng.gen());
+        let radiance = trace(ray, scene, &mut wavelengths, rng);
+        wavelengths.to_color(&radiance)
+    } else {
+        trace(ray, scene, &mut Rgb, rng)
+    }
+}
+
+// Follows a path from the camera, adding up the light found at every vertex weighted by the throughput,
+// the fraction of it that the earlier bounces pass on towards the camera. Each kind of bounce has its own
+// limit, so long chains of glass aren't cut short by the diffuse limit.
+fn trace<C: Channels>(
+    ray: &Ray,
+    scene: &Scene,
+    channels: &mut C,
+    rng: &mut dyn RngCore,
+) -> C::Value {
+    let mut ray = Ray::at_time(ray.origin, ray.direction, ray.time);
+    let mut radiance = channels.convert(&Color::new(0.0, 0.0, 0.0, 0.0));
+    let mut throughput = channels.convert(&Color::new(1.0, 1.0, 1.0, 1.0));
+    let mut bounces = Bounces::default();
+
+    // The density with which the previous bounce picked `ray`, or `None` when it came from the camera or a
+    // specular bounce, in which case emitters hit by the ray count in full.
+    let mut scatter_pdf: Option<f64> = None;
+
+    // The absorption of the transparent materials the ray is inside, innermost last. Paths enter and leave
+    // closed surfaces in nested order, so leaving one drops the innermost.
+    let mut interiors: Vec<Color> = Vec::new();
+
+    loop {
+        let i = match scene.hit(&ray, 0.001, f64::INFINITY, rng) {
+            Some(i) => i,
+            None => {
+                // Sampled environments share the light mixture, so scattered rays reaching them are weighted
+                // like rays hitting a light.
+                let background = scene.environment.value(&ray.direction);
+                let background = match scatter_pdf {
+                    Some(pdf) if scene.environment.is_sampled() => {
+                        background * power_heuristic(pdf, scene.pdf_value(&ray))
+                    }
+                    _ => background,
+                };
+                return radiance + throughput * channels.convert(&background);
+            }
+        };
+
+        if let Some(absorption) = interiors.last() {
+            let transmittance = beer_lambert(absorption, i.distance * ray.direction.magnitude());
+            throughput = throughput * channels.convert(&transmittance);
+        }
+
+        // Only lights are sampled directly, other emitters such as glowing media count in full.
+        let emitted = match scatter_pdf {
+            Some(pdf) if i.material.is_light() => {
+                i.material.emitted(&ray, &i) * power_heuristic(pdf, scene.pdf_value(&ray))
+            }
+            _ => i.material.emitted(&ray, &i),
+        };
+        radiance = radiance + throughput * channels.convert(&emitted);
+
+        if bounces.total >= scene.max_recursion {
+            return radiance;
+        }
+
+        if i.material.is_dispersive() {
+            channels.disperse();
         }
+        let bsdf = match i.material.bsdf(&i, channels.wavelength()) {
+            Some(bsdf) => bsdf,
+            None => return radiance,
+        };
 
-        if let Some(s) = i.material.scatter(ray, &i, rng) {
-            return s.0 * get_color(&s.1, scene, rng, depth + 1);
+        if let Some(light) = sample_lights(&ray, &i, &bsdf, interiors.last(), scene, rng) {
+            let direct = channels.convert(&light.bsdf)
+                * channels.convert(&light.emitted)
+                * channels.convert(&light.absorption);
+            radiance = radiance + throughput * direct;
+        }
+
+        let wo = -ray.direction.normalize();
+        let sample = match bsdf.sample(&wo, rng) {
+            Some(sample) => sample,
+            None => return radiance,
+        };
+
+        scatter_pdf = if sample.specular {
+            None
         } else {
-            return Color::new(0.0, 0.0, 0.0, 1.0);
+            Some(sample.pdf)
+        };
+        let kind = if sample.transmitted {
+            Bounce::Transmission
+        } else if sample.specular {
+            Bounce::Specular
+        } else {
+            Bounce::Diffuse
+        };
+        if sample.transmitted {
+            if let Some(absorption) = i.material.absorption() {
+                if Vec3::dot(&wo, &i.normal) > 0.0 {
+                    interiors.push(absorption);
+                } else {
+                    interiors.pop();
+                }
+            }
+        }
+        if !bounces.add(kind, &scene.max_bounces) {
+            return radiance;
         }
+
+        throughput = throughput * channels.convert(&sample.weight);
+
+        // Paths carrying little light are ended at random, and the survivors are boosted to make up for the
+        // ones that were ended.
+        if bounces.total > ROULETTE_DEPTH {
+            let survival = C::max(&throughput).min(0.95);
+            if rng.gen::<f64>() >= survival {
+                return radiance;
+            }
+            throughput = throughput * (1.0 / survival);
+        }
+
+        ray = Ray::at_time(i.position, sample.direction, ray.time);
     }
+}
+
+#[derive(Copy, Clone)]
+enum Bounce {
+    Diffuse,
+    Specular,
+    Transmission,
+}
+
+#[derive(Default)]
+struct Bounces {
+    total: u32,
+    diffuse: u32,
+    specular: u32,
+    transmission: u32,
+}
 
-    color_from_direction(ray)
+impl Bounces {
+    // Counts the bounce, returning false if that goes over its limit.
+    fn add(&mut self, kind: Bounce, limits: &BounceLimits) -> bool {
+        self.total += 1;
+        let (count, limit) = match kind {
+            Bounce::Diffuse => (&mut self.diffuse, limits.diffuse),
+            Bounce::Specular => (&mut self.specular, limits.specular),
+            Bounce::Transmission => (&mut self.transmission, limits.transmission),
+        };
+
+        *count += 1;
+        *count <= limit
+    }
 }
 
 pub fn color_from_direction(ray: &Ray) -> Color {
@@ -52,7 +374,9 @@ pub fn render_chunk(
     let wr = 1.0 / width as f32;
     let hr = 1.0 / height as f32;
 
-    let mut result = vec![0; cp.w * cp.h];
+    let mut result = vec![Color::new(0.0, 0.0, 0.0, 0.0); cp.w * cp.h];
+    let mut aovs = vec![(Vec3::new(0.0, 0.0, 0.0), 0.0, 0); cp.w * cp.h];
+    let with_aovs = buffer.lock().unwrap().has_aovs();
 
     let fw = cp.x + cp.w;
     let fh = cp.y + cp.h;
@@ -62,25 +386,35 @@ pub fn render_chunk(
 
     for x in 0..cw {
         for y in 0..ch {
-            let mut color = Color::new(0.0, 0.0, 0.0, 1.0);
+            let mut color = Color::new(0.0, 0.0, 0.0, 0.0);
             for _s in 0..ms {
                 let u = (((cp.x + x) as f32) + rng.gen::<f32>()) * wr;
                 let v = 1.0 - (((cp.y + y) as f32) + rng.gen::<f32>()) * hr;
 
                 let ray = camera.get_ray(u, v, rng);
-                color = color + get_color(&ray, scene, rng, 0);
-            }
+                color = color + get_color(&ray, scene, rng);
 
-            color = color * (1.0 / (ms as f32));
+                if with_aovs {
+                    if let Some(hit) = scene.intersect(&ray, 0.001, f64::INFINITY) {
+                        let aov = &mut aovs[x + y * cw];
+                        aov.0 = aov.0 + hit.normal;
+                        aov.1 += hit.distance * ray.direction.magnitude();
+                        aov.2 += 1;
+                    }
+                }
+            }
 
-            result[x + y * cw] = color.into_pixel();
+            result[x + y * cw] = color;
         }
     }
     {
         let mut buffer = buffer.lock().unwrap();
         for x in 0..cw {
             for y in 0..ch {
-                buffer[(x + cp.x) + (y + cp.y) * width] = result[x + y * cw];
+                buffer.add(x + cp.x, y + cp.y, result[x + y * cw], ms);
+
+                let (normal, depth, hits) = aovs[x + y * cw];
+                buffer.add_aovs(x + cp.x, y + cp.y, normal, depth, hits);
             }
         }
     }
@@ -0,0 +1,310 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ExrPixelType {
+    Half,
+    Float,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OutputFormat {
+    Png,
+    Exr(ExrPixelType),
+    Hdr,
+    Pfm,
+}
+
+impl OutputFormat {
+    // Picks the format from the file extension. EXR files are written with `exr_type` samples.
+    pub fn from_path(path: &Path, exr_type: ExrPixelType) -> Option<OutputFormat> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+
+        match extension.as_str() {
+            "png" => Some(OutputFormat::Png),
+            "exr" => Some(OutputFormat::Exr(exr_type)),
+            "hdr" => Some(OutputFormat::Hdr),
+            "pfm" => Some(OutputFormat::Pfm),
+            _ => None,
+        }
+    }
+}
+
+// Rounds to the nearest half precision value, with ties going to even. Values too large for a half become
+// infinity and values too small become (signed) zero or a subnormal.
+fn f32_to_half(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        let half = mantissa >> shift;
+        let remainder = mantissa & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        let round = remainder > halfway || (remainder == halfway && half & 1 == 1);
+        return sign | (half + round as u32) as u16;
+    }
+
+    // A carry out of the mantissa correctly bumps the exponent, up to infinity.
+    let half = ((exponent as u32) << 10) | (mantissa >> 13);
+    let remainder = mantissa & 0x1fff;
+    let round = remainder > 0x1000 || (remainder == 0x1000 && half & 1 == 1);
+    sign | (half + round as u32) as u16
+}
+
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+// Writes a single part, uncompressed, scanline OpenEXR file. `channels` holds one plane of
+// `width * height` values per channel, stored top row first. Layers are expressed through channel names
+// such as `normal.X`.
+pub fn write_exr(
+    path: &Path,
+    width: usize,
+    height: usize,
+    channels: &[(String, Vec<f32>)],
+    pixel_type: ExrPixelType,
+) -> io::Result<()> {
+    // Readers expect the channel list, and the channel data in every scanline, sorted by name.
+    let mut channels: Vec<&(String, Vec<f32>)> = channels.iter().collect();
+    channels.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let (type_id, sample_size) = match pixel_type {
+        ExrPixelType::Half => (1i32, 2),
+        ExrPixelType::Float => (2i32, 4),
+    };
+
+    let mut channel_list = vec![];
+    for (name, _) in &channels {
+        channel_list.extend_from_slice(name.as_bytes());
+        channel_list.push(0);
+        channel_list.extend_from_slice(&type_id.to_le_bytes());
+        channel_list.extend_from_slice(&[0, 0, 0, 0]);
+        channel_list.extend_from_slice(&1i32.to_le_bytes());
+        channel_list.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channel_list.push(0);
+
+    let mut window = vec![];
+    for value in &[0, 0, width as i32 - 1, height as i32 - 1] {
+        window.extend_from_slice(&value.to_le_bytes());
+    }
+
+    let long_names = channels.iter().any(|(name, _)| name.len() > 31);
+
+    let mut header = vec![0x76, 0x2f, 0x31, 0x01];
+    header.extend_from_slice(&(2u32 | if long_names { 0x400 } else { 0 }).to_le_bytes());
+    attribute(&mut header, "channels", "chlist", &channel_list);
+    attribute(&mut header, "compression", "compression", &[0]);
+    attribute(&mut header, "dataWindow", "box2i", &window);
+    attribute(&mut header, "displayWindow", "box2i", &window);
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    header.push(0);
+
+    let mut file = BufWriter::new(File::create(path)?);
+    file.write_all(&header)?;
+
+    let line_size = width * channels.len() * sample_size;
+    let first_line = header.len() + height * 8;
+    for y in 0..height {
+        let offset = (first_line + y * (8 + line_size)) as u64;
+        file.write_all(&offset.to_le_bytes())?;
+    }
+
+    for y in 0..height {
+        file.write_all(&(y as i32).to_le_bytes())?;
+        file.write_all(&(line_size as i32).to_le_bytes())?;
+
+        for (_, values) in &channels {
+            for value in &values[(y * width)..((y + 1) * width)] {
+                match pixel_type {
+                    ExrPixelType::Half => file.write_all(&f32_to_half(*value).to_le_bytes())?,
+                    ExrPixelType::Float => file.write_all(&value.to_le_bytes())?,
+                }
+            }
+        }
+    }
+
+    file.flush()
+}
+
+// Writes a colour Portable Float Map. The negative scale marks the data as little endian, and rows are
+// stored bottom to top.
+pub fn write_pfm(path: &Path, width: usize, height: usize, rgb: &[[f32; 3]]) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write!(file, "PF\n{} {}\n-1.0\n", width, height)?;
+
+    for y in (0..height).rev() {
+        for pixel in &rgb[(y * width)..((y + 1) * width)] {
+            for value in pixel {
+                file.write_all(&value.to_le_bytes())?;
+            }
+        }
+    }
+
+    file.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::read_exr;
+    use std::fs;
+
+    #[test]
+    fn halves_round_to_nearest_even() {
+        assert_eq!(f32_to_half(1.0), 0x3c00);
+        assert_eq!(f32_to_half(-2.0), 0xc000);
+        assert_eq!(f32_to_half(65504.0), 0x7bff);
+
+        // Halfway between two halves the even mantissa wins, anything past it rounds up.
+        let ulp = 2f32.powi(-10);
+        assert_eq!(f32_to_half(1.0 + 0.5 * ulp), 0x3c00);
+        assert_eq!(f32_to_half(1.0 + 1.5 * ulp), 0x3c02);
+        assert_eq!(f32_to_half(1.0 + 0.5 * ulp + 2f32.powi(-20)), 0x3c01);
+        assert_eq!(f32_to_half(2.0 - 0.5 * ulp), 0x4000);
+    }
+
+    #[test]
+    fn small_values_become_subnormals() {
+        let tiny = 2f32.powi(-24);
+        assert_eq!(f32_to_half(tiny), 0x0001);
+        assert_eq!(f32_to_half(-tiny), 0x8001);
+        assert_eq!(f32_to_half(1.5 * tiny), 0x0002);
+        assert_eq!(f32_to_half(0.5 * tiny), 0x0000);
+        assert_eq!(f32_to_half(0.5 * tiny * 1.01), 0x0001);
+        assert_eq!(f32_to_half(-2f32.powi(-30)), 0x8000);
+        assert_eq!(f32_to_half(1023.0 * tiny), 0x03ff);
+        assert_eq!(f32_to_half(1023.5 * tiny), 0x0400);
+        assert_eq!(f32_to_half(2f32.powi(-14)), 0x0400);
+    }
+
+    #[test]
+    fn large_values_overflow_to_infinity() {
+        assert_eq!(f32_to_half(65519.0), 0x7bff);
+        assert_eq!(f32_to_half(65520.0), 0x7c00);
+        assert_eq!(f32_to_half(1.0e6), 0x7c00);
+        assert_eq!(f32_to_half(-1.0e6), 0xfc00);
+        assert_eq!(f32_to_half(f32::INFINITY), 0x7c00);
+        assert_eq!(f32_to_half(f32::NEG_INFINITY), 0xfc00);
+    }
+
+    #[test]
+    fn nan_stays_nan() {
+        let half = f32_to_half(f32::NAN);
+        assert_eq!(half & 0x7c00, 0x7c00);
+        assert_ne!(half & 0x3ff, 0);
+    }
+
+    fn channels(width: usize, height: usize) -> Vec<(String, Vec<f32>)> {
+        let plane = |scale: f32| {
+            (0..(width * height))
+                .map(|i| scale * (i as f32 - 3.0))
+                .collect::<Vec<f32>>()
+        };
+        vec![
+            ("R".to_string(), plane(0.25)),
+            ("G".to_string(), plane(-1.5)),
+            ("B".to_string(), plane(1024.0)),
+            ("depth.Z".to_string(), plane(0.001)),
+        ]
+    }
+
+    #[test]
+    fn exr_files_read_back() {
+        let (width, height) = (5, 3);
+        let written = channels(width, height);
+        let path = std::env::temp_dir().join(format!("output-{}.exr", std::process::id()));
+
+        for &pixel_type in &[ExrPixelType::Float, ExrPixelType::Half] {
+            write_exr(&path, width, height, &written, pixel_type).unwrap();
+            let (read_width, read_height, mut read) = read_exr(&path).unwrap();
+            assert_eq!((read_width, read_height), (width, height));
+
+            read.sort_by(|a, b| a.0.cmp(&b.0));
+            let mut expected = written.clone();
+            expected.sort_by(|a, b| a.0.cmp(&b.0));
+            for ((name, values), (expected_name, expected_values)) in read.iter().zip(&expected) {
+                assert_eq!(name, expected_name);
+                for (value, expected) in values.iter().zip(expected_values) {
+                    let tolerance = match pixel_type {
+                        ExrPixelType::Float => 0.0,
+                        ExrPixelType::Half => expected.abs() * 2f32.powi(-11),
+                    };
+                    assert!(
+                        (value - expected).abs() <= tolerance,
+                        "{} {}",
+                        value,
+                        expected
+                    );
+                }
+            }
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn pfm_files_read_back() {
+        let (width, height) = (4, 3);
+        let rgb: Vec<[f32; 3]> = (0..(width * height))
+            .map(|i| [i as f32, -0.5 * i as f32, 1.0e-3 * i as f32])
+            .collect();
+        let path = std::env::temp_dir().join(format!("output-{}.pfm", std::process::id()));
+        write_pfm(&path, width, height, &rgb).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let header = format!("PF\n{} {}\n-1.0\n", width, height);
+        assert!(bytes.starts_with(header.as_bytes()));
+        let values: Vec<f32> = bytes[header.len()..]
+            .chunks(4)
+            .map(|word| f32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect();
+        assert_eq!(values.len(), width * height * 3);
+
+        // Rows are stored bottom to top.
+        for y in 0..height {
+            for x in 0..width {
+                let stored = 3 * ((height - 1 - y) * width + x);
+                assert_eq!(&values[stored..(stored + 3)], &rgb[y * width + x]);
+            }
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use super::bsdf::{cosine_hemisphere, upper, BsdfSample, Lobe};
+use super::material::Principled;
+use super::microfacet::{Ggx, MIN_ALPHA};
+use crate::color::Color;
+use crate::math::Vec3;
+use rand::prelude::*;
+use std::f64::consts::PI;
+
+fn mix(a: &Color, b: &Color, t: f64) -> Color {
+    *a * (1.0 - t) + *b * t
+}
+
+fn schlick_weight(cosine: f64) -> f64 {
+    (1.0 - cosine.clamp(0.0, 1.0)).powi(5)
+}
+
+// Smooth surfaces are given the narrowest distribution that can still be evaluated, so every lobe can be
+// mixed with the others and sampled by next event estimation.
+fn rough_ggx(roughness: f64) -> Ggx {
+    Ggx {
+        alpha: Ggx::from_roughness(roughness).alpha.max(MIN_ALPHA),
+    }
+}
+
+// The Disney principled BSDF with its parameters evaluated at a point: a Burley diffuse lobe with sheen, a
+// GGX specular lobe whose Fresnel colour blends from a tinted dielectric reflectance to the base colour as
+// the surface becomes metallic, a rough dielectric for transmission tinted by the base colour, and a clear
+// coat on top. Directions are sampled by picking one lobe and weighted by the density of all of them.
+#[derive(Copy, Clone)]
+pub struct PrincipledLobe {
+    base_color: Color,
+    roughness: f64,
+    specular: Ggx,
+    specular_color: Color,
+    sheen_color: Color,
+    clearcoat: f64,
+    clearcoat_ggx: Ggx,
+    ior: f64,
+
+    diffuse_weight: f64,
+    specular_weight: f64,
+    transmission_weight: f64,
+}
+
+impl PrincipledLobe {
+    // The parameters of `principled` with its base colour, metallic and roughness already looked up at the
+    // shading point.
+    pub fn new(
+        principled: &Principled,
+        base_color: Color,
+        metallic: f64,
+        roughness: f64,
+    ) -> PrincipledLobe {
+        let metallic = metallic.clamp(0.0, 1.0);
+        let transmission = principled.transmission.clamp(0.0, 1.0);
+
+        let white = Color::new(1.0, 1.0, 1.0, 1.0);
+        let tint = match base_color.luminance() {
+            l if l > 0.0 => base_color * (1.0 / l),
+            _ => white,
+        };
+        let dielectric =
+            mix(&white, &tint, principled.specular_tint) * (0.08 * principled.specular);
+
+        PrincipledLobe {
+            base_color,
+            roughness,
+            specular: rough_ggx(roughness),
+            specular_color: mix(&dielectric, &base_color, metallic),
+            sheen_color: mix(&white, &tint, principled.sheen_tint) * principled.sheen,
+            clearcoat: 0.25 * principled.clearcoat.clamp(0.0, 1.0),
+            clearcoat_ggx: rough_ggx(principled.clearcoat_roughness),
+            ior: principled.ior,
+            diffuse_weight: (1.0 - metallic) * (1.0 - transmission),
+            specular_weight: 1.0 - (1.0 - metallic) * transmission,
+            transmission_weight: (1.0 - metallic) * transmission,
+        }
+    }
+
+    fn transmission(&self) -> Lobe {
+        Lobe::Dielectric {
+            index: self.ior,
+            ggx: self.specular,
+            film: None,
+            wavelength: None,
+        }
+    }
+
+    // Chances of sampling the diffuse, specular, transmission and clear coat lobes.
+    fn probabilities(&self) -> [f64; 4] {
+        let weights = [
+            self.diffuse_weight,
+            self.specular_weight,
+            self.transmission_weight,
+            self.clearcoat,
+        ];
+        let total: f64 = weights.iter().sum();
+
+        [
+            weights[0] / total,
+            weights[1] / total,
+            weights[2] / total,
+            weights[3] / total,
+        ]
+    }
+
+    // The diffuse, sheen, specular and clear coat lobes reflect on both sides of the surface, the
+    // transmission lobe also passes through it.
+    pub(super) fn eval(&self, wo: &Vec3, wi: &Vec3) -> Color {
+        let mut value = self.transmission().eval(wo, wi) * self.transmission_weight;
+        if wo.z * wi.z < 0.0 {
+            return value * self.base_color;
+        }
+
+        let (wo, wi) = (upper(wo), upper(wi));
+        if wo.z == 0.0 || wi.z == 0.0 {
+            return value;
+        }
+        let h = (wo + wi).normalize();
+        let cos_d = Vec3::dot(&wi, &h);
+
+        if self.diffuse_weight > 0.0 {
+            let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+            let fl = 1.0 + (fd90 - 1.0) * schlick_weight(wi.z);
+            let fv = 1.0 + (fd90 - 1.0) * schlick_weight(wo.z);
+            let diffuse =
+                self.base_color * (fl * fv / PI) + self.sheen_color * schlick_weight(cos_d);
+            value = value + diffuse * (self.diffuse_weight * wi.z);
+        }
+
+        let fh = schlick_weight(cos_d);
+        let white = Color::new(1.0, 1.0, 1.0, 1.0);
+        let fresnel = mix(&self.specular_color, &white, fh);
+        let specular = self.specular.d(&h) * self.specular.g(&wo, &wi) / (4.0 * wo.z);
+        value = value + fresnel * (self.specular_weight * specular);
+
+        if self.clearcoat > 0.0 {
+            let fresnel = 0.04 + 0.96 * fh;
+            let coat = self.clearcoat_ggx.d(&h) * self.clearcoat_ggx.g(&wo, &wi) / (4.0 * wo.z);
+            value = value + white * (self.clearcoat * fresnel * coat);
+        }
+
+        value
+    }
+
+    pub(super) fn pdf(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        let p = self.probabilities();
+        let mut pdf = p[2] * self.transmission().pdf(wo, wi);
+        if wo.z * wi.z <= 0.0 {
+            return pdf;
+        }
+
+        let (wo, wi) = (upper(wo), upper(wi));
+        let h = (wo + wi).normalize();
+        let cos_o = Vec3::dot(&wo, &h);
+        if cos_o <= 0.0 {
+            return pdf;
+        }
+
+        pdf += p[0] * wi.z / PI;
+        pdf += p[1] * self.specular.pdf_visible(&wo, &h) / (4.0 * cos_o);
+        pdf += p[3] * self.clearcoat_ggx.pdf_visible(&wo, &h) / (4.0 * cos_o);
+        pdf
+    }
+
+    pub(super) fn sample(&self, wo: &Vec3, rng: &mut dyn RngCore) -> Option<BsdfSample> {
+        let p = self.probabilities();
+        let u = rng.gen::<f64>();
+
+        let wi = if u < p[2] {
+            self.transmission().sample(wo, rng)?.direction
+        } else {
+            let flip = if wo.z < 0.0 { -1.0 } else { 1.0 };
+            let up = upper(wo);
+            let wi = if u < p[2] + p[0] {
+                cosine_hemisphere(rng)
+            } else {
+                let ggx = if u < p[2] + p[0] + p[1] {
+                    &self.specular
+                } else {
+                    &self.clearcoat_ggx
+                };
+                let h = ggx.sample_visible(&up, rng.gen(), rng.gen());
+                Vec3::reflect(&-up, &h)
+            };
+            if wi.z <= 0.0 {
+                return None;
+            }
+            Vec3::new(wi.x, wi.y, flip * wi.z)
+        };
+
+        let pdf = self.pdf(wo, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(BsdfSample {
+            direction: wi,
+            weight: self.eval(wo, &wi) * (1.0 / pdf),
+            pdf,
+            specular: false,
+            transmitted: wo.z * wi.z < 0.0,
+        })
+    }
+}
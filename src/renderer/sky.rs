@@ -0,0 +1,196 @@
+use crate::color::Color;
+use crate::math::{Onb, Vec3};
+use rand::prelude::*;
+use std::f64::consts::PI;
+
+// Radiance of the sun disk before the atmosphere, relative to the sky which has a zenith of roughly one.
+const SUN_RADIANCE: f64 = 2.0e5;
+// Angular radius of the sun in degrees.
+const SUN_RADIUS: f64 = 0.2665;
+// Sky luminance comes out of the model in kcd/m^2.
+const SKY_SCALE: f64 = 0.1;
+
+type Perez = [f64; 5];
+
+fn perez(c: &Perez, cos_theta: f64, gamma: f64) -> f64 {
+    (1.0 + c[0] * (c[1] / cos_theta).exp())
+        * (1.0 + c[2] * (c[3] * gamma).exp() + c[4] * gamma.cos() * gamma.cos())
+}
+
+fn xyy_to_rgb(x: f64, y: f64, luminance: f64) -> Color {
+    let big_x = x / y * luminance;
+    let big_z = (1.0 - x - y) / y * luminance;
+
+    Color::new(
+        (3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z) as f32,
+        (-0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z) as f32,
+        (0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z) as f32,
+        1.0,
+    )
+}
+
+// The Preetham, Shirley and Smits analytic daylight model: sky luminance and chromaticity follow Perez
+// distributions fitted to the `turbidity` of the air and the sun position, relative to their values at the
+// zenith. The sun is a disk of sunlight attenuated by Rayleigh and aerosol scattering along its path
+// through the atmosphere, and is importance sampled so next event estimation finds it. The model only
+// covers a sun above the horizon, and the ground below the horizon reflects the mirrored sky with
+// `ground_albedo`.
+pub struct Sky {
+    pub sun_direction: Vec3,
+    pub turbidity: f64,
+    pub intensity: f32,
+    pub sun_size: f64,
+    pub ground_albedo: f32,
+
+    theta_sun: f64,
+    coefficients: [Perez; 3],
+    zenith: [f64; 3],
+    sun_color: Color,
+    cos_sun_radius: f64,
+}
+
+impl Sky {
+    // `elevation` is the sun's angle above the horizon and `azimuth` turns it from -z towards +x, both in
+    // degrees. `sun_size` scales the apparent radius of the sun, which softens shadows.
+    pub fn new(
+        elevation: f64,
+        azimuth: f64,
+        turbidity: f64,
+        intensity: f32,
+        sun_size: f64,
+        ground_albedo: f32,
+    ) -> Sky {
+        let elevation = elevation.clamp(0.0, 90.0).to_radians();
+        let azimuth = azimuth.to_radians();
+        let sun_direction = Vec3::new(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.cos(),
+        );
+
+        let t = turbidity;
+        let coefficients = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        let theta_sun = PI / 2.0 - elevation;
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+        let cubic = |c: [f64; 4]| {
+            c[0] * theta_sun * theta_sun * theta_sun
+                + c[1] * theta_sun * theta_sun
+                + c[2] * theta_sun
+                + c[3]
+        };
+        let zenith = [
+            (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192,
+            t * t * cubic([0.00166, -0.00375, 0.00209, 0.0])
+                + t * cubic([-0.02903, 0.06377, -0.03202, 0.00394])
+                + cubic([0.11693, -0.21196, 0.06052, 0.25886]),
+            t * t * cubic([0.00275, -0.00610, 0.00317, 0.0])
+                + t * cubic([-0.04214, 0.08970, -0.04153, 0.00516])
+                + cubic([0.15346, -0.26756, 0.06670, 0.26688]),
+        ];
+
+        // Transmittance along the sun's relative optical air mass at the centre wavelengths of the red,
+        // green and blue primaries, in micrometres.
+        let air_mass =
+            1.0 / (theta_sun.cos() + 0.15 * (93.885 - theta_sun.to_degrees()).powf(-1.253));
+        let beta = 0.046_083_658 * t - 0.045_860_259;
+        let transmittance = |lambda: f64| {
+            let rayleigh = (-0.008_735 * lambda.powf(-4.08) * air_mass).exp();
+            let aerosol = (-beta * lambda.powf(-1.3) * air_mass).exp();
+            (SUN_RADIANCE * rayleigh * aerosol) as f32
+        };
+        let sun_color = Color::new(
+            transmittance(0.65),
+            transmittance(0.55),
+            transmittance(0.45),
+            1.0,
+        );
+
+        Sky {
+            sun_direction,
+            turbidity,
+            intensity,
+            sun_size,
+            ground_albedo,
+            theta_sun,
+            coefficients,
+            zenith,
+            sun_color,
+            cos_sun_radius: (SUN_RADIUS * sun_size).to_radians().cos(),
+        }
+    }
+
+    fn sky(&self, direction: &Vec3) -> Color {
+        let cos_theta = direction.y.max(0.001);
+        let cos_gamma = Vec3::dot(direction, &self.sun_direction).clamp(-1.0, 1.0);
+        let gamma = cos_gamma.acos();
+
+        let value = |i: usize| {
+            let c = &self.coefficients[i];
+            self.zenith[i] * perez(c, cos_theta, gamma) / perez(c, 1.0, self.theta_sun)
+        };
+
+        let color = xyy_to_rgb(value(1), value(2), SKY_SCALE * value(0));
+        Color::new(color.r.max(0.0), color.g.max(0.0), color.b.max(0.0), 1.0)
+    }
+
+    pub fn value(&self, direction: &Vec3) -> Color {
+        let d = direction.normalize();
+        if d.y < 0.0 {
+            let mirrored = Vec3::new(d.x, -d.y, d.z);
+            return self.sky(&mirrored) * self.ground_albedo * self.intensity;
+        }
+
+        let sun = if Vec3::dot(&d, &self.sun_direction) >= self.cos_sun_radius {
+            // A larger sun keeps the same total power.
+            self.sun_color * (1.0 / (self.sun_size * self.sun_size))
+        } else {
+            Color::new(0.0, 0.0, 0.0, 1.0)
+        };
+
+        (self.sky(&d) + sun) * self.intensity
+    }
+
+    // Directions are sampled uniformly in the cone of the sun disk.
+    pub fn random_direction(&self, rng: &mut dyn RngCore) -> Vec3 {
+        let cos_theta = 1.0 - rng.gen::<f64>() * (1.0 - self.cos_sun_radius);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * rng.gen::<f64>();
+
+        Onb::from_w(&self.sun_direction).local(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        )
+    }
+
+    pub fn pdf_value(&self, direction: &Vec3) -> f64 {
+        if Vec3::dot(&direction.normalize(), &self.sun_direction) >= self.cos_sun_radius {
+            1.0 / (2.0 * PI * (1.0 - self.cos_sun_radius))
+        } else {
+            0.0
+        }
+    }
+}
@@ -0,0 +1,352 @@
+use crate::color::Color;
+use std::ops::{Add, Mul};
+
+// Wavelengths traced in spectral mode, in nanometres.
+pub const MIN_WAVELENGTH: f64 = 380.0;
+pub const MAX_WAVELENGTH: f64 = 720.0;
+const RANGE: f64 = MAX_WAVELENGTH - MIN_WAVELENGTH;
+
+pub const SAMPLES: usize = 4;
+
+// Integral of the luminance matching function over the traced range.
+const CIE_Y_INTEGRAL: f64 = 106.911_868;
+
+// XYZ to linear sRGB, with the rows scaled so a constant spectrum (illuminant E) comes out white and RGB
+// colours survive being upsampled and converted back.
+const XYZ_TO_RGB: [[f64; 3]; 3] = [
+    [2.699_133, -1.280_344, -0.415_288],
+    [-1.020_168, 1.975_070, 0.043_696],
+    [0.061_360, -0.224_727, 1.164_393],
+];
+
+// Smits' "An RGB to Spectrum Conversion for Reflectances": spectra of white and of the primary and secondary
+// colours in ten bins across the traced range, which are blended to match an RGB colour.
+const WHITE: [f64; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const CYAN: [f64; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const MAGENTA: [f64; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const YELLOW: [f64; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const RED: [f64; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const GREEN: [f64; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const BLUE: [f64; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+// Linear interpolation between the bin centres.
+fn lookup(table: &[f64; 10], wavelength: f64) -> f64 {
+    let x = (wavelength - MIN_WAVELENGTH) / RANGE * 10.0 - 0.5;
+    let i = x.floor();
+    let t = x - i;
+    let bin = |i: f64| table[i.clamp(0.0, 9.0) as usize];
+
+    bin(i) * (1.0 - t) + bin(i + 1.0) * t
+}
+
+// The value at `wavelength` of a smooth spectrum with the given RGB colour.
+pub fn upsample(color: &Color, wavelength: f64) -> f64 {
+    let (r, g, b) = (color.r as f64, color.g as f64, color.b as f64);
+    let at = |table| lookup(table, wavelength);
+
+    if r <= g && r <= b {
+        r * at(&WHITE)
+            + if g <= b {
+                (g - r) * at(&CYAN) + (b - g) * at(&BLUE)
+            } else {
+                (b - r) * at(&CYAN) + (g - b) * at(&GREEN)
+            }
+    } else if g <= r && g <= b {
+        g * at(&WHITE)
+            + if r <= b {
+                (r - g) * at(&MAGENTA) + (b - r) * at(&BLUE)
+            } else {
+                (b - g) * at(&MAGENTA) + (r - b) * at(&RED)
+            }
+    } else {
+        b * at(&WHITE)
+            + if r <= g {
+                (r - b) * at(&YELLOW) + (g - r) * at(&GREEN)
+            } else {
+                (g - b) * at(&YELLOW) + (r - g) * at(&RED)
+            }
+    }
+}
+
+fn lobe(wavelength: f64, mean: f64, below: f64, above: f64) -> f64 {
+    let t = (wavelength - mean) / if wavelength < mean { below } else { above };
+    (-0.5 * t * t).exp()
+}
+
+// The CIE 1931 colour matching functions, using the multi-lobe fit of Wyman, Sloan and Shirley.
+fn xyz_matching(wavelength: f64) -> [f64; 3] {
+    let l = wavelength;
+    [
+        1.056 * lobe(l, 599.8, 37.9, 31.0) + 0.362 * lobe(l, 442.0, 16.0, 26.7)
+            - 0.065 * lobe(l, 501.1, 20.4, 26.2),
+        0.821 * lobe(l, 568.8, 46.9, 40.5) + 0.286 * lobe(l, 530.9, 16.3, 31.1),
+        1.217 * lobe(l, 437.0, 11.8, 36.0) + 0.681 * lobe(l, 459.0, 26.0, 13.8),
+    ]
+}
+
+// Values of a spectrum at the sampled wavelengths.
+#[derive(Copy, Clone)]
+pub struct SampledSpectrum(pub [f64; SAMPLES]);
+
+impl Add for SampledSpectrum {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self::Output {
+        let mut values = self.0;
+        values.iter_mut().zip(&other.0).for_each(|(a, b)| *a += b);
+        SampledSpectrum(values)
+    }
+}
+
+impl Mul for SampledSpectrum {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self::Output {
+        let mut values = self.0;
+        values.iter_mut().zip(&other.0).for_each(|(a, b)| *a *= b);
+        SampledSpectrum(values)
+    }
+}
+
+impl Mul<f64> for SampledSpectrum {
+    type Output = Self;
+
+    fn mul(self, other: f64) -> Self::Output {
+        let mut values = self.0;
+        values.iter_mut().for_each(|a| *a *= other);
+        SampledSpectrum(values)
+    }
+}
+
+// Hero wavelength sampling: a uniformly sampled hero wavelength and the others spaced evenly from it across
+// the traced range, so one path estimates several wavelengths at once. Bounces that depend on the
+// wavelength, like refraction into dispersive glass, keep only the hero.
+#[derive(Copy, Clone)]
+pub struct SampledWavelengths {
+    pub wavelengths: [f64; SAMPLES],
+    pub pdf: [f64; SAMPLES],
+}
+
+impl SampledWavelengths {
+    pub fn sample(u: f64) -> SampledWavelengths {
+        let mut wavelengths = [0.0; SAMPLES];
+        for (i, wavelength) in wavelengths.iter_mut().enumerate() {
+            let offset = (u + i as f64 / SAMPLES as f64).fract();
+            *wavelength = MIN_WAVELENGTH + offset * RANGE;
+        }
+
+        SampledWavelengths {
+            wavelengths,
+            pdf: [1.0 / RANGE; SAMPLES],
+        }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.wavelengths[0]
+    }
+
+    pub fn terminate_secondary(&mut self) {
+        if self.pdf[1] == 0.0 {
+            return;
+        }
+
+        self.pdf[0] /= SAMPLES as f64;
+        self.pdf[1..].iter_mut().for_each(|pdf| *pdf = 0.0);
+    }
+
+    pub fn upsample(&self, color: &Color) -> SampledSpectrum {
+        let mut values = [0.0; SAMPLES];
+        for (value, wavelength) in values.iter_mut().zip(&self.wavelengths) {
+            *value = upsample(color, *wavelength);
+        }
+
+        SampledSpectrum(values)
+    }
+
+    // Estimates the XYZ colour of the spectrum from its samples and converts it to linear RGB.
+    pub fn to_color(&self, spectrum: &SampledSpectrum) -> Color {
+        let mut xyz = [0.0; 3];
+        for i in 0..SAMPLES {
+            if self.pdf[i] <= 0.0 {
+                continue;
+            }
+
+            let matching = xyz_matching(self.wavelengths[i]);
+            let weight = spectrum.0[i] / (self.pdf[i] * SAMPLES as f64 * CIE_Y_INTEGRAL);
+            xyz.iter_mut()
+                .zip(&matching)
+                .for_each(|(c, m)| *c += m * weight);
+        }
+
+        xyz_to_color(&xyz)
+    }
+}
+
+fn xyz_to_color(xyz: &[f64; 3]) -> Color {
+    let row = |m: &[f64; 3]| (m[0] * xyz[0] + m[1] * xyz[1] + m[2] * xyz[2]) as f32;
+    Color::new(
+        row(&XYZ_TO_RGB[0]),
+        row(&XYZ_TO_RGB[1]),
+        row(&XYZ_TO_RGB[2]),
+        1.0,
+    )
+}
+
+// The RGB colour of white light reflected by a surface with the given reflectance spectrum, integrated at
+// evenly spaced wavelengths.
+pub fn reflectance_color(reflectance: impl Fn(f64) -> f64) -> Color {
+    const STEPS: usize = 32;
+
+    let mut xyz = [0.0; 3];
+    for i in 0..STEPS {
+        let wavelength = MIN_WAVELENGTH + (i as f64 + 0.5) / STEPS as f64 * RANGE;
+        let weight = reflectance(wavelength) * RANGE / (STEPS as f64 * CIE_Y_INTEGRAL);
+        xyz.iter_mut()
+            .zip(&xyz_matching(wavelength))
+            .for_each(|(c, m)| *c += m * weight);
+    }
+
+    xyz_to_color(&xyz)
+}
+
+// Wavelength dependent index of refraction, with wavelengths in micrometres as the coefficients are usually
+// published.
+#[derive(Copy, Clone)]
+pub enum Dispersion {
+    // n = a + b / wavelength^2
+    Cauchy { a: f64, b: f64 },
+    // n^2 = 1 + sum of b * wavelength^2 / (wavelength^2 - c)
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    // Index at a wavelength in nanometres.
+    pub fn index(&self, wavelength: f64) -> f64 {
+        let l2 = (wavelength / 1000.0) * (wavelength / 1000.0);
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / l2,
+            Dispersion::Sellmeier { b, c } => {
+                let sum: f64 = (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Midpoint rule over the traced range in steps of 0.1 nm.
+    fn integrate(f: impl Fn(f64) -> f64) -> f64 {
+        let steps = (RANGE * 10.0) as usize;
+        (0..steps)
+            .map(|i| f(MIN_WAVELENGTH + (i as f64 + 0.5) * 0.1) * 0.1)
+            .sum()
+    }
+
+    // The colour of a spectrum found by converting it at evenly spread sets of wavelengths.
+    fn round_trip(color: &Color) -> Color {
+        const SETS: usize = 1000;
+        let mut total = [0.0; 3];
+        for i in 0..SETS {
+            let wavelengths = SampledWavelengths::sample((i as f64 + 0.5) / SETS as f64);
+            let rgb = wavelengths.to_color(&wavelengths.upsample(color));
+            total[0] += rgb.r as f64 / SETS as f64;
+            total[1] += rgb.g as f64 / SETS as f64;
+            total[2] += rgb.b as f64 / SETS as f64;
+        }
+        Color::new(total[0] as f32, total[1] as f32, total[2] as f32, 1.0)
+    }
+
+    #[test]
+    fn white_upsamples_to_one_everywhere() {
+        let white = Color::new(1.0, 1.0, 1.0, 1.0);
+        for i in 0..=340 {
+            let wavelength = MIN_WAVELENGTH + i as f64;
+            assert!(
+                (upsample(&white, wavelength) - 1.0).abs() < 1e-3,
+                "{}",
+                wavelength
+            );
+        }
+
+        let gray = Color::new(0.25, 0.25, 0.25, 1.0);
+        assert!((upsample(&gray, 550.0) - 0.25).abs() < 1e-3);
+    }
+
+    #[test]
+    fn matching_functions_fit_cie_1931() {
+        let y = integrate(|l| xyz_matching(l)[1]);
+        assert!((y - CIE_Y_INTEGRAL).abs() < 1e-3, "{}", y);
+
+        // Peaks of the tabulated functions.
+        let close =
+            |l: f64, channel: usize, value: f64| (xyz_matching(l)[channel] - value).abs() < 0.02;
+        assert!(close(555.0, 1, 1.0));
+        assert!(close(600.0, 0, 1.062));
+        assert!(close(445.0, 0, 0.348));
+        assert!(close(445.0, 2, 1.782));
+    }
+
+    #[test]
+    fn constant_spectra_are_white() {
+        let xyz: Vec<f64> = (0..3)
+            .map(|c| integrate(|l| xyz_matching(l)[c]) / CIE_Y_INTEGRAL)
+            .collect();
+        for row in &XYZ_TO_RGB {
+            let value = row[0] * xyz[0] + row[1] * xyz[1] + row[2] * xyz[2];
+            assert!((value - 1.0).abs() < 1e-3, "{}", value);
+        }
+    }
+
+    #[test]
+    fn colours_survive_the_round_trip() {
+        let colors = [
+            Color::new(1.0, 1.0, 1.0, 1.0),
+            Color::new(0.8, 0.4, 0.2, 1.0),
+            Color::new(0.1, 0.5, 0.3, 1.0),
+            Color::new(0.2, 0.3, 0.9, 1.0),
+        ];
+        for color in &colors {
+            let back = round_trip(color);
+            let error = (back.r - color.r)
+                .abs()
+                .max((back.g - color.g).abs())
+                .max((back.b - color.b).abs());
+            assert!(
+                error < 0.05,
+                "{:?} {:?}",
+                (color.r, color.g, color.b),
+                (back.r, back.g, back.b)
+            );
+        }
+    }
+
+    #[test]
+    fn dispersion_follows_the_published_fits() {
+        let bk7 = Dispersion::Sellmeier {
+            b: [1.039_612_12, 0.231_792_344, 1.010_469_45],
+            c: [0.006_000_698_67, 0.020_017_914_4, 103.560_653],
+        };
+        assert!((bk7.index(587.6) - 1.5168).abs() < 1e-4);
+
+        let cauchy = Dispersion::Cauchy { a: 1.5, b: 0.004 };
+        assert!((cauchy.index(500.0) - 1.516).abs() < 1e-12);
+        assert!(cauchy.index(400.0) > cauchy.index(700.0));
+    }
+}
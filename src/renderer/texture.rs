@@ -0,0 +1,241 @@
+use crate::color::Color;
+use crate::math::{Vec3, AABB};
+use crate::objects::VoxelGrid;
+use rand::prelude::*;
+use std::path::Path;
+use std::sync::Arc;
+
+const POINT_COUNT: usize = 256;
+
+// Gradient noise from Ken Perlin's improved noise, with random unit gradients on the lattice points.
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+fn permutation(rng: &mut dyn RngCore) -> Vec<usize> {
+    let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+    p.shuffle(rng);
+    p
+}
+
+impl Perlin {
+    pub fn new(rng: &mut dyn RngCore) -> Perlin {
+        let gradients = (0..POINT_COUNT)
+            .map(|_| {
+                Vec3::new(
+                    rng.gen_range(-1.0, 1.0),
+                    rng.gen_range(-1.0, 1.0),
+                    rng.gen_range(-1.0, 1.0),
+                )
+                .normalize()
+            })
+            .collect();
+
+        Perlin {
+            gradients,
+            perm_x: permutation(rng),
+            perm_y: permutation(rng),
+            perm_z: permutation(rng),
+        }
+    }
+
+    // Returns a value in roughly [-1, 1].
+    pub fn noise(&self, p: &Vec3) -> f64 {
+        let (fx, fy, fz) = (p.x.floor(), p.y.floor(), p.z.floor());
+        let (u, v, w) = (p.x - fx, p.y - fy, p.z - fz);
+        let (i, j, k) = (fx as i64, fy as i64, fz as i64);
+
+        let smooth = |t: f64| t * t * (3.0 - 2.0 * t);
+        let (uu, vv, ww) = (smooth(u), smooth(v), smooth(w));
+
+        let mut sum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let index = self.perm_x[((i + di) & 255) as usize]
+                        ^ self.perm_y[((j + dj) & 255) as usize]
+                        ^ self.perm_z[((k + dk) & 255) as usize];
+                    let (a, b, c) = (di as f64, dj as f64, dk as f64);
+                    let weight = Vec3::new(u - a, v - b, w - c);
+
+                    sum += (a * uu + (1.0 - a) * (1.0 - uu))
+                        * (b * vv + (1.0 - b) * (1.0 - vv))
+                        * (c * ww + (1.0 - c) * (1.0 - ww))
+                        * Vec3::dot(&self.gradients[index], &weight);
+                }
+            }
+        }
+
+        sum
+    }
+
+    // Sum of `depth` octaves of the absolute noise, each at twice the frequency and half the weight of the
+    // previous one.
+    pub fn turbulence(&self, p: &Vec3, depth: usize) -> f64 {
+        let mut sum = 0.0;
+        let mut p = *p;
+        let mut weight = 1.0;
+
+        for _ in 0..depth {
+            sum += weight * self.noise(&p);
+            weight *= 0.5;
+            p = 2.0 * p;
+        }
+
+        sum.abs()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum WrapMode {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+impl WrapMode {
+    fn wrap(&self, i: i64, size: usize) -> usize {
+        let size = size as i64;
+        let i = match self {
+            WrapMode::Repeat => i.rem_euclid(size),
+            WrapMode::Clamp => i.max(0).min(size - 1),
+            WrapMode::Mirror => {
+                let i = i.rem_euclid(2 * size);
+                if i >= size {
+                    2 * size - 1 - i
+                } else {
+                    i
+                }
+            }
+        };
+
+        i as usize
+    }
+}
+
+// Pixels are stored as linear colour, top row first.
+pub struct ImageTexture {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+    pub wrap: WrapMode,
+}
+
+impl ImageTexture {
+    // 8 bit images are assumed to be sRGB encoded.
+    pub fn load(path: &Path, wrap: WrapMode) -> image::ImageResult<ImageTexture> {
+        let image = image::open(path)?.to_rgba();
+
+        Ok(ImageTexture {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            pixels: image.pixels().map(|p| Color::from_rgba(*p)).collect(),
+            wrap,
+        })
+    }
+
+    // Data such as roughness or metallic maps, whose 8 bit values aren't sRGB encoded.
+    pub fn load_linear(path: &Path, wrap: WrapMode) -> image::ImageResult<ImageTexture> {
+        let image = image::open(path)?.to_rgba();
+        let channel = |c: u8| c as f32 / 255.0;
+
+        Ok(ImageTexture {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            pixels: image
+                .pixels()
+                .map(|p| Color::new(channel(p[0]), channel(p[1]), channel(p[2]), channel(p[3])))
+                .collect(),
+            wrap,
+        })
+    }
+
+    fn texel(&self, x: i64, y: i64) -> Color {
+        let x = self.wrap.wrap(x, self.width);
+        let y = self.wrap.wrap(y, self.height);
+        self.pixels[x + y * self.width]
+    }
+
+    // Bilinear lookup with texel centers at half integer coordinates. `v` runs from the bottom of the image
+    // to the top.
+    pub fn sample(&self, u: f64, v: f64) -> Color {
+        if self.pixels.is_empty() {
+            return Color::new(0.0, 0.0, 0.0, 1.0);
+        }
+
+        let x = u * self.width as f64 - 0.5;
+        let y = (1.0 - v) * self.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = ((x - x0) as f32, (y - y0) as f32);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = self.texel(x0, y0) * (1.0 - fx) + self.texel(x0 + 1, y0) * fx;
+        let bottom = self.texel(x0, y0 + 1) * (1.0 - fx) + self.texel(x0 + 1, y0 + 1) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+}
+
+#[derive(Clone)]
+pub enum Texture {
+    Constant(Color),
+    // Alternates between the two colours in a 3D grid of cubes `1 / scale` wide.
+    Checker {
+        even: Color,
+        odd: Color,
+        scale: f64,
+    },
+    Noise {
+        perlin: Arc<Perlin>,
+        scale: f64,
+    },
+    // Veins of `color` following a sine wave along z, perturbed by turbulence.
+    Marble {
+        perlin: Arc<Perlin>,
+        scale: f64,
+        color: Color,
+    },
+    Image(Arc<ImageTexture>),
+    // `color` scaled by a voxel grid stretched over `bounds`, such as the temperature of a fire.
+    Grid {
+        grid: Arc<VoxelGrid>,
+        bounds: AABB,
+        color: Color,
+    },
+}
+
+impl Texture {
+    pub fn value(&self, u: f64, v: f64, p: &Vec3) -> Color {
+        match self {
+            Texture::Constant(color) => *color,
+            Texture::Checker { even, odd, scale } => {
+                let sum = (scale * p.x).floor() + (scale * p.y).floor() + (scale * p.z).floor();
+                if sum.rem_euclid(2.0) < 1.0 {
+                    *even
+                } else {
+                    *odd
+                }
+            }
+            Texture::Noise { perlin, scale } => {
+                let n = 0.5 * (1.0 + perlin.noise(&(*scale * *p)));
+                Color::new(n as f32, n as f32, n as f32, 1.0)
+            }
+            Texture::Marble {
+                perlin,
+                scale,
+                color,
+            } => {
+                let n = 0.5 * (1.0 + (scale * p.z + 10.0 * perlin.turbulence(p, 7)).sin());
+                *color * n
+            }
+            Texture::Image(image) => image.sample(u, v),
+            Texture::Grid {
+                grid,
+                bounds,
+                color,
+            } => *color * grid.value_in(bounds, p),
+        }
+    }
+}
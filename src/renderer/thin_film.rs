@@ -0,0 +1,139 @@
+use super::microfacet::fresnel_dielectric;
+use super::spectrum::reflectance_color;
+use crate::color::Color;
+use std::f64::consts::PI;
+
+// A transparent film a few hundred nanometres thick on a surface, like soap or oil on water. Light reflected
+// by its top and bottom interferes, colouring reflections depending on the angle and the `thickness`, which
+// is in nanometres.
+#[derive(Copy, Clone)]
+pub struct ThinFilm {
+    pub thickness: f64,
+    pub index: f64,
+}
+
+// Amplitude reflection coefficients of an interface for s and p polarized light.
+fn amplitudes(n1: f64, cos1: f64, n2: f64, cos2: f64) -> (f64, f64) {
+    (
+        (n1 * cos1 - n2 * cos2) / (n1 * cos1 + n2 * cos2),
+        (n2 * cos1 - n1 * cos2) / (n2 * cos1 + n1 * cos2),
+    )
+}
+
+impl ThinFilm {
+    // Unpolarized reflectance for light of one wavelength arriving at an angle with the given cosine from a
+    // medium of index `outer`, onto the film lying on a medium of index `inner`. The reflections bouncing
+    // back and forth inside the film are summed with Airy's formula.
+    pub fn reflectance(&self, cosine: f64, outer: f64, inner: f64, wavelength: f64) -> f64 {
+        let cos1 = cosine.clamp(0.0, 1.0);
+        let sin2_1 = 1.0 - cos1 * cos1;
+        let sin2_2 = sin2_1 * (outer / self.index) * (outer / self.index);
+        let sin2_3 = sin2_1 * (outer / inner) * (outer / inner);
+        if sin2_3 >= 1.0 {
+            return 1.0;
+        }
+        // Light can't enter a film thinner than the media around it at this angle.
+        if sin2_2 >= 1.0 {
+            return fresnel_dielectric(cos1, inner / outer);
+        }
+
+        let (cos2, cos3) = ((1.0 - sin2_2).sqrt(), (1.0 - sin2_3).sqrt());
+        let (s12, p12) = amplitudes(outer, cos1, self.index, cos2);
+        let (s23, p23) = amplitudes(self.index, cos2, inner, cos3);
+        let phase = 4.0 * PI * self.index * self.thickness * cos2 / wavelength;
+
+        let airy = |r12: f64, r23: f64| {
+            let interference = 2.0 * r12 * r23 * phase.cos();
+            (r12 * r12 + r23 * r23 + interference) / (1.0 + r12 * r12 * r23 * r23 + interference)
+        };
+        0.5 * (airy(s12, s23) + airy(p12, p23))
+    }
+
+    // The reflectance at `wavelength` in spectral mode, or the colour the film gives white light in RGB.
+    pub fn fresnel(&self, cosine: f64, outer: f64, inner: f64, wavelength: Option<f64>) -> Color {
+        match wavelength {
+            Some(wavelength) => {
+                let r = self.reflectance(cosine, outer, inner, wavelength) as f32;
+                Color::new(r, r, r, 1.0)
+            }
+            None => {
+                reflectance_color(|wavelength| self.reflectance(cosine, outer, inner, wavelength))
+                    .clamp()
+            }
+        }
+    }
+}
+
+// Reflectance of an interface between dielectrics for light arriving from the medium of index `outer`, under
+// a thin film if there is one. `wavelength` is `None` in RGB mode.
+pub fn dielectric_fresnel(
+    cosine: f64,
+    outer: f64,
+    inner: f64,
+    film: Option<ThinFilm>,
+    wavelength: Option<f64>,
+) -> Color {
+    match film {
+        Some(film) => film.fresnel(cosine, outer, inner, wavelength),
+        None => {
+            let r = fresnel_dielectric(cosine, inner / outer) as f32;
+            Color::new(r, r, r, 1.0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vanishing_film_is_plain_fresnel() {
+        let film = ThinFilm {
+            thickness: 0.0,
+            index: 1.33,
+        };
+        for &cosine in &[1.0, 0.7, 0.3, 0.05] {
+            let value = film.reflectance(cosine, 1.0, 1.5, 550.0);
+            assert!((value - fresnel_dielectric(cosine, 1.5)).abs() < 1e-9);
+
+            // White light comes back without a tint.
+            let color = film.fresnel(cosine, 1.0, 1.5, None);
+            for channel in &[color.r, color.g, color.b] {
+                assert!(
+                    (*channel as f64 - value).abs() < 0.01,
+                    "{} {}",
+                    channel,
+                    value
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn film_matching_the_surface_is_plain_fresnel() {
+        let film = ThinFilm {
+            thickness: 350.0,
+            index: 1.5,
+        };
+        for &cosine in &[1.0, 0.7, 0.3, 0.05] {
+            let value = film.reflectance(cosine, 1.0, 1.5, 480.0);
+            assert!((value - fresnel_dielectric(cosine, 1.5)).abs() < 1e-9);
+        }
+    }
+
+    // A quarter wave coating with the geometric mean index cancels the reflection at normal incidence.
+    #[test]
+    fn quarter_wave_film_cancels_reflection() {
+        let index = 1.5f64.sqrt();
+        let film = ThinFilm {
+            thickness: 550.0 / (4.0 * index),
+            index,
+        };
+        assert!(film.reflectance(1.0, 1.0, 1.5, 550.0) < 1e-9);
+
+        let fresnel = dielectric_fresnel(1.0, 1.0, 1.5, Some(film), Some(550.0));
+        assert!(fresnel.r < 1e-6);
+        let bare = dielectric_fresnel(1.0, 1.0, 1.5, None, Some(550.0));
+        assert!((bare.r - 0.04).abs() < 1e-6);
+    }
+}
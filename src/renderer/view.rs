@@ -0,0 +1,291 @@
+use crate::color::{srgb_encode, Color};
+use std::str::FromStr;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ToneMapping {
+    Clamp,
+    Reinhard,
+    Aces,
+    Filmic,
+    Agx,
+}
+
+impl FromStr for ToneMapping {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<ToneMapping, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "clamp" => Ok(ToneMapping::Clamp),
+            "reinhard" => Ok(ToneMapping::Reinhard),
+            "aces" => Ok(ToneMapping::Aces),
+            "filmic" => Ok(ToneMapping::Filmic),
+            "agx" => Ok(ToneMapping::Agx),
+            _ => Err(format!(
+                "unknown tone mapping '{}', expected clamp, reinhard, aces, filmic or agx",
+                name
+            )),
+        }
+    }
+}
+
+type Rgb = [f32; 3];
+
+fn transform(m: &[Rgb; 3], c: Rgb) -> Rgb {
+    [
+        m[0][0] * c[0] + m[0][1] * c[1] + m[0][2] * c[2],
+        m[1][0] * c[0] + m[1][1] * c[1] + m[1][2] * c[2],
+        m[2][0] * c[0] + m[2][1] * c[1] + m[2][2] * c[2],
+    ]
+}
+
+fn reinhard(c: Rgb) -> Rgb {
+    let l = Color::new(c[0], c[1], c[2], 1.0).luminance();
+    if l <= 0.0 {
+        return [0.0; 3];
+    }
+
+    let scale = 1.0 / (1.0 + l);
+    [c[0] * scale, c[1] * scale, c[2] * scale]
+}
+
+// Stephen Hill's fit of the ACES reference rendering and sRGB output transforms, including the conversions
+// into and out of the ACES working space.
+fn aces(c: Rgb) -> Rgb {
+    const INPUT: [Rgb; 3] = [
+        [0.59719, 0.35458, 0.04823],
+        [0.07600, 0.90834, 0.01566],
+        [0.02840, 0.13383, 0.83777],
+    ];
+    const OUTPUT: [Rgb; 3] = [
+        [1.60475, -0.53108, -0.07367],
+        [-0.10208, 1.10813, -0.00605],
+        [-0.00327, -0.07276, 1.07602],
+    ];
+
+    let fit = |v: f32| {
+        (v * (v + 0.024_578_6) - 0.000_090_537) / (v * (0.983_729 * v + 0.432_951) + 0.238_081)
+    };
+    let c = transform(&INPUT, c);
+    transform(&OUTPUT, [fit(c[0]), fit(c[1]), fit(c[2])])
+}
+
+// John Hable's filmic curve with its exposure bias of two, normalized so that a linear value of half of
+// `WHITE` maps to one.
+fn filmic(c: Rgb) -> Rgb {
+    const WHITE: f32 = 11.2;
+
+    let curve = |x: f32| {
+        let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+        (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+    };
+    let white = curve(WHITE);
+    [
+        curve(2.0 * c[0]) / white,
+        curve(2.0 * c[1]) / white,
+        curve(2.0 * c[2]) / white,
+    ]
+}
+
+// The AgX base look, using the polynomial approximation of its contrast curve. The curve produces display
+// encoded values, so the result is decoded with a 2.2 power to go through the same sRGB encoding as the
+// other curves.
+fn agx(c: Rgb) -> Rgb {
+    const INSET: [Rgb; 3] = [
+        [0.842_479_06, 0.078_433_6, 0.079_223_745],
+        [0.042_328_242, 0.878_468_6, 0.079_166_13],
+        [0.042_375_655, 0.078_433_6, 0.879_143],
+    ];
+    const OUTSET: [Rgb; 3] = [
+        [1.196_879, -0.098_020_88, -0.099_029_74],
+        [-0.052_896_85, 1.151_903_1, -0.098_961_17],
+        [-0.052_971_635, -0.098_043_45, 1.151_073_7],
+    ];
+    const MIN_EV: f32 = -12.473_93;
+    const MAX_EV: f32 = 4.026_069;
+
+    let contrast = |v: f32| {
+        let x = (v.max(1e-10).log2().clamp(MIN_EV, MAX_EV) - MIN_EV) / (MAX_EV - MIN_EV);
+        let x2 = x * x;
+        let x4 = x2 * x2;
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+            - 0.00232
+    };
+
+    let c = transform(&INSET, c);
+    let c = transform(&OUTSET, [contrast(c[0]), contrast(c[1]), contrast(c[2])]);
+    [
+        c[0].max(0.0).powf(2.2),
+        c[1].max(0.0).powf(2.2),
+        c[2].max(0.0).powf(2.2),
+    ]
+}
+
+// Hashes the pixel coordinates and channel into a value in [0, 1), so the dither pattern is stable between
+// preview updates.
+fn hash(x: u32, y: u32, channel: u32) -> f32 {
+    let mut h = x
+        .wrapping_mul(0x8da6_b343)
+        .wrapping_add(y.wrapping_mul(0xd816_3841))
+        .wrapping_add(channel.wrapping_mul(0xcb1a_b31f));
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7feb_352d);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x846c_a68b);
+    h ^= h >> 16;
+
+    (h >> 8) as f32 / (1 << 24) as f32
+}
+
+// Turns scene linear radiance into display values: the exposure is applied first, then the tone curve
+// compresses the range to [0, 1] and the result is encoded with the sRGB transfer function.
+#[derive(Copy, Clone, Debug)]
+pub struct ViewTransform {
+    pub exposure: f32,
+    pub tone_mapping: ToneMapping,
+    pub dither: bool,
+}
+
+impl Default for ViewTransform {
+    fn default() -> Self {
+        ViewTransform::new()
+    }
+}
+
+impl ViewTransform {
+    pub fn new() -> ViewTransform {
+        ViewTransform {
+            exposure: 0.0,
+            tone_mapping: ToneMapping::Clamp,
+            dither: false,
+        }
+    }
+
+    // Returns the sRGB encoded colour, with every channel in [0, 1].
+    pub fn apply(&self, color: &Color) -> Color {
+        let scale = 2f32.powf(self.exposure);
+        let c = [
+            (color.r * scale).max(0.0),
+            (color.g * scale).max(0.0),
+            (color.b * scale).max(0.0),
+        ];
+
+        let c = match self.tone_mapping {
+            ToneMapping::Clamp => c,
+            ToneMapping::Reinhard => reinhard(c),
+            ToneMapping::Aces => aces(c),
+            ToneMapping::Filmic => filmic(c),
+            ToneMapping::Agx => agx(c),
+        };
+
+        let encode = |v: f32| srgb_encode(v.clamp(0.0, 1.0));
+        Color::new(
+            encode(c[0]),
+            encode(c[1]),
+            encode(c[2]),
+            color.a.clamp(0.0, 1.0),
+        )
+    }
+
+    // Quantizes the transformed colour to 8 bits per channel. Dithering adds triangular noise of up to one
+    // step before rounding, which breaks up banding in smooth gradients.
+    pub fn to_rgb8(&self, color: &Color, x: usize, y: usize) -> [u8; 3] {
+        let c = self.apply(color);
+
+        let mut rgb = [0; 3];
+        for (channel, value) in [c.r, c.g, c.b].iter().enumerate() {
+            let noise = if self.dither {
+                let (x, y, channel) = (x as u32, y as u32, channel as u32);
+                hash(x, y, 2 * channel) + hash(x, y, 2 * channel + 1) - 1.0
+            } else {
+                0.0
+            };
+
+            rgb[channel] = (value * 255.0 + noise + 0.5).floor().clamp(0.0, 255.0) as u8;
+        }
+
+        rgb
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::srgb_decode;
+
+    const CURVES: [ToneMapping; 5] = [
+        ToneMapping::Clamp,
+        ToneMapping::Reinhard,
+        ToneMapping::Aces,
+        ToneMapping::Filmic,
+        ToneMapping::Agx,
+    ];
+
+    fn view(tone_mapping: ToneMapping) -> ViewTransform {
+        ViewTransform {
+            tone_mapping,
+            ..ViewTransform::new()
+        }
+    }
+
+    fn grey(value: f32) -> Color {
+        Color::new(value, value, value, 1.0)
+    }
+
+    #[test]
+    fn srgb_encoding_round_trips() {
+        assert_eq!(srgb_encode(0.0), 0.0);
+        assert!((srgb_encode(1.0) - 1.0).abs() < 1e-6);
+        for i in 0..=1000 {
+            let v = i as f32 / 1000.0;
+            assert!((srgb_decode(srgb_encode(v)) - v).abs() < 1e-5);
+            assert!((srgb_encode(srgb_decode(v)) - v).abs() < 1e-5);
+        }
+
+        // The linear segment and the power curve meet without a visible step.
+        let (below, above) = (srgb_encode(0.003_130_7), srgb_encode(0.003_130_9));
+        assert!(above > below && above - below < 1e-5);
+    }
+
+    #[test]
+    fn tone_curves_are_monotonic_and_in_range() {
+        for &curve in &CURVES {
+            let mut previous = [0.0; 3];
+            for i in 0..=400 {
+                let value = 2f32.powf(i as f32 / 20.0 - 14.0);
+                let c = view(curve).apply(&Color::new(value, 0.5 * value, 0.1 * value, 1.0));
+                for (channel, &v) in [c.r, c.g, c.b].iter().enumerate() {
+                    assert!((0.0..=1.0).contains(&v), "{:?} gives {}", curve, v);
+                    assert!(v >= previous[channel] - 1e-6, "{:?} at {}", curve, value);
+                    previous[channel] = v;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tone_curves_keep_black_and_reach_white() {
+        for &curve in &CURVES {
+            let black = view(curve).apply(&grey(0.0));
+            assert!(black.r < 1e-3, "{:?} lifts black to {}", curve, black.r);
+
+            let white = view(curve).apply(&grey(1.0e4));
+            assert!(white.r > 0.99, "{:?} maps white to {}", curve, white.r);
+        }
+
+        assert!((view(ToneMapping::Clamp).apply(&grey(1.0)).r - 1.0).abs() < 1e-6);
+        assert!((view(ToneMapping::Filmic).apply(&grey(5.6)).r - 1.0).abs() < 1e-5);
+
+        // Reinhard maps a luminance of one to a half.
+        let reinhard = view(ToneMapping::Reinhard).apply(&grey(1.0));
+        assert!((srgb_decode(reinhard.r) - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn exposure_scales_in_stops() {
+        let mut brighter = view(ToneMapping::Clamp);
+        brighter.exposure = 1.0;
+        let a = brighter.apply(&grey(0.2));
+        let b = view(ToneMapping::Clamp).apply(&grey(0.4));
+        assert!((a.r - b.r).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,427 @@
+use crate::math::{Ray, Vec3, AABB};
+use crate::objects::{Intersectable, Intersection};
+use rand::RngCore;
+
+const BINS: usize = 16;
+const MAX_LEAF_SIZE: usize = 4;
+const MAX_SAH_DEPTH: usize = 32;
+const STACK_SIZE: usize = 64;
+
+// Interior nodes are stored with their first child directly after them and `offset` pointing at the second
+// child. Leaves have a non-zero `count` and reference `objects[offset..offset + count]`.
+#[derive(Copy, Clone)]
+struct Node {
+    bounds: AABB,
+    offset: usize,
+    count: usize,
+    axis: usize,
+}
+
+struct BuildItem {
+    bounds: AABB,
+    centroid: Vec3,
+    index: usize,
+}
+
+pub struct Bvh {
+    nodes: Vec<Node>,
+}
+
+fn bounds_of(items: &[BuildItem]) -> AABB {
+    items.iter().skip(1).fold(items[0].bounds, |bounds, item| {
+        AABB::combine(&bounds, &item.bounds)
+    })
+}
+
+fn largest_axis(extent: &Vec3) -> usize {
+    if extent.x > extent.y && extent.x > extent.z {
+        0
+    } else if extent.y > extent.z {
+        1
+    } else {
+        2
+    }
+}
+
+fn bin_index(centroid: f64, min: f64, extent: f64) -> usize {
+    let bin = ((centroid - min) / extent * BINS as f64) as usize;
+    bin.min(BINS - 1)
+}
+
+// Evaluates the surface area heuristic for the planes between the bins along `axis`, returning the best
+// split bin and its cost relative to intersecting every item in a single leaf.
+fn best_split(items: &[BuildItem], axis: usize, centroids: &AABB, bounds: &AABB) -> (usize, f64) {
+    let min = centroids.min[axis];
+    let extent = centroids.max[axis] - min;
+
+    let mut counts = [0usize; BINS];
+    let mut bin_bounds: [Option<AABB>; BINS] = [None; BINS];
+    for item in items {
+        let bin = bin_index(item.centroid[axis], min, extent);
+        counts[bin] += 1;
+        bin_bounds[bin] = Some(match bin_bounds[bin] {
+            Some(b) => AABB::combine(&b, &item.bounds),
+            None => item.bounds,
+        });
+    }
+
+    let grow = |acc: (usize, Option<AABB>), bin: usize| {
+        let bounds = match (acc.1, bin_bounds[bin]) {
+            (Some(a), Some(b)) => Some(AABB::combine(&a, &b)),
+            (a, b) => a.or(b),
+        };
+        (acc.0 + counts[bin], bounds)
+    };
+
+    let area = |bounds: Option<AABB>| bounds.map_or(0.0, |b| b.surface_area());
+    let parent_area = bounds.surface_area().max(f64::EPSILON);
+
+    let mut best = (0, f64::INFINITY);
+    for split in 1..BINS {
+        let (left_count, left_bounds) = (0..split).fold((0, None), grow);
+        let (right_count, right_bounds) = (split..BINS).fold((0, None), grow);
+        if left_count == 0 || right_count == 0 {
+            continue;
+        }
+
+        let cost = 1.0
+            + (area(left_bounds) * left_count as f64 + area(right_bounds) * right_count as f64)
+                / parent_area;
+        if cost < best.1 {
+            best = (split, cost);
+        }
+    }
+
+    best
+}
+
+fn partition(items: &mut [BuildItem], predicate: impl Fn(&BuildItem) -> bool) -> usize {
+    let mut mid = 0;
+    for i in 0..items.len() {
+        if predicate(&items[i]) {
+            items.swap(i, mid);
+            mid += 1;
+        }
+    }
+
+    mid
+}
+
+fn build_recursive(
+    items: &mut [BuildItem],
+    first: usize,
+    depth: usize,
+    nodes: &mut Vec<Node>,
+) -> usize {
+    let bounds = bounds_of(items);
+    let node_index = nodes.len();
+    nodes.push(Node {
+        bounds,
+        offset: first,
+        count: items.len(),
+        axis: 0,
+    });
+
+    if items.len() <= MAX_LEAF_SIZE {
+        return node_index;
+    }
+
+    let centroids = items.iter().fold(
+        AABB::from_min_max(items[0].centroid, items[0].centroid),
+        |b, item| b.grow(&item.centroid),
+    );
+    let axis = largest_axis(&centroids.extent());
+    if centroids.extent()[axis] <= 0.0 {
+        return node_index;
+    }
+
+    let mut mid = 0;
+    if depth < MAX_SAH_DEPTH {
+        let (split, cost) = best_split(items, axis, &centroids, &bounds);
+        if cost >= items.len() as f64 && items.len() <= 4 * MAX_LEAF_SIZE {
+            return node_index;
+        }
+
+        let min = centroids.min[axis];
+        let extent = centroids.extent()[axis];
+        mid = partition(items, |item| {
+            bin_index(item.centroid[axis], min, extent) < split
+        });
+    }
+
+    // Deep trees and splits the heuristic can't separate fall back to a median split, which keeps the depth
+    // bounded by the traversal stack.
+    if mid == 0 || mid == items.len() {
+        items.sort_unstable_by(|a, b| {
+            a.centroid[axis]
+                .partial_cmp(&b.centroid[axis])
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        mid = items.len() / 2;
+    }
+
+    let (left, right) = items.split_at_mut(mid);
+    build_recursive(left, first, depth + 1, nodes);
+    let right_index = build_recursive(right, first + mid, depth + 1, nodes);
+
+    nodes[node_index].offset = right_index;
+    nodes[node_index].count = 0;
+    nodes[node_index].axis = axis;
+
+    node_index
+}
+
+impl Bvh {
+    // Builds the hierarchy and reorders `objects` so every leaf covers a contiguous range. Objects without a
+    // bounding box must be kept out of the hierarchy by the caller.
+    pub fn build<T: Intersectable>(objects: &mut Vec<T>, t0: f64, t1: f64) -> Bvh {
+        if objects.is_empty() {
+            return Bvh { nodes: vec![] };
+        }
+
+        let mut items: Vec<BuildItem> = objects
+            .iter()
+            .enumerate()
+            .map(|(index, object)| {
+                let bounds = object.bounding_box(t0, t1).unwrap_or_else(AABB::new);
+                BuildItem {
+                    bounds,
+                    centroid: bounds.centroid(),
+                    index,
+                }
+            })
+            .collect();
+
+        let mut nodes = Vec::with_capacity(2 * objects.len());
+        build_recursive(&mut items, 0, 0, &mut nodes);
+
+        let mut slots: Vec<Option<T>> = objects.drain(..).map(Some).collect();
+        objects.extend(items.iter().map(|item| slots[item.index].take().unwrap()));
+
+        Bvh { nodes }
+    }
+
+    pub fn bounds(&self) -> Option<AABB> {
+        self.nodes.first().map(|node| node.bounds)
+    }
+
+    // Visits the objects in every leaf the ray reaches between `t_min` and `t_max`. Children are visited front
+    // to back along the split axis, and `visit` can return a closer `t_max`, letting the box tests reject
+    // anything behind the closest hit so far.
+    fn traverse<'a, T>(
+        &self,
+        objects: &'a [T],
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        mut visit: impl FnMut(&'a T, f64) -> Option<f64>,
+    ) {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let inv_direction = Vec3::new(
+            1.0 / ray.direction.x,
+            1.0 / ray.direction.y,
+            1.0 / ray.direction.z,
+        );
+        let negative = [
+            inv_direction.x < 0.0,
+            inv_direction.y < 0.0,
+            inv_direction.z < 0.0,
+        ];
+
+        let mut t_max = t_max;
+
+        let mut stack = [0usize; STACK_SIZE];
+        let mut stack_size = 0;
+        let mut current = 0;
+
+        loop {
+            let node = &self.nodes[current];
+
+            if node
+                .bounds
+                .hit_inverse(&ray.origin, &inv_direction, t_min, t_max)
+            {
+                if node.count > 0 {
+                    for object in &objects[node.offset..(node.offset + node.count)] {
+                        if let Some(closer) = visit(object, t_max) {
+                            t_max = closer;
+                        }
+                    }
+                } else {
+                    let (near, far) = if negative[node.axis] {
+                        (node.offset, current + 1)
+                    } else {
+                        (current + 1, node.offset)
+                    };
+
+                    stack[stack_size] = far;
+                    stack_size += 1;
+                    current = near;
+                    continue;
+                }
+            }
+
+            if stack_size == 0 {
+                break;
+            }
+
+            stack_size -= 1;
+            current = stack[stack_size];
+        }
+    }
+
+    pub fn intersect<'a, T: Intersectable>(
+        &self,
+        objects: &'a [T],
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<Intersection<'a>> {
+        let mut closest = None;
+        self.traverse(objects, ray, t_min, t_max, |object, t_max| {
+            let hit = object.intersect(ray, t_min, t_max)?;
+            let distance = hit.distance;
+            closest = Some(hit);
+            Some(distance)
+        });
+
+        closest
+    }
+
+    // Like `intersect`, but also finds where the ray scatters inside volumes.
+    pub fn hit<'a, T: Intersectable>(
+        &self,
+        objects: &'a [T],
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rng: &mut dyn RngCore,
+    ) -> Option<Intersection<'a>> {
+        let mut closest = None;
+        self.traverse(objects, ray, t_min, t_max, |object, t_max| {
+            let hit = object.hit(ray, t_min, t_max, rng)?;
+            let distance = hit.distance;
+            closest = Some(hit);
+            Some(distance)
+        });
+
+        closest
+    }
+
+    // The product of the transmittance of every object the ray passes between `t_min` and `t_max`.
+    pub fn transmittance<T: Intersectable>(
+        &self,
+        objects: &[T],
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rng: &mut dyn RngCore,
+    ) -> f64 {
+        let mut transmittance = 1.0;
+        self.traverse(objects, ray, t_min, t_max, |object, _| {
+            transmittance *= object.transmittance(ray, t_min, t_max, rng);
+            None
+        });
+
+        transmittance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::objects::{Object, Sphere, TriangleMesh};
+    use crate::renderer::{Lambertian, Material, Texture};
+    use rand::prelude::*;
+
+    fn random_point(rng: &mut StdRng, size: f64) -> Vec3 {
+        Vec3::new(
+            rng.gen_range(-size, size),
+            rng.gen_range(-size, size),
+            rng.gen_range(-size, size),
+        )
+    }
+
+    fn random_objects(rng: &mut StdRng) -> Vec<Object> {
+        let material = Material::Lambertian(Lambertian {
+            albedo: Texture::Constant(Color::new(0.5, 0.5, 0.5, 1.0)),
+        });
+
+        let mut objects: Vec<Object> = (0..200)
+            .map(|_| {
+                Object::Sphere(Sphere {
+                    center: random_point(rng, 10.0),
+                    radius: rng.gen_range(0.1, 1.5),
+                    material: material.clone(),
+                })
+            })
+            .collect();
+
+        let mut positions = vec![];
+        for _ in 0..100 {
+            let corner = random_point(rng, 10.0);
+            positions.push(corner);
+            positions.push(corner + random_point(rng, 2.0));
+            positions.push(corner + random_point(rng, 2.0));
+        }
+        let indices = (0..100).map(|i| [3 * i, 3 * i + 1, 3 * i + 2]).collect();
+        objects.extend(TriangleMesh::new(positions, indices, material).into_objects());
+        objects
+    }
+
+    fn random_ray(rng: &mut StdRng) -> Ray {
+        let mut direction = random_point(rng, 1.0);
+        // Rays parallel to the axes have infinite inverse directions.
+        match rng.gen_range(0, 4) {
+            0 => direction = Vec3::new(direction.x, 0.0, 0.0),
+            1 => direction = Vec3::new(0.0, direction.y, direction.z),
+            _ => {}
+        }
+        Ray::new(random_point(rng, 15.0), direction.normalize())
+    }
+
+    fn brute_force<'a>(objects: &'a [Object], ray: &Ray) -> Option<Intersection<'a>> {
+        let mut closest: Option<Intersection> = None;
+        for object in objects {
+            let t_max = closest.as_ref().map_or(f64::INFINITY, |hit| hit.distance);
+            if let Some(hit) = object.intersect(ray, 0.001, t_max) {
+                closest = Some(hit);
+            }
+        }
+        closest
+    }
+
+    #[test]
+    fn agrees_with_brute_force() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut objects = random_objects(&mut rng);
+        let bvh = Bvh::build(&mut objects, 0.0, 1.0);
+
+        let mut hits = 0;
+        for _ in 0..20_000 {
+            let ray = random_ray(&mut rng);
+
+            let expected = brute_force(&objects, &ray).map(|hit| hit.distance);
+            let found = bvh.intersect(&objects, &ray, 0.001, f64::INFINITY);
+            assert_eq!(found.map(|hit| hit.distance), expected);
+            hits += expected.is_some() as usize;
+        }
+        assert!(hits > 1000);
+    }
+
+    #[test]
+    fn empty_hierarchy_misses() {
+        let mut objects: Vec<Object> = vec![];
+        let bvh = Bvh::build(&mut objects, 0.0, 1.0);
+        let ray = Ray::new(Vec3::zero(), Vec3::new(0.0, 0.0, 1.0));
+
+        assert!(bvh.bounds().is_none());
+        assert!(bvh
+            .intersect(&objects, &ray, 0.001, f64::INFINITY)
+            .is_none());
+    }
+}
@@ -78,6 +78,17 @@ impl Camera {
         Camera::perspective_with_time(from, at, up, vfov, aspect, aperture, focus_dist, 0.0, 0.0)
     }
 
+    pub fn with_aspect(&self, aspect: f64) -> Camera {
+        let current = self.horizontal.magnitude() / self.vertical.magnitude();
+        let horizontal = self.horizontal * (aspect / current);
+
+        Camera {
+            lower_left: self.lower_left + 0.5 * self.horizontal - 0.5 * horizontal,
+            horizontal,
+            ..*self
+        }
+    }
+
     pub fn get_ray(&self, s: f32, t: f32, rng: &mut dyn RngCore) -> Ray {
         let rd = self.lens_radius * random_in_unit_disk(rng);
         let offset = self.u * rd.x + self.v * rd.y;
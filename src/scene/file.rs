@@ -0,0 +1,1326 @@
+use super::obj::{load_obj, ObjError};
+use super::{BounceLimits, Camera, Scene};
+use crate::color::Color;
+use crate::math::{Matrix4, Transform, Vec3, AABB};
+use crate::objects::{
+    cuboid, ConstantMedium, GridMedium, Group, Instance, MovingSphere, Object, Quad, Sphere,
+    VoxelGrid,
+};
+use crate::renderer::{
+    Coated, Conductor, ConductorPreset, Dialectric, DiffuseLight, Dispersion, Environment,
+    EnvironmentMap, HenyeyGreenstein, ImageTexture, Isotropic, Lambertian, Material, Metal, Perlin,
+    Principled, Sky, Texture, ThinFilm, ToneMapping, ViewTransform, WrapMode,
+};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use ron::extensions::Extensions;
+use ron::Options;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    UnknownMaterial {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        name: String,
+    },
+    UnknownTexture {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        name: String,
+    },
+    InvalidMaterial {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        name: String,
+        message: String,
+    },
+    Image {
+        path: PathBuf,
+        error: image::ImageError,
+    },
+    InvalidObject {
+        path: PathBuf,
+        index: usize,
+        message: String,
+    },
+    Obj(ObjError),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            SceneError::Parse {
+                path,
+                line,
+                column,
+                message,
+            } => write!(f, "{}:{}:{}: {}", path.display(), line, column, message),
+            SceneError::UnknownMaterial {
+                path,
+                line,
+                column,
+                name,
+            } => write!(
+                f,
+                "{}:{}:{}: unknown material '{}'",
+                path.display(),
+                line,
+                column,
+                name
+            ),
+            SceneError::UnknownTexture {
+                path,
+                line,
+                column,
+                name,
+            } => write!(
+                f,
+                "{}:{}:{}: unknown texture '{}'",
+                path.display(),
+                line,
+                column,
+                name
+            ),
+            SceneError::InvalidMaterial {
+                path,
+                line,
+                column,
+                name,
+                message,
+            } => write!(
+                f,
+                "{}:{}:{}: material '{}': {}",
+                path.display(),
+                line,
+                column,
+                name,
+                message
+            ),
+            SceneError::Image { path, error } => write!(f, "{}: {}", path.display(), error),
+            SceneError::InvalidObject {
+                path,
+                index,
+                message,
+            } => write!(f, "{}: object {}: {}", path.display(), index, message),
+            SceneError::Obj(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SceneError::Io { error, .. } => Some(error),
+            SceneError::Image { error, .. } => Some(error),
+            SceneError::Obj(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<ObjError> for SceneError {
+    fn from(error: ObjError) -> Self {
+        SceneError::Obj(error)
+    }
+}
+
+pub struct LoadedScene {
+    pub scene: Scene,
+    pub camera: Camera,
+    pub width: usize,
+    pub height: usize,
+    pub samples: Vec<u32>,
+    pub view: ViewTransform,
+}
+
+type Vector = (f64, f64, f64);
+type Rgb = (f32, f32, f32);
+
+#[derive(Deserialize)]
+#[serde(rename = "Scene", deny_unknown_fields)]
+struct SceneFile {
+    #[serde(default = "default_resolution")]
+    width: usize,
+    #[serde(default = "default_resolution")]
+    height: usize,
+    #[serde(default = "default_max_recursion")]
+    max_recursion: u32,
+    #[serde(default)]
+    max_bounces: BounceLimitsFile,
+    #[serde(default)]
+    spectral: bool,
+    #[serde(default = "default_samples")]
+    samples: Vec<u32>,
+    #[serde(default)]
+    environment: EnvironmentFile,
+    camera: CameraFile,
+    #[serde(default)]
+    view: ViewFile,
+    #[serde(default)]
+    textures: HashMap<String, TextureFile>,
+    #[serde(default)]
+    materials: HashMap<String, MaterialFile>,
+    objects: Vec<ObjectFile>,
+}
+
+fn default_resolution() -> usize {
+    512
+}
+
+fn default_max_recursion() -> u32 {
+    64
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct BounceLimitsFile {
+    diffuse: u32,
+    specular: u32,
+    transmission: u32,
+}
+
+impl Default for BounceLimitsFile {
+    fn default() -> Self {
+        let limits = BounceLimits::default();
+        BounceLimitsFile {
+            diffuse: limits.diffuse,
+            specular: limits.specular,
+            transmission: limits.transmission,
+        }
+    }
+}
+
+fn default_samples() -> Vec<u32> {
+    vec![4, 32, 128, 256, 512]
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraFile {
+    from: Vector,
+    at: Vector,
+    #[serde(default = "default_up")]
+    up: Vector,
+    vfov: f64,
+    #[serde(default)]
+    aperture: f64,
+    #[serde(default)]
+    focus_dist: Option<f64>,
+    #[serde(default)]
+    time0: f64,
+    #[serde(default)]
+    time1: f64,
+}
+
+fn default_up() -> Vector {
+    (0.0, 1.0, 0.0)
+}
+
+// Maps are equirectangular images, turned by `rotation` degrees around the y axis and scaled by
+// `intensity`.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+enum EnvironmentFile {
+    Constant(Rgb),
+    #[default]
+    Gradient,
+    Map {
+        path: String,
+        #[serde(default)]
+        rotation: f64,
+        #[serde(default = "default_intensity")]
+        intensity: f32,
+    },
+    // Daylight for a sun `elevation` degrees above the horizon, turned `azimuth` degrees from -z towards +x.
+    Sky {
+        elevation: f64,
+        #[serde(default)]
+        azimuth: f64,
+        #[serde(default = "default_turbidity")]
+        turbidity: f64,
+        #[serde(default = "default_intensity")]
+        intensity: f32,
+        #[serde(default = "default_sun_size")]
+        sun_size: f64,
+        #[serde(default = "default_ground_albedo")]
+        ground_albedo: f32,
+    },
+}
+
+fn default_intensity() -> f32 {
+    1.0
+}
+
+fn default_turbidity() -> f64 {
+    3.0
+}
+
+fn default_sun_size() -> f64 {
+    1.0
+}
+
+fn default_ground_albedo() -> f32 {
+    0.3
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ViewFile {
+    #[serde(default)]
+    exposure: f32,
+    #[serde(default)]
+    tone_mapping: ToneMappingFile,
+    #[serde(default)]
+    dither: bool,
+}
+
+#[derive(Deserialize, Copy, Clone, Default)]
+enum ToneMappingFile {
+    #[default]
+    Clamp,
+    Reinhard,
+    Aces,
+    Filmic,
+    Agx,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+enum TextureFile {
+    Checker {
+        even: Rgb,
+        odd: Rgb,
+        #[serde(default = "default_texture_scale")]
+        scale: f64,
+    },
+    Noise {
+        #[serde(default = "default_texture_scale")]
+        scale: f64,
+    },
+    Marble {
+        #[serde(default = "default_texture_scale")]
+        scale: f64,
+        #[serde(default = "default_marble_color")]
+        color: Rgb,
+    },
+    Image {
+        path: String,
+        #[serde(default)]
+        wrap: WrapFile,
+        // Set for data such as roughness maps, which aren't sRGB encoded.
+        #[serde(default)]
+        linear: bool,
+    },
+}
+
+fn default_texture_scale() -> f64 {
+    1.0
+}
+
+fn default_marble_color() -> Rgb {
+    (1.0, 1.0, 1.0)
+}
+
+#[derive(Deserialize, Copy, Clone, Default)]
+enum WrapFile {
+    #[default]
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+// Material parameters are either a colour or the name of a texture.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TextureRef {
+    Color(Rgb),
+    Named(String),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+enum MaterialFile {
+    Lambertian {
+        albedo: TextureRef,
+    },
+    Metal {
+        albedo: TextureRef,
+        fuzz: f64,
+    },
+    Conductor {
+        metal: ConductorFile,
+        #[serde(default)]
+        roughness: f64,
+    },
+    Dialectric {
+        index: f64,
+        #[serde(default)]
+        roughness: f64,
+        // The colour left of white light after travelling `tint_distance` through the inside.
+        #[serde(default = "default_tint")]
+        tint: Rgb,
+        #[serde(default = "default_tint_distance")]
+        tint_distance: f64,
+        #[serde(default)]
+        dispersion: Option<DispersionFile>,
+        #[serde(default)]
+        film: Option<ThinFilmFile>,
+    },
+    DiffuseLight {
+        emit: TextureRef,
+    },
+    Isotropic {
+        albedo: TextureRef,
+    },
+    HenyeyGreenstein {
+        albedo: TextureRef,
+        g: f64,
+        #[serde(default)]
+        emission: Option<TextureRef>,
+    },
+    Principled {
+        #[serde(default = "default_base_color")]
+        base_color: TextureRef,
+        #[serde(default)]
+        metallic: f64,
+        #[serde(default = "default_half")]
+        roughness: f64,
+        // The name of a linear texture with roughness in green and metallic in blue, as in glTF.
+        #[serde(default)]
+        metallic_roughness: Option<String>,
+        #[serde(default = "default_half")]
+        specular: f64,
+        #[serde(default)]
+        specular_tint: f64,
+        #[serde(default)]
+        sheen: f64,
+        #[serde(default = "default_half")]
+        sheen_tint: f64,
+        #[serde(default)]
+        clearcoat: f64,
+        #[serde(default = "default_clearcoat_roughness")]
+        clearcoat_roughness: f64,
+        #[serde(default)]
+        transmission: f64,
+        #[serde(default = "default_ior")]
+        ior: f64,
+        #[serde(default)]
+        emission: Option<TextureRef>,
+        #[serde(default = "default_intensity")]
+        emission_strength: f32,
+    },
+    // A clear coat over any other material except another coating, written out in place.
+    Coated {
+        base: Box<MaterialFile>,
+        #[serde(default = "default_ior")]
+        index: f64,
+        #[serde(default)]
+        roughness: f64,
+        // The colour left of white light after crossing the coat once straight down.
+        #[serde(default = "default_tint")]
+        tint: Rgb,
+        #[serde(default)]
+        film: Option<ThinFilmFile>,
+    },
+}
+
+fn default_base_color() -> TextureRef {
+    TextureRef::Color((0.8, 0.8, 0.8))
+}
+
+fn default_tint() -> Rgb {
+    (1.0, 1.0, 1.0)
+}
+
+fn default_tint_distance() -> f64 {
+    1.0
+}
+
+fn default_half() -> f64 {
+    0.5
+}
+
+fn default_clearcoat_roughness() -> f64 {
+    0.03
+}
+
+fn default_ior() -> f64 {
+    1.5
+}
+
+// Coefficients for wavelengths in micrometres.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+enum DispersionFile {
+    Cauchy { a: f64, b: f64 },
+    Sellmeier { b: Vector, c: Vector },
+}
+
+// Thickness in nanometres.
+#[derive(Deserialize)]
+#[serde(rename = "ThinFilm", deny_unknown_fields)]
+struct ThinFilmFile {
+    thickness: f64,
+    index: f64,
+}
+
+impl ThinFilmFile {
+    fn to_film(&self) -> ThinFilm {
+        ThinFilm {
+            thickness: self.thickness,
+            index: self.index,
+        }
+    }
+}
+
+// A measured metal, or the red, green and blue components of a complex index of refraction `eta + i k`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+enum ConductorFile {
+    Gold,
+    Copper,
+    Aluminium,
+    Silver,
+    Custom { eta: Rgb, k: Rgb },
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+enum ObjectFile {
+    Sphere {
+        center: Vector,
+        radius: f64,
+        material: String,
+    },
+    MovingSphere {
+        center0: Vector,
+        center1: Vector,
+        time0: f64,
+        time1: f64,
+        radius: f64,
+        material: String,
+    },
+    Mesh {
+        path: String,
+        #[serde(default)]
+        material: Option<String>,
+    },
+    Quad {
+        q: Vector,
+        u: Vector,
+        v: Vector,
+        material: String,
+    },
+    Rect {
+        min: Vector,
+        max: Vector,
+        material: String,
+    },
+    Box {
+        min: Vector,
+        max: Vector,
+        material: String,
+    },
+    // The transforms are applied in order, to the object centered in its own space.
+    Instance {
+        object: Box<ObjectFile>,
+        transform: Vec<TransformFile>,
+    },
+    // Fills a closed boundary object with a fog of the given density, scattering with `material`.
+    Medium {
+        boundary: Box<ObjectFile>,
+        density: f64,
+        material: String,
+    },
+    // A voxel density grid stretched between `min` and `max`. `.vol` files carry their resolution, raw
+    // files of 32 bit floats need it given.
+    Volume {
+        path: String,
+        #[serde(default)]
+        resolution: Option<(usize, usize, usize)>,
+        min: Vector,
+        max: Vector,
+        density: f64,
+        material: String,
+        #[serde(default)]
+        emission: Option<EmissionFile>,
+    },
+}
+
+// Emission of a volume, `color` scaled by the grid in `path`, or by the density grid without one.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EmissionFile {
+    #[serde(default)]
+    path: Option<String>,
+    color: Rgb,
+}
+
+#[derive(Deserialize)]
+enum TransformFile {
+    Translate(Vector),
+    Scale(Vector),
+    RotateX(f64),
+    RotateY(f64),
+    RotateZ(f64),
+    Rotate(Vector, f64),
+}
+
+impl TransformFile {
+    fn to_matrix(&self) -> Matrix4 {
+        match *self {
+            TransformFile::Translate(offset) => Matrix4::translation(&vec3(offset)),
+            TransformFile::Scale(scale) => Matrix4::scaling(&vec3(scale)),
+            TransformFile::RotateX(degrees) => {
+                Matrix4::rotation(&Vec3::new(1.0, 0.0, 0.0), degrees)
+            }
+            TransformFile::RotateY(degrees) => {
+                Matrix4::rotation(&Vec3::new(0.0, 1.0, 0.0), degrees)
+            }
+            TransformFile::RotateZ(degrees) => {
+                Matrix4::rotation(&Vec3::new(0.0, 0.0, 1.0), degrees)
+            }
+            TransformFile::Rotate(axis, degrees) => Matrix4::rotation(&vec3(axis), degrees),
+        }
+    }
+}
+
+// Deserialized values carry no spans, so unknown references are reported at the first place the quoted
+// name appears in the source.
+fn find_position(source: &str, name: &str) -> (usize, usize) {
+    line_column(source, source.find(&format!("\"{}\"", name)))
+}
+
+// The first place a field is given a value, like `width: 0`, skipping longer names that end or start
+// with it.
+fn find_field(source: &str, field: &str) -> (usize, usize) {
+    let is_identifier = |c: char| c.is_alphanumeric() || c == '_';
+    let offset = source
+        .match_indices(field)
+        .map(|(offset, _)| offset)
+        .find(|&offset| {
+            let after = &source[offset + field.len()..];
+            !source[..offset].ends_with(is_identifier)
+                && !after.starts_with(is_identifier)
+                && after.trim_start().starts_with(':')
+        });
+    line_column(source, offset)
+}
+
+fn line_column(source: &str, offset: Option<usize>) -> (usize, usize) {
+    match offset {
+        Some(offset) => {
+            let before = &source[..offset];
+            let line = before.matches('\n').count() + 1;
+            let column = before.len() - before.rfind('\n').map_or(0, |n| n + 1) + 1;
+            (line, column)
+        }
+        None => (0, 0),
+    }
+}
+
+fn vec3(v: Vector) -> Vec3 {
+    Vec3::new(v.0, v.1, v.2)
+}
+
+fn color(c: Rgb) -> Color {
+    Color::new(c.0, c.1, c.2, 1.0)
+}
+
+impl ViewFile {
+    fn to_view(&self) -> ViewTransform {
+        let tone_mapping = match self.tone_mapping {
+            ToneMappingFile::Clamp => ToneMapping::Clamp,
+            ToneMappingFile::Reinhard => ToneMapping::Reinhard,
+            ToneMappingFile::Aces => ToneMapping::Aces,
+            ToneMappingFile::Filmic => ToneMapping::Filmic,
+            ToneMappingFile::Agx => ToneMapping::Agx,
+        };
+
+        ViewTransform {
+            exposure: self.exposure,
+            tone_mapping,
+            dither: self.dither,
+        }
+    }
+}
+
+impl EnvironmentFile {
+    fn to_environment(&self, directory: &Path) -> Result<Environment, SceneError> {
+        let environment = match *self {
+            EnvironmentFile::Constant(c) => Environment::Constant(color(c)),
+            EnvironmentFile::Gradient => Environment::Gradient,
+            EnvironmentFile::Map {
+                ref path,
+                rotation,
+                intensity,
+            } => {
+                let path = directory.join(path);
+                let map = EnvironmentMap::load(&path, rotation, intensity)
+                    .map_err(|error| SceneError::Image { path, error })?;
+                Environment::Map(Arc::new(map))
+            }
+            EnvironmentFile::Sky {
+                elevation,
+                azimuth,
+                turbidity,
+                intensity,
+                sun_size,
+                ground_albedo,
+            } => Environment::Sky(Arc::new(Sky::new(
+                elevation,
+                azimuth,
+                turbidity,
+                intensity,
+                sun_size,
+                ground_albedo,
+            ))),
+        };
+
+        Ok(environment)
+    }
+}
+
+impl TextureFile {
+    fn to_texture(&self, directory: &Path, rng: &mut StdRng) -> Result<Texture, SceneError> {
+        let texture = match *self {
+            TextureFile::Checker { even, odd, scale } => Texture::Checker {
+                even: color(even),
+                odd: color(odd),
+                scale,
+            },
+            TextureFile::Noise { scale } => Texture::Noise {
+                perlin: Arc::new(Perlin::new(rng)),
+                scale,
+            },
+            TextureFile::Marble { scale, color: c } => Texture::Marble {
+                perlin: Arc::new(Perlin::new(rng)),
+                scale,
+                color: color(c),
+            },
+            TextureFile::Image {
+                ref path,
+                wrap,
+                linear,
+            } => {
+                let wrap = match wrap {
+                    WrapFile::Repeat => WrapMode::Repeat,
+                    WrapFile::Clamp => WrapMode::Clamp,
+                    WrapFile::Mirror => WrapMode::Mirror,
+                };
+
+                let path = directory.join(path);
+                let image = if linear {
+                    ImageTexture::load_linear(&path, wrap)
+                } else {
+                    ImageTexture::load(&path, wrap)
+                };
+                let image = image.map_err(|error| SceneError::Image { path, error })?;
+                Texture::Image(Arc::new(image))
+            }
+        };
+
+        Ok(texture)
+    }
+}
+
+impl MaterialFile {
+    fn to_material(
+        &self,
+        texture: impl Fn(&TextureRef) -> Result<Texture, SceneError>,
+    ) -> Result<Material, SceneError> {
+        let material = match self {
+            MaterialFile::Lambertian { albedo } => Material::Lambertian(Lambertian {
+                albedo: texture(albedo)?,
+            }),
+            MaterialFile::Metal { albedo, fuzz } => {
+                Material::Metal(Metal::new(texture(albedo)?, *fuzz))
+            }
+            MaterialFile::Conductor { metal, roughness } => {
+                let preset = |preset| Conductor::preset(preset, *roughness);
+                Material::Conductor(match metal {
+                    ConductorFile::Gold => preset(ConductorPreset::Gold),
+                    ConductorFile::Copper => preset(ConductorPreset::Copper),
+                    ConductorFile::Aluminium => preset(ConductorPreset::Aluminium),
+                    ConductorFile::Silver => preset(ConductorPreset::Silver),
+                    ConductorFile::Custom { eta, k } => Conductor {
+                        eta: color(*eta),
+                        k: color(*k),
+                        roughness: *roughness,
+                    },
+                })
+            }
+            MaterialFile::Dialectric {
+                index,
+                roughness,
+                tint,
+                tint_distance,
+                dispersion,
+                film,
+            } => {
+                let absorption = |t: f32| (-(t.max(1e-6).ln() as f64) / *tint_distance) as f32;
+                Material::Dialectric(Dialectric {
+                    index: *index,
+                    roughness: *roughness,
+                    absorption: Color::new(
+                        absorption(tint.0),
+                        absorption(tint.1),
+                        absorption(tint.2),
+                        1.0,
+                    ),
+                    dispersion: dispersion.as_ref().map(|dispersion| match dispersion {
+                        DispersionFile::Cauchy { a, b } => Dispersion::Cauchy { a: *a, b: *b },
+                        DispersionFile::Sellmeier { b, c } => Dispersion::Sellmeier {
+                            b: [b.0, b.1, b.2],
+                            c: [c.0, c.1, c.2],
+                        },
+                    }),
+                    film: film.as_ref().map(ThinFilmFile::to_film),
+                })
+            }
+            MaterialFile::DiffuseLight { emit } => Material::DiffuseLight(DiffuseLight {
+                emit: texture(emit)?,
+            }),
+            MaterialFile::Isotropic { albedo } => Material::Isotropic(Isotropic {
+                albedo: texture(albedo)?,
+            }),
+            MaterialFile::HenyeyGreenstein {
+                albedo,
+                g,
+                emission,
+            } => Material::HenyeyGreenstein(HenyeyGreenstein {
+                albedo: texture(albedo)?,
+                g: *g,
+                emission: match emission {
+                    Some(emission) => texture(emission)?,
+                    None => Texture::Constant(Color::new(0.0, 0.0, 0.0, 1.0)),
+                },
+            }),
+            MaterialFile::Principled {
+                base_color,
+                metallic,
+                roughness,
+                metallic_roughness,
+                specular,
+                specular_tint,
+                sheen,
+                sheen_tint,
+                clearcoat,
+                clearcoat_roughness,
+                transmission,
+                ior,
+                emission,
+                emission_strength,
+            } => Material::Principled(Principled {
+                base_color: texture(base_color)?,
+                metallic: *metallic,
+                roughness: *roughness,
+                metallic_roughness: match metallic_roughness {
+                    Some(name) => Some(texture(&TextureRef::Named(name.clone()))?),
+                    None => None,
+                },
+                specular: *specular,
+                specular_tint: *specular_tint,
+                sheen: *sheen,
+                sheen_tint: *sheen_tint,
+                clearcoat: *clearcoat,
+                clearcoat_roughness: *clearcoat_roughness,
+                transmission: *transmission,
+                ior: *ior,
+                emission: match emission {
+                    Some(emission) => texture(emission)?,
+                    None => Texture::Constant(Color::new(0.0, 0.0, 0.0, 1.0)),
+                },
+                emission_strength: *emission_strength,
+            }),
+            MaterialFile::Coated {
+                base,
+                index,
+                roughness,
+                tint,
+                film,
+            } => Material::Coated(Coated {
+                index: *index,
+                roughness: *roughness,
+                tint: color(*tint),
+                film: film.as_ref().map(ThinFilmFile::to_film),
+                base: Box::new(base.to_material(texture)?),
+            }),
+        };
+
+        Ok(material)
+    }
+}
+
+fn load_grid(
+    path: &Path,
+    resolution: Option<(usize, usize, usize)>,
+    index: usize,
+    scene_path: &Path,
+) -> Result<Arc<VoxelGrid>, SceneError> {
+    let is_vol = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("vol"));
+
+    let grid = match resolution {
+        _ if is_vol => VoxelGrid::load_vol(path),
+        Some((nx, ny, nz)) => VoxelGrid::load_raw(path, nx, ny, nz),
+        None => {
+            return Err(SceneError::InvalidObject {
+                path: scene_path.to_path_buf(),
+                index,
+                message: "raw volume files need a resolution".to_string(),
+            })
+        }
+    };
+
+    grid.map(Arc::new).map_err(|error| SceneError::Io {
+        path: path.to_path_buf(),
+        error,
+    })
+}
+
+// Builds an object file entry as a single shared object, grouping entries that expand into several objects.
+fn build_object(
+    object: &ObjectFile,
+    index: usize,
+    path: &Path,
+    directory: &Path,
+    find_material: &dyn Fn(&str) -> Result<Material, SceneError>,
+) -> Result<Arc<Object>, SceneError> {
+    let mut objects = build_objects(object, index, path, directory, find_material)?;
+    let object = if objects.len() == 1 {
+        objects.pop().unwrap()
+    } else {
+        Object::Group(Arc::new(Group::new(objects)))
+    };
+
+    Ok(Arc::new(object))
+}
+
+fn build_objects(
+    object: &ObjectFile,
+    index: usize,
+    path: &Path,
+    directory: &Path,
+    find_material: &dyn Fn(&str) -> Result<Material, SceneError>,
+) -> Result<Vec<Object>, SceneError> {
+    let objects =
+        match object {
+            ObjectFile::Sphere {
+                center,
+                radius,
+                material,
+            } => vec![Object::Sphere(Sphere {
+                center: vec3(*center),
+                radius: *radius,
+                material: find_material(material)?,
+            })],
+            ObjectFile::MovingSphere {
+                center0,
+                center1,
+                time0,
+                time1,
+                radius,
+                material,
+            } => vec![Object::MovingSphere(MovingSphere {
+                center0: vec3(*center0),
+                center1: vec3(*center1),
+                time0: *time0,
+                time1: *time1,
+                radius: *radius,
+                material: find_material(material)?,
+            })],
+            ObjectFile::Mesh {
+                path: mesh_path,
+                material,
+            } => {
+                let material = match material {
+                    Some(name) => Some(find_material(name)?),
+                    None => None,
+                };
+
+                let mut objects = vec![];
+                for mut mesh in load_obj(&directory.join(mesh_path))? {
+                    if let Some(material) = &material {
+                        mesh.material = material.clone();
+                    }
+                    objects.extend(mesh.into_objects());
+                }
+                objects
+            }
+            ObjectFile::Quad { q, u, v, material } => vec![Object::Quad(Quad::new(
+                vec3(*q),
+                vec3(*u),
+                vec3(*v),
+                find_material(material)?,
+            ))],
+            ObjectFile::Rect { min, max, material } => {
+                let rect = Quad::rect(vec3(*min), vec3(*max), find_material(material)?)
+                    .ok_or_else(|| SceneError::InvalidObject {
+                        path: path.to_path_buf(),
+                        index,
+                        message: "a rectangle needs exactly one coordinate shared by its corners"
+                            .to_string(),
+                    })?;
+                vec![Object::Quad(rect)]
+            }
+            ObjectFile::Box { min, max, material } => {
+                cuboid(vec3(*min), vec3(*max), find_material(material)?)
+            }
+            ObjectFile::Instance { object, transform } => {
+                let matrix = transform
+                    .iter()
+                    .fold(Matrix4::identity(), |matrix, t| t.to_matrix() * matrix);
+                let transform =
+                    Transform::new(matrix).ok_or_else(|| SceneError::InvalidObject {
+                        path: path.to_path_buf(),
+                        index,
+                        message: "the transform can't be inverted".to_string(),
+                    })?;
+
+                let object = build_object(object, index, path, directory, find_material)?;
+                vec![Object::Instance(Instance::new(object, transform))]
+            }
+            ObjectFile::Medium {
+                boundary,
+                density,
+                material,
+            } => {
+                let boundary = build_object(boundary, index, path, directory, find_material)?;
+                vec![Object::ConstantMedium(ConstantMedium::new(
+                    boundary,
+                    *density,
+                    find_material(material)?,
+                ))]
+            }
+            ObjectFile::Volume {
+                path: grid_path,
+                resolution,
+                min,
+                max,
+                density,
+                material,
+                emission,
+            } => {
+                let grid = load_grid(&directory.join(grid_path), *resolution, index, path)?;
+                let bounds = AABB::from_min_max(vec3(*min), vec3(*max));
+
+                let mut material = find_material(material)?;
+                if let Some(emission) = emission {
+                    let emission_grid = match &emission.path {
+                        Some(emission_path) => {
+                            load_grid(&directory.join(emission_path), *resolution, index, path)?
+                        }
+                        None => grid.clone(),
+                    };
+                    let emission = Texture::Grid {
+                        grid: emission_grid,
+                        bounds,
+                        color: color(emission.color),
+                    };
+
+                    material = match material {
+                        Material::Isotropic(i) => Material::HenyeyGreenstein(HenyeyGreenstein {
+                            albedo: i.albedo,
+                            g: 0.0,
+                            emission,
+                        }),
+                        Material::HenyeyGreenstein(h) => {
+                            Material::HenyeyGreenstein(HenyeyGreenstein { emission, ..h })
+                        }
+                        _ => return Err(SceneError::InvalidObject {
+                            path: path.to_path_buf(),
+                            index,
+                            message:
+                                "emitting volumes need an Isotropic or HenyeyGreenstein material"
+                                    .to_string(),
+                        }),
+                    };
+                }
+
+                vec![Object::GridMedium(GridMedium::new(
+                    grid, bounds, *density, material,
+                ))]
+            }
+        };
+
+    Ok(objects)
+}
+
+pub fn load_scene(path: &Path) -> Result<LoadedScene, SceneError> {
+    let source = fs::read_to_string(path).map_err(|error| SceneError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+
+    let options = Options::default().with_default_extension(Extensions::IMPLICIT_SOME);
+    let file: SceneFile = options
+        .from_str(&source)
+        .map_err(|error| SceneError::Parse {
+            path: path.to_path_buf(),
+            line: error.position.line,
+            column: error.position.col,
+            message: error.code.to_string(),
+        })?;
+
+    let invalid = [
+        ("width", file.width == 0, "must be positive"),
+        ("height", file.height == 0, "must be positive"),
+        (
+            "samples",
+            file.samples.is_empty() || file.samples.contains(&0),
+            "must list positive counts",
+        ),
+    ];
+    if let Some((field, _, message)) = invalid.iter().find(|(_, invalid, _)| *invalid) {
+        let (line, column) = find_field(&source, field);
+        return Err(SceneError::Parse {
+            path: path.to_path_buf(),
+            line,
+            column,
+            message: format!("`{}` {}", field, message),
+        });
+    }
+
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+    // Noise textures are seeded in name order so a scene renders the same every time.
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut texture_names: Vec<&String> = file.textures.keys().collect();
+    texture_names.sort();
+
+    let mut textures: HashMap<&str, Texture> = HashMap::new();
+    for name in texture_names {
+        let texture = file.textures[name].to_texture(directory, &mut rng)?;
+        textures.insert(name.as_str(), texture);
+    }
+
+    let find_texture = |texture: &TextureRef| match texture {
+        TextureRef::Color(c) => Ok(Texture::Constant(color(*c))),
+        TextureRef::Named(name) => textures.get(name.as_str()).cloned().ok_or_else(|| {
+            let (line, column) = find_position(&source, name);
+            SceneError::UnknownTexture {
+                path: path.to_path_buf(),
+                line,
+                column,
+                name: name.to_string(),
+            }
+        }),
+    };
+
+    let mut materials: HashMap<&str, Material> = HashMap::new();
+    for (name, material) in &file.materials {
+        if let MaterialFile::Coated { base, .. } = material {
+            if let MaterialFile::Coated { .. } = **base {
+                let (line, column) = find_position(&source, name);
+                return Err(SceneError::InvalidMaterial {
+                    path: path.to_path_buf(),
+                    line,
+                    column,
+                    name: name.to_string(),
+                    message: "the base of a coating can't be coated".to_string(),
+                });
+            }
+        }
+        materials.insert(name.as_str(), material.to_material(find_texture)?);
+    }
+
+    let find_material = |name: &str| {
+        materials.get(name).cloned().ok_or_else(|| {
+            let (line, column) = find_position(&source, name);
+            SceneError::UnknownMaterial {
+                path: path.to_path_buf(),
+                line,
+                column,
+                name: name.to_string(),
+            }
+        })
+    };
+
+    let mut objects = vec![];
+    for (index, object) in file.objects.iter().enumerate() {
+        objects.extend(build_objects(
+            object,
+            index,
+            path,
+            directory,
+            &find_material,
+        )?);
+    }
+
+    let camera_file = &file.camera;
+    let from = vec3(camera_file.from);
+    let at = vec3(camera_file.at);
+    let camera = Camera::perspective_with_time(
+        from,
+        at,
+        vec3(camera_file.up),
+        camera_file.vfov,
+        (file.width as f64) / (file.height as f64),
+        camera_file.aperture,
+        camera_file
+            .focus_dist
+            .unwrap_or_else(|| (from - at).magnitude()),
+        camera_file.time0,
+        camera_file.time1,
+    );
+
+    let mut scene = Scene::create_with_bvh(&objects, file.max_recursion);
+    scene.max_bounces = BounceLimits {
+        diffuse: file.max_bounces.diffuse,
+        specular: file.max_bounces.specular,
+        transmission: file.max_bounces.transmission,
+    };
+    scene.spectral = file.spectral;
+    scene.environment = file.environment.to_environment(directory)?;
+
+    Ok(LoadedScene {
+        scene,
+        camera,
+        width: file.width,
+        height: file.height,
+        samples: file.samples,
+        view: file.view.to_view(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(name: &str, source: &str) -> Result<LoadedScene, SceneError> {
+        let path = std::env::temp_dir().join(format!("scene-{}-{}.ron", name, std::process::id()));
+        fs::write(&path, source).unwrap();
+        let result = load_scene(&path);
+        fs::remove_file(&path).unwrap();
+        result
+    }
+
+    fn scene(settings: &str, material: &str) -> String {
+        format!(
+            "Scene(\n{}\n    camera: (from: (0, 0, 1), at: (0, 0, 0), vfov: 40),\n    materials: {{\n        \"grey\": Lambertian(albedo: (0.5, 0.5, 0.5)),\n    }},\n    objects: [\n        Sphere(center: (0, 0, 0), radius: 1, material: \"{}\"),\n    ],\n)\n",
+            settings, material
+        )
+    }
+
+    fn parse_error(result: Result<LoadedScene, SceneError>) -> (usize, usize, String) {
+        match result {
+            Err(SceneError::Parse {
+                line,
+                column,
+                message,
+                ..
+            }) => (line, column, message),
+            Err(error) => panic!("unexpected error: {}", error),
+            Ok(_) => panic!("scene loaded"),
+        }
+    }
+
+    #[test]
+    fn valid_scenes_load() {
+        let loaded = load("valid", &scene("    width: 4,\n    height: 2,", "grey")).unwrap();
+        assert_eq!((loaded.width, loaded.height), (4, 2));
+    }
+
+    #[test]
+    fn unknown_materials_are_reported_where_they_are_used() {
+        match load("unknown", &scene("", "gray")) {
+            Err(SceneError::UnknownMaterial {
+                line, column, name, ..
+            }) => {
+                assert_eq!(name, "gray");
+                assert_eq!((line, column), (8, 56));
+            }
+            Err(error) => panic!("unexpected error: {}", error),
+            Ok(_) => panic!("scene loaded"),
+        }
+    }
+
+    #[test]
+    fn zero_sizes_are_reported_at_their_field() {
+        let (line, column, message) = parse_error(load("width", &scene("    width: 0,", "grey")));
+        assert_eq!((line, column), (2, 5));
+        assert_eq!(message, "`width` must be positive");
+
+        let (line, column, message) = parse_error(load(
+            "height",
+            &scene("    width: 4,\n    height: 0,", "grey"),
+        ));
+        assert_eq!((line, column), (3, 5));
+        assert_eq!(message, "`height` must be positive");
+
+        let (line, _, message) =
+            parse_error(load("samples", &scene("    samples: [4, 0],", "grey")));
+        assert_eq!(line, 2);
+        assert_eq!(message, "`samples` must list positive counts");
+    }
+
+    #[test]
+    fn fields_are_found_at_identifier_boundaries() {
+        let source = "Scene(\n    max_width: 3,\n    width: 0,\n)";
+        assert_eq!(find_field(source, "width"), (3, 5));
+        assert_eq!(find_field("widths: 1\nwidth: 0", "width"), (2, 1));
+    }
+
+    fn volume_scene(resolution: &str) -> String {
+        format!(
+            "Scene(\n    camera: (from: (0, 0, 5), at: (0, 0, 0), vfov: 40),\n    materials: {{\n        \"fog\": Isotropic(albedo: (0.5, 0.5, 0.5)),\n    }},\n    objects: [\n        Volume(path: \"grid-{}.raw\", {}min: (0, 0, 0), max: (1, 1, 1), density: 1, material: \"fog\"),\n    ],\n)\n",
+            std::process::id(),
+            resolution
+        )
+    }
+
+    #[test]
+    fn raw_volumes_need_a_resolution() {
+        let grid = std::env::temp_dir().join(format!("grid-{}.raw", std::process::id()));
+        fs::write(&grid, vec![0; 4 * 8]).unwrap();
+
+        let missing = load("raw", &volume_scene(""));
+        let given = load("raw-resolution", &volume_scene("resolution: (2, 2, 2), "));
+        let wrong = load("raw-wrong", &volume_scene("resolution: (2, 2, 3), "));
+        fs::remove_file(&grid).unwrap();
+
+        match missing {
+            Err(SceneError::InvalidObject { index, message, .. }) => {
+                assert_eq!(index, 0);
+                assert_eq!(message, "raw volume files need a resolution");
+            }
+            Err(error) => panic!("unexpected error: {}", error),
+            Ok(_) => panic!("scene loaded"),
+        }
+        assert!(given.is_ok());
+        assert!(matches!(wrong, Err(SceneError::Io { .. })));
+    }
+
+    #[test]
+    fn emissive_principled_surfaces_are_lights() {
+        let source = "Scene(\n    camera: (from: (0, 0, 5), at: (0, 0, 0), vfov: 40),\n    materials: {\n        \"lamp\": Principled(base_color: (0.5, 0.5, 0.5), emission: (4, 4, 4)),\n        \"dark\": Principled(base_color: (0.5, 0.5, 0.5), emission: (0, 0, 0)),\n        \"off\": Principled(base_color: (0.5, 0.5, 0.5), emission: (4, 4, 4), emission_strength: 0),\n    },\n    objects: [\n        Sphere(center: (0, 0, 0), radius: 1, material: \"lamp\"),\n        Sphere(center: (3, 0, 0), radius: 1, material: \"dark\"),\n        Sphere(center: (-3, 0, 0), radius: 1, material: \"off\"),\n    ],\n)\n";
+        let lights = load("principled", source).unwrap().scene.lights;
+        assert_eq!(lights.len(), 1);
+        assert!(lights[0]
+            .material()
+            .is_some_and(|m| matches!(m, Material::Principled(_))));
+    }
+}
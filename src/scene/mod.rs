@@ -1,58 +1,124 @@
+mod bvh;
 mod camera;
+mod file;
+mod obj;
 
-use crate::math::{Ray, AABB};
+use crate::math::{Ray, Vec3, AABB};
 use crate::objects::{Intersectable, Intersection, Object};
-use bvh::bvh::BVH;
-use bvh::nalgebra::{Point3, Vector3};
-use bvh::ray::Ray as BVH_Ray;
+use crate::renderer::{Environment, Material};
+use rand::prelude::*;
 
+pub use bvh::Bvh;
 pub use camera::Camera;
+pub use file::{load_scene, LoadedScene, SceneError};
+pub use obj::{load_mtl, load_obj, MtlMaterial, ObjError};
+
+// The most bounces of each kind a path may make. Diffuse bounces include scattering in media, specular
+// ones reflect into a single direction and transmission passes through a surface.
+#[derive(Copy, Clone, Debug)]
+pub struct BounceLimits {
+    pub diffuse: u32,
+    pub specular: u32,
+    pub transmission: u32,
+}
+
+impl Default for BounceLimits {
+    fn default() -> Self {
+        BounceLimits {
+            diffuse: 8,
+            specular: 16,
+            transmission: 32,
+        }
+    }
+}
 
 pub struct Scene {
+    // Limit on the total number of bounces.
     pub max_recursion: u32,
+    pub max_bounces: BounceLimits,
+    // Traces sampled wavelengths instead of RGB, which dispersive materials need.
+    pub spectral: bool,
     pub objects: Vec<Object>,
+    pub environment: Environment,
+    pub lights: Vec<Object>,
 
-    bvh: BVH,
+    bvh: Bvh,
+    bounded: usize,
 }
 
 impl Scene {
+    // `objects` ends up in BVH order, followed by any objects without a bounding box which are tested
+    // separately for every ray.
     pub fn create_with_bvh(objects: &[Object], max_recursion: u32) -> Scene {
-        let mut objects_vec = objects.to_vec();
+        let (mut bounded, unbounded): (Vec<Object>, Vec<Object>) = objects
+            .iter()
+            .cloned()
+            .partition(|o| o.bounding_box(0.0, 1.0).is_some());
+
+        let bvh = Bvh::build(&mut bounded, 0.0, 1.0);
+        let bounded_count = bounded.len();
+        bounded.extend(unbounded);
 
         Scene {
             max_recursion,
-            objects: objects.to_vec(),
-            bvh: BVH::build(&mut objects_vec),
+            max_bounces: BounceLimits::default(),
+            spectral: false,
+            lights: bounded
+                .iter()
+                .filter(|o| o.material().is_some_and(Material::is_light))
+                .cloned()
+                .collect(),
+            objects: bounded,
+            environment: Environment::Gradient,
+            bvh,
+            bounded: bounded_count,
         }
     }
 }
 
 impl Intersectable for Scene {
-    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Intersection> {
-        let bvh_ray = BVH_Ray::new(
-            Point3::new(
-                ray.origin.x as f32,
-                ray.origin.y as f32,
-                ray.origin.z as f32,
-            ),
-            Vector3::new(
-                ray.direction.x as f32,
-                ray.direction.y as f32,
-                ray.direction.z as f32,
-            ),
-        );
-
-        self.bvh
-            // TODO: Mutable copy of each scene (object list) for each thread. This is very inefficient and might
-            // also hurt the BVH efficiency by not allowing it to cache the indices properly.
-            .traverse(&bvh_ray, &mut self.objects.to_vec())
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Intersection<'_>> {
+        let mut closest = self.bvh.intersect(&self.objects, ray, t_min, t_max);
+
+        for object in &self.objects[self.bounded..] {
+            let t_max = closest.as_ref().map_or(t_max, |hit| hit.distance);
+            if let Some(hit) = object.intersect(ray, t_min, t_max) {
+                closest = Some(hit);
+            }
+        }
+
+        closest
+    }
+
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rng: &mut dyn RngCore,
+    ) -> Option<Intersection<'_>> {
+        let mut closest = self.bvh.hit(&self.objects, ray, t_min, t_max, rng);
+
+        for object in &self.objects[self.bounded..] {
+            let t_max = closest.as_ref().map_or(t_max, |hit| hit.distance);
+            if let Some(hit) = object.hit(ray, t_min, t_max, rng) {
+                closest = Some(hit);
+            }
+        }
+
+        closest
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64, rng: &mut dyn RngCore) -> f64 {
+        let bounded = self
+            .bvh
+            .transmittance(&self.objects, ray, t_min, t_max, rng);
+
+        self.objects[self.bounded..]
             .iter()
-            .filter_map(|s| s.intersect(ray, t_min, t_max))
-            .min_by(|i1, i2| i1.distance.partial_cmp(&i2.distance).unwrap())
-        // self.objects
-        //     .iter()
-        //     .filter_map(|s| s.intersect(ray, t_min, t_max))
-        //     .min_by(|i1, i2| i1.distance.partial_cmp(&i2.distance).unwrap())
+            .fold(bounded, |product, object| {
+                product * object.transmittance(ray, t_min, t_max, rng)
+            })
     }
 
     fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB> {
@@ -77,4 +143,28 @@ impl Intersectable for Scene {
 
         Some(result)
     }
+
+    // The lights, and the environment when it can be sampled, form a mixture density: one of them is picked
+    // uniformly and sampled, so the density of a direction is the average of their individual densities.
+    fn random_direction(&self, origin: &Vec3, time: f64, rng: &mut dyn RngCore) -> Option<Vec3> {
+        let count = self.lights.len() + self.environment.is_sampled() as usize;
+        if count == 0 {
+            return None;
+        }
+
+        match self.lights.get(rng.gen_range(0, count)) {
+            Some(light) => light.random_direction(origin, time, rng),
+            None => self.environment.random_direction(rng),
+        }
+    }
+
+    fn pdf_value(&self, ray: &Ray) -> f64 {
+        let count = self.lights.len() + self.environment.is_sampled() as usize;
+        if count == 0 {
+            return 0.0;
+        }
+
+        let sum: f64 = self.lights.iter().map(|light| light.pdf_value(ray)).sum();
+        (sum + self.environment.pdf_value(&ray.direction)) / count as f64
+    }
 }
@@ -0,0 +1,493 @@
+use crate::color::Color;
+use crate::math::Vec3;
+use crate::objects::TriangleMesh;
+use crate::renderer::{Dialectric, DiffuseLight, Lambertian, Material, Metal, Texture};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            ObjError::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ObjError::Io { error, .. } => Some(error),
+            ObjError::Parse { .. } => None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct MtlMaterial {
+    pub name: String,
+    pub diffuse: Color,
+    pub specular: Color,
+    pub emission: Color,
+    pub shininess: f64,
+    pub index: f64,
+    pub dissolve: f64,
+    pub illum: u32,
+}
+
+impl MtlMaterial {
+    fn new(name: &str) -> MtlMaterial {
+        MtlMaterial {
+            name: name.to_string(),
+            diffuse: Color::new(0.8, 0.8, 0.8, 1.0),
+            specular: Color::new(0.0, 0.0, 0.0, 1.0),
+            emission: Color::new(0.0, 0.0, 0.0, 1.0),
+            shininess: 0.0,
+            index: 1.0,
+            dissolve: 1.0,
+            illum: 2,
+        }
+    }
+
+    // Emissive materials become lights, transparent materials (`d` < 1 or one of the refraction illumination
+    // models) become glass, materials whose specular reflectance dominates the diffuse one become metal, and
+    // everything else is diffuse.
+    pub fn to_material(&self) -> Material {
+        if self.emission.luminance() > 0.0 {
+            return Material::DiffuseLight(DiffuseLight {
+                emit: Texture::Constant(self.emission),
+            });
+        }
+
+        let transparent =
+            self.dissolve < 1.0 || self.illum == 4 || self.illum == 6 || self.illum == 7;
+        if transparent {
+            let index = if self.index > 1.0 { self.index } else { 1.5 };
+            return Material::Dialectric(Dialectric::new(index));
+        }
+
+        if self.specular.luminance() > self.diffuse.luminance() {
+            let roughness = (2.0 / (self.shininess.max(0.0) + 2.0)).sqrt();
+            return Material::Metal(Metal::new(Texture::Constant(self.specular), roughness));
+        }
+
+        Material::Lambertian(Lambertian {
+            albedo: Texture::Constant(self.diffuse),
+        })
+    }
+}
+
+struct Parser<'a> {
+    path: &'a Path,
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: String) -> ObjError {
+        ObjError::Parse {
+            path: self.path.to_path_buf(),
+            line: self.line,
+            message,
+        }
+    }
+
+    fn floats(&self, args: &[&str], min: usize, max: usize) -> Result<Vec<f64>, ObjError> {
+        if args.len() < min || args.len() > max {
+            return Err(self.error(format!(
+                "expected {} to {} numbers, found {}",
+                min,
+                max,
+                args.len()
+            )));
+        }
+
+        args.iter()
+            .map(|a| {
+                a.parse::<f64>()
+                    .map_err(|_| self.error(format!("invalid number '{}'", a)))
+            })
+            .collect()
+    }
+
+    fn color(&self, args: &[&str]) -> Result<Color, ObjError> {
+        let values = self.floats(args, 1, 3)?;
+        if values.len() == 1 {
+            let v = values[0] as f32;
+            Ok(Color::new(v, v, v, 1.0))
+        } else if values.len() == 3 {
+            Ok(Color::new(
+                values[0] as f32,
+                values[1] as f32,
+                values[2] as f32,
+                1.0,
+            ))
+        } else {
+            Err(self.error("expected 1 or 3 color components".to_string()))
+        }
+    }
+
+    fn index(&self, value: &str, count: usize) -> Result<usize, ObjError> {
+        let index = value
+            .parse::<i64>()
+            .map_err(|_| self.error(format!("invalid index '{}'", value)))?;
+
+        let resolved = if index > 0 {
+            index - 1
+        } else {
+            count as i64 + index
+        };
+
+        if index == 0 || resolved < 0 || resolved >= count as i64 {
+            return Err(self.error(format!(
+                "index {} out of range (have {} elements)",
+                index, count
+            )));
+        }
+
+        Ok(resolved as usize)
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, ObjError> {
+    fs::read_to_string(path).map_err(|error| ObjError::Io {
+        path: path.to_path_buf(),
+        error,
+    })
+}
+
+fn statements(source: &str) -> impl Iterator<Item = (usize, &str, Vec<&str>)> {
+    source.lines().enumerate().filter_map(|(i, line)| {
+        let line = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+
+        let mut tokens = line.split_whitespace();
+        tokens
+            .next()
+            .map(|keyword| (i + 1, keyword, tokens.collect()))
+    })
+}
+
+pub fn load_mtl(path: &Path) -> Result<Vec<MtlMaterial>, ObjError> {
+    let source = read_file(path)?;
+    let mut parser = Parser { path, line: 0 };
+    let mut materials: Vec<MtlMaterial> = vec![];
+
+    for (line, keyword, args) in statements(&source) {
+        parser.line = line;
+
+        if keyword == "newmtl" {
+            if args.is_empty() {
+                return Err(parser.error("newmtl without a name".to_string()));
+            }
+            materials.push(MtlMaterial::new(&args.join(" ")));
+            continue;
+        }
+
+        let material = match materials.last_mut() {
+            Some(material) => material,
+            None if keyword.starts_with("map_") => continue,
+            None => return Err(parser.error(format!("'{}' before any newmtl", keyword))),
+        };
+
+        match keyword {
+            "Kd" => material.diffuse = parser.color(&args)?,
+            "Ks" => material.specular = parser.color(&args)?,
+            "Ke" => material.emission = parser.color(&args)?,
+            "Ns" => material.shininess = parser.floats(&args, 1, 1)?[0],
+            "Ni" => material.index = parser.floats(&args, 1, 1)?[0],
+            "d" => material.dissolve = parser.floats(&args, 1, 1)?[0],
+            "Tr" => material.dissolve = 1.0 - parser.floats(&args, 1, 1)?[0],
+            "illum" => {
+                material.illum = parser.floats(&args, 1, 1)?[0] as u32;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(materials)
+}
+
+struct MeshBuilder {
+    material: Material,
+    positions: Vec<Vec3>,
+    normals: Vec<Option<Vec3>>,
+    uvs: Vec<Option<(f64, f64)>>,
+    indices: Vec<[usize; 3]>,
+    vertex_map: HashMap<(usize, Option<usize>, Option<usize>), usize>,
+}
+
+impl MeshBuilder {
+    fn new(material: Material) -> MeshBuilder {
+        MeshBuilder {
+            material,
+            positions: vec![],
+            normals: vec![],
+            uvs: vec![],
+            indices: vec![],
+            vertex_map: HashMap::new(),
+        }
+    }
+
+    fn vertex(&mut self, key: (usize, Option<usize>, Option<usize>), data: &ObjData) -> usize {
+        if let Some(index) = self.vertex_map.get(&key) {
+            return *index;
+        }
+
+        let (v, vt, vn) = key;
+        let index = self.positions.len();
+        self.positions.push(data.positions[v]);
+        self.uvs.push(vt.map(|vt| data.uvs[vt]));
+        self.normals.push(vn.map(|vn| data.normals[vn]));
+        self.vertex_map.insert(key, index);
+
+        index
+    }
+
+    // Normals and texture coordinates are only kept when every vertex of the mesh has them.
+    fn build(self) -> TriangleMesh {
+        let normals = self.normals.into_iter().collect::<Option<Vec<Vec3>>>();
+        let uvs = self.uvs.into_iter().collect::<Option<Vec<(f64, f64)>>>();
+
+        TriangleMesh {
+            positions: self.positions,
+            normals: normals.unwrap_or_default(),
+            uvs: uvs.unwrap_or_default(),
+            indices: self.indices,
+            material: self.material,
+        }
+    }
+}
+
+struct ObjData {
+    positions: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+}
+
+pub fn load_obj(path: &Path) -> Result<Vec<TriangleMesh>, ObjError> {
+    let source = read_file(path)?;
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut parser = Parser { path, line: 0 };
+
+    let default_material = Material::Lambertian(Lambertian {
+        albedo: Texture::Constant(Color::new(0.8, 0.8, 0.8, 1.0)),
+    });
+
+    let mut data = ObjData {
+        positions: vec![],
+        normals: vec![],
+        uvs: vec![],
+    };
+    let mut materials: HashMap<String, Material> = HashMap::new();
+    let mut meshes: Vec<MeshBuilder> = vec![MeshBuilder::new(default_material)];
+    let mut current = 0;
+    let mut mesh_by_material: HashMap<String, usize> = HashMap::new();
+
+    for (line, keyword, args) in statements(&source) {
+        parser.line = line;
+
+        match keyword {
+            "v" => {
+                let v = parser.floats(&args, 3, 4)?;
+                data.positions.push(Vec3::new(v[0], v[1], v[2]));
+            }
+            "vn" => {
+                let n = parser.floats(&args, 3, 3)?;
+                data.normals.push(Vec3::new(n[0], n[1], n[2]).normalize());
+            }
+            "vt" => {
+                let t = parser.floats(&args, 1, 3)?;
+                data.uvs.push((t[0], *t.get(1).unwrap_or(&0.0)));
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(parser.error(format!(
+                        "face needs at least 3 vertices, found {}",
+                        args.len()
+                    )));
+                }
+
+                let mut face = Vec::with_capacity(args.len());
+                for arg in &args {
+                    let mut parts = arg.split('/');
+                    let v = parser.index(parts.next().unwrap_or(""), data.positions.len())?;
+                    let vt = match parts.next() {
+                        Some(vt) if !vt.is_empty() => Some(parser.index(vt, data.uvs.len())?),
+                        _ => None,
+                    };
+                    let vn = match parts.next() {
+                        Some(vn) if !vn.is_empty() => Some(parser.index(vn, data.normals.len())?),
+                        _ => None,
+                    };
+
+                    face.push(meshes[current].vertex((v, vt, vn), &data));
+                }
+
+                for i in 1..(face.len() - 1) {
+                    meshes[current]
+                        .indices
+                        .push([face[0], face[i], face[i + 1]]);
+                }
+            }
+            "mtllib" => {
+                if args.is_empty() {
+                    return Err(parser.error("mtllib without a file name".to_string()));
+                }
+
+                for file in &args {
+                    for mtl in load_mtl(&directory.join(file))? {
+                        materials.insert(mtl.name.clone(), mtl.to_material());
+                    }
+                }
+            }
+            "usemtl" => {
+                let name = args.join(" ");
+                let material = match materials.get(&name) {
+                    Some(material) => material.clone(),
+                    None => return Err(parser.error(format!("unknown material '{}'", name))),
+                };
+
+                current = *mesh_by_material.entry(name).or_insert_with(|| {
+                    meshes.push(MeshBuilder::new(material));
+                    meshes.len() - 1
+                });
+            }
+            _ => {}
+        }
+    }
+
+    Ok(meshes
+        .into_iter()
+        .filter(|mesh| !mesh.indices.is_empty())
+        .map(|mesh| mesh.build())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Writes the files into a directory of their own and loads `mesh.obj` from it.
+    fn load(name: &str, files: &[(&str, &str)]) -> Result<Vec<TriangleMesh>, ObjError> {
+        let directory = std::env::temp_dir().join(format!("obj-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        for (file, contents) in files {
+            fs::write(directory.join(file), contents).unwrap();
+        }
+
+        let result = load_obj(&directory.join("mesh.obj"));
+        fs::remove_dir_all(&directory).unwrap();
+        result
+    }
+
+    const SQUARE: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n";
+
+    #[test]
+    fn negative_indices_count_back_from_the_last_vertex() {
+        let meshes = load(
+            "negative",
+            &[("mesh.obj", &format!("{}f -4 -3 -2\n", SQUARE))],
+        )
+        .unwrap();
+
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(meshes[0].indices, vec![[0, 1, 2]]);
+        assert_eq!(meshes[0].positions[2].x, 1.0);
+        assert_eq!(meshes[0].positions[2].y, 1.0);
+    }
+
+    #[test]
+    fn polygons_are_split_into_a_fan() {
+        let source = format!("{}v 0.5 1.5 0\nf 1 2 3 4\nf 1 2 3 5 4\n", SQUARE);
+        let meshes = load("polygons", &[("mesh.obj", &source)]).unwrap();
+
+        let indices = &meshes[0].indices;
+        assert_eq!(indices.len(), 5);
+        assert_eq!(indices[..2], [[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(indices[2..], [[0, 1, 2], [0, 2, 4], [0, 4, 3]]);
+    }
+
+    #[test]
+    fn attributes_are_only_kept_when_every_vertex_has_them() {
+        let normals = format!("{}vt 0 0\nvn 0 0 1\nf 1//1 2//1 3//1\n", SQUARE);
+        let meshes = load("normals", &[("mesh.obj", &normals)]).unwrap();
+        assert_eq!(meshes[0].normals.len(), 3);
+        assert!(meshes[0].uvs.is_empty());
+
+        let mixed = format!("{}vt 0 0\nvn 0 0 1\nf 1/1/1 2/1/1 3/1/1\nf 1 3 4\n", SQUARE);
+        let meshes = load("mixed", &[("mesh.obj", &mixed)]).unwrap();
+        assert_eq!(meshes[0].positions.len(), 6);
+        assert!(meshes[0].normals.is_empty());
+        assert!(meshes[0].uvs.is_empty());
+    }
+
+    #[test]
+    fn invalid_faces_are_reported_with_their_line() {
+        let errors = [
+            format!("{}\nf 1 2\n", SQUARE),
+            format!("{}\nf 1 2 5\n", SQUARE),
+            format!("{}\nf 1/1 2 3\n", SQUARE),
+        ];
+        for source in &errors {
+            match load("invalid", &[("mesh.obj", source)]) {
+                Err(ObjError::Parse { line, .. }) => assert_eq!(line, 6),
+                _ => panic!("expected a parse error"),
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_materials_are_errors() {
+        let source = format!("{}usemtl missing\nf 1 2 3\n", SQUARE);
+        match load("unknown", &[("mesh.obj", &source)]) {
+            Err(ObjError::Parse { line, message, .. }) => {
+                assert_eq!(line, 5);
+                assert!(message.contains("missing"));
+            }
+            _ => panic!("expected an unknown material error"),
+        }
+    }
+
+    #[test]
+    fn every_mtllib_file_is_loaded() {
+        let source = format!(
+            "mtllib a.mtl b.mtl\n{}usemtl red\nf 1 2 3\nusemtl mirror\nf 1 3 4\n",
+            SQUARE
+        );
+        let meshes = load(
+            "mtllib",
+            &[
+                ("mesh.obj", &source),
+                ("a.mtl", "newmtl red\nKd 1 0 0\n"),
+                ("b.mtl", "newmtl mirror\nKd 0 0 0\nKs 0.9 0.9 0.9\n"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(meshes.len(), 2);
+        assert!(matches!(meshes[0].material, Material::Lambertian(_)));
+        assert!(matches!(meshes[1].material, Material::Metal(_)));
+    }
+}